@@ -0,0 +1,140 @@
+// A single pattern cut out of a session, for building a pattern bank:
+//
+//   "NCSF"                 magic
+//   u8                     format version (1)
+//   u8 track, u8 pattern   slot the pattern was extracted from
+//   u8 steps               bytes per plane row
+//   8 x u32 LE             source offset of each plane (PLANE_NAMES order)
+//   u32 LE, u32 LE         source track stride, pattern stride
+//   8 x steps bytes        the plane rows, in PLANE_NAMES order
+//
+// The offsets and strides only document where the bytes came from; `inject` places
+// the rows with the destination's own offsets, and needs its layout to have as many
+// steps per pattern as the fragment.
+
+use std::io;
+
+use ncs_tui::{checked_offset, Layout, Offsets};
+
+pub const FRAGMENT_MAGIC: [u8; 4] = *b"NCSF";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8 + 8 * 4 + 2 * 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub track: u8,
+    pub pattern: u8,
+    pub plane_offsets: [u32; 8],
+    pub track_stride: u32,
+    pub pattern_stride: u32,
+    pub planes: [Vec<u8>; 8],
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Offset of one plane row, or an error naming the slot when it is not in `len` bytes.
+fn row(off: &Offsets, layout: &Layout, base: usize, track: usize, pattern: usize, len: usize) -> io::Result<usize> {
+    if track >= layout.tracks || pattern >= layout.patterns {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "Track {} pattern {} out of range (0..{}, 0..{})", track, pattern, layout.tracks.saturating_sub(1), layout.patterns.saturating_sub(1))));
+    }
+    let start = checked_offset("pattern row", base, &[(track, off.track_stride), (pattern, off.pattern_stride)])?;
+    if start.checked_add(layout.steps).is_none_or(|end| end > len) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!(
+            "Pattern row at 0x{:X} runs past the end of the file (0x{:X} bytes)", start, len)));
+    }
+    Ok(start)
+}
+
+fn to_u32(v: usize) -> io::Result<u32> {
+    u32::try_from(v).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Offset 0x{:X} does not fit a fragment header", v)))
+}
+
+impl Fragment {
+    /// Copy the eight plane rows of one pattern slot out of a session file.
+    pub fn extract(data: &[u8], off: &Offsets, layout: &Layout, track: usize, pattern: usize) -> io::Result<Self> {
+        if u8::try_from(layout.steps).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} steps per pattern do not fit a fragment header", layout.steps)));
+        }
+        let mut planes: [Vec<u8>; 8] = Default::default();
+        let mut plane_offsets = [0u32; 8];
+        for (i, base) in off.planes().into_iter().enumerate() {
+            let start = row(off, layout, base, track, pattern, data.len())?;
+            planes[i] = data[start..start + layout.steps].to_vec();
+            plane_offsets[i] = to_u32(base)?;
+        }
+        Ok(Fragment {
+            track: track as u8,
+            pattern: pattern as u8,
+            plane_offsets,
+            track_stride: to_u32(off.track_stride)?,
+            pattern_stride: to_u32(off.pattern_stride)?,
+            planes,
+        })
+    }
+
+    /// Overwrite one pattern slot of `data` with this fragment's rows.
+    pub fn inject(&self, data: &mut [u8], off: &Offsets, layout: &Layout, track: usize, pattern: usize) -> io::Result<()> {
+        if self.steps() != layout.steps {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Fragment has {} steps per pattern, the session {}", self.steps(), layout.steps)));
+        }
+        let starts = off.planes().into_iter()
+            .map(|base| row(off, layout, base, track, pattern, data.len()))
+            .collect::<io::Result<Vec<usize>>>()?;
+        for (start, plane) in starts.into_iter().zip(&self.planes) {
+            data[start..start + plane.len()].copy_from_slice(plane);
+        }
+        Ok(())
+    }
+
+    // Steps per pattern, the length of every plane row.
+    pub fn steps(&self) -> usize {
+        self.planes[0].len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + 8 * self.steps());
+        out.extend_from_slice(&FRAGMENT_MAGIC);
+        // extract refuses layouts with more steps than a u8 holds
+        out.extend_from_slice(&[VERSION, self.track, self.pattern, self.steps() as u8]);
+        for o in self.plane_offsets {
+            out.extend_from_slice(&o.to_le_bytes());
+        }
+        out.extend_from_slice(&self.track_stride.to_le_bytes());
+        out.extend_from_slice(&self.pattern_stride.to_le_bytes());
+        for plane in &self.planes {
+            out.extend_from_slice(plane);
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        if !data.starts_with(&FRAGMENT_MAGIC) {
+            return Err(bad("Not a pattern fragment: missing \"NCSF\" magic".to_string()));
+        }
+        if data.len() < HEADER_LEN {
+            return Err(bad(format!("Fragment header cut short: {} bytes, need {}", data.len(), HEADER_LEN)));
+        }
+        if data[4] != VERSION {
+            return Err(bad(format!("Unsupported fragment version {} (expected {})", data[4], VERSION)));
+        }
+        let steps = data[7] as usize;
+        if data.len() != HEADER_LEN + 8 * steps {
+            return Err(bad(format!("Fragment is {} bytes, expected {}", data.len(), HEADER_LEN + 8 * steps)));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let plane_offsets = std::array::from_fn(|i| u32_at(8 + 4 * i));
+        let body = &data[HEADER_LEN..];
+        Ok(Fragment {
+            track: data[5],
+            pattern: data[6],
+            plane_offsets,
+            track_stride: u32_at(40),
+            pattern_stride: u32_at(44),
+            planes: std::array::from_fn(|i| body[i * steps..(i + 1) * steps].to_vec()),
+        })
+    }
+}
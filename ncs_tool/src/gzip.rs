@@ -0,0 +1,29 @@
+// Gzip-compressed session backups, decoded with flate2 so they can be parsed directly.
+// Only decompression is supported.
+
+use std::io::{self, Read};
+
+use flate2::read::GzDecoder;
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+// Cap on inflated output. A session is about 160 KiB (header length 0x2740C), so
+// anything far past that is not a session, and a small crafted stream must not be able
+// to expand without bound.
+pub const MAX_OUTPUT: usize = 1 << 20;
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+// Decompress one gzip member; flate2 checks the trailing CRC-32 and length.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if !is_gzip(data) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not gzip data"));
+    }
+    let mut out = Vec::new();
+    GzDecoder::new(data).take(MAX_OUTPUT as u64 + 1).read_to_end(&mut out)?;
+    if out.len() > MAX_OUTPUT {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("gzip data inflates past {} bytes", MAX_OUTPUT)));
+    }
+    Ok(out)
+}
@@ -0,0 +1,116 @@
+// Append-only edit journal. Every write command can log the bytes it changed
+// as one text line, which is enough to revert the edit later:
+//
+//   <command>\t<offset>:<old hex>:<new hex> <offset>:<old hex>:<new hex> ...
+//
+// Offsets are hex; each change covers one contiguous run of differing bytes.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub offset: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub command: String,
+    pub changes: Vec<Change>,
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> io::Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) { return Err(bad(format!("Odd-length hex run in journal: {}", s))); }
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| bad(format!("Bad hex in journal: {}", e))))
+        .collect()
+}
+
+impl Record {
+    /// Diff two equally sized buffers into runs of changed bytes.
+    pub fn between(command: &str, before: &[u8], after: &[u8]) -> io::Result<Self> {
+        if before.len() != after.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Journal cannot record edits that change the file length"));
+        }
+        let mut changes: Vec<Change> = Vec::new();
+        for (i, (&a, &b)) in before.iter().zip(after).enumerate() {
+            if a == b { continue; }
+            match changes.last_mut() {
+                Some(c) if c.offset + c.old.len() == i => { c.old.push(a); c.new.push(b); }
+                _ => changes.push(Change { offset: i, old: vec![a], new: vec![b] }),
+            }
+        }
+        Ok(Record { command: command.to_string(), changes })
+    }
+
+    fn to_line(&self) -> String {
+        let runs: Vec<String> = self.changes.iter()
+            .map(|c| format!("{:x}:{}:{}", c.offset, hex(&c.old), hex(&c.new)))
+            .collect();
+        format!("{}\t{}", self.command, runs.join(" "))
+    }
+
+    fn from_line(line: &str) -> io::Result<Self> {
+        let (command, runs) = line.split_once('\t').ok_or_else(|| bad(format!("Malformed journal line: {}", line)))?;
+        let mut changes = Vec::new();
+        for run in runs.split_whitespace() {
+            let mut parts = run.split(':');
+            let (Some(off), Some(old), Some(new), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+                return Err(bad(format!("Malformed journal change: {}", run)));
+            };
+            let offset = usize::from_str_radix(off, 16).map_err(|e| bad(format!("Bad journal offset {}: {}", off, e)))?;
+            let (old, new) = (unhex(old)?, unhex(new)?);
+            if old.len() != new.len() { return Err(bad(format!("Journal change lengths differ: {}", run))); }
+            changes.push(Change { offset, old, new });
+        }
+        Ok(Record { command: command.to_string(), changes })
+    }
+
+    /// Undo this record on `data`, refusing if the bytes are not what the edit wrote.
+    pub fn revert(&self, data: &mut [u8]) -> io::Result<()> {
+        for c in &self.changes {
+            let end = c.offset + c.new.len();
+            if end > data.len() || data[c.offset..end] != c.new[..] {
+                return Err(bad(format!("File does not match the journaled '{}' edit at {:#x}", self.command, c.offset)));
+            }
+        }
+        for c in &self.changes {
+            data[c.offset..c.offset + c.old.len()].copy_from_slice(&c.old);
+        }
+        Ok(())
+    }
+}
+
+pub fn append(path: &Path, record: &Record) -> io::Result<()> {
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(f, "{}", record.to_line())
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<Record>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(Record::from_line)
+        .collect()
+}
+
+/// Rewrite the journal with `records` (used after undoing the last one).
+pub fn write_all(path: &Path, records: &[Record]) -> io::Result<()> {
+    let mut text = String::new();
+    for r in records {
+        text.push_str(&r.to_line());
+        text.push('\n');
+    }
+    std::fs::write(path, text)
+}
@@ -0,0 +1,1923 @@
+// Parser and writer for Novation Circuit Tracks .ncs session files: the step
+// planes, timing, scenes, chains, scale, FX and the offset tables they live at.
+// The ncs-tui binary is a CLI over this; other tools can depend on it directly.
+#![forbid(unsafe_code)]
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{self, Read};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+pub fn invalid_data<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+// Why a session could not be parsed. Converts into an io::Error that keeps the
+// NcsError as its payload, so CLI code built on io::Result can still use `?`.
+#[derive(Debug, thiserror::Error)]
+pub enum NcsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("File is too small: {got} bytes, but {field} needs {needed} bytes")]
+    Truncated { field: String, needed: usize, got: usize },
+    #[error("{field} out of range: {value}")]
+    OutOfRange { field: String, value: i64 },
+    #[error("Not a Circuit Tracks session: expected \"USER\" at offset 0, found {found:?}")]
+    BadMagic { found: String },
+    #[error("Offset of {field} overflows: base 0x{base:X} plus the configured strides is past usize::MAX")]
+    OffsetOverflow { field: String, base: usize },
+}
+
+impl NcsError {
+    // The io::ErrorKind each variant stood for before NcsError existed.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            NcsError::Io(e) => e.kind(),
+            NcsError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            NcsError::OutOfRange { .. } => io::ErrorKind::InvalidInput,
+            NcsError::BadMagic { .. } => io::ErrorKind::InvalidData,
+            NcsError::OffsetOverflow { .. } => io::ErrorKind::InvalidInput,
+        }
+    }
+
+    fn truncated(field: impl Into<String>, needed: usize, got: usize) -> Self {
+        NcsError::Truncated { field: field.into(), needed, got }
+    }
+}
+
+// `base + index * stride` summed over `terms`, failing cleanly instead of wrapping or
+// panicking when a crafted or mismatched profile makes the arithmetic overflow.
+pub fn checked_offset(field: &str, base: usize, terms: &[(usize, usize)]) -> Result<usize, NcsError> {
+    terms.iter()
+        .try_fold(base, |acc, &(index, stride)| index.checked_mul(stride).and_then(|step| acc.checked_add(step)))
+        .ok_or_else(|| NcsError::OffsetOverflow { field: field.to_string(), base })
+}
+
+impl From<NcsError> for io::Error {
+    fn from(e: NcsError) -> Self {
+        match e {
+            NcsError::Io(e) => e,
+            other => io::Error::new(other.kind(), other),
+        }
+    }
+}
+
+// Default counts, as on the Circuit Tracks. Other models describe theirs with a Layout.
+pub const TRACKS: usize = 4;
+pub const PATTERNS: usize = 8;
+pub const STEPS: usize = 32;
+
+// How many drum tracks, patterns per track and steps per pattern a device stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub tracks: usize,
+    pub patterns: usize,
+    pub steps: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout { tracks: TRACKS, patterns: PATTERNS, steps: STEPS }
+    }
+}
+
+impl Layout {
+    // Steps over every track and pattern, saturating for absurd counts
+    pub fn total_steps(&self) -> usize {
+        self.tracks.saturating_mul(self.patterns).saturating_mul(self.steps)
+    }
+
+    // Each step has its own byte in every plane, so a layout with more steps than the
+    // file has bytes cannot describe it. Refusing it up front keeps a crafted profile
+    // from allocating or looping without bound.
+    pub fn check_fits(&self, len: usize) -> Result<(), NcsError> {
+        let total = self.total_steps();
+        if total > len {
+            return Err(NcsError::OutOfRange {
+                field: format!("Layout {}x{}x{} for a 0x{:X}-byte file (steps)", self.tracks, self.patterns, self.steps, len),
+                value: i64::try_from(total).unwrap_or(i64::MAX),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    pub velocity: u8,
+    pub probability: u8,
+    pub choice: u8,
+    pub mask: u8,
+    pub pitch: u8,
+    pub decay: u8,
+    pub distortion: u8,
+    pub eq: u8,
+}
+
+// What we know about each per-step byte plane, in layout order: valid range, the byte
+// that means "not set" (if any), whether it is a parameter lock, whether the byte is a
+// signed quantity, and whether the meaning has been confirmed against the firmware.
+// Names, ranges and the parameter planes below are all derived from this table.
+pub struct PlaneInfo {
+    pub name: &'static str,
+    pub min: i16,
+    pub max: i16,
+    pub unset: Option<u8>,
+    pub param: bool,
+    pub signed: bool,
+    pub validated: bool,
+}
+
+pub const PLANE_INFO: [PlaneInfo; 8] = [
+    PlaneInfo { name: "velocity", min: 0, max: 127, unset: None, param: false, signed: false, validated: true },
+    PlaneInfo { name: "probability", min: 0, max: 7, unset: None, param: false, signed: false, validated: true },
+    PlaneInfo { name: "choice", min: 0, max: DRUM_SAMPLES as i16 - 1, unset: Some(CHOICE_NONE), param: false, signed: false, validated: true },
+    // Only bit 0 (MASK_NOTE) is backed by data, so any other bit is out of range
+    PlaneInfo { name: "mask", min: 0, max: 1, unset: None, param: false, signed: false, validated: true },
+    // Provisional: a signed offset from PITCH_CENTER, up to the 63 notes of the firmware's
+    // 0-63 drum note range either way (pitch_shifting.md). The step byte is not traced to
+    // it; the sample data only holds 0, 3, 15 and 31
+    PlaneInfo { name: "pitch", min: -63, max: 63, unset: Some(PARAM_UNSET), param: true, signed: true, validated: false },
+    // Provisional: assumed 0..127 like the device's MIDI controls. The sample data has
+    // decay 32..61 and distortion 62..65 on locked steps, and eq only ever unset
+    PlaneInfo { name: "decay", min: 0, max: 127, unset: Some(PARAM_UNSET), param: true, signed: false, validated: false },
+    PlaneInfo { name: "distortion", min: 0, max: 127, unset: Some(PARAM_UNSET), param: true, signed: false, validated: false },
+    PlaneInfo { name: "eq", min: 0, max: 127, unset: Some(PARAM_UNSET), param: true, signed: false, validated: false },
+];
+
+// Names of the per-step byte planes, in layout order.
+pub const PLANE_NAMES: [&str; 8] = {
+    let mut names = [""; 8];
+    let mut i = 0;
+    while i < names.len() {
+        names[i] = PLANE_INFO[i].name;
+        i += 1;
+    }
+    names
+};
+
+// The parameter-lock planes as (PLANE_NAMES index, max). PARAM_UNSET on any of them
+// means the step carries no lock.
+pub const PARAM_PLANES: [(usize, u8); 4] = {
+    let mut out = [(0, 0); 4];
+    let (mut i, mut n) = (0, 0);
+    while i < PLANE_INFO.len() {
+        if PLANE_INFO[i].param {
+            out[n] = (i, PLANE_INFO[i].max as u8);
+            n += 1;
+        }
+        i += 1;
+    }
+    assert!(n == out.len());
+    out
+};
+
+pub const PARAM_UNSET: u8 = 0xFF;
+
+pub fn param_in_range(plane: usize, value: u8) -> bool {
+    PLANE_INFO.get(plane).is_some_and(|info| info.param) && plane_value_valid(plane, value)
+}
+
+// Guessed meaning of each `mask` bit, bit 0 first. In the sample data the mask is 1 on
+// every hit and 0 on every rest, so only bit 0 is backed by data; the '?' names are
+// the parameter-lock reading from the firmware analysis and still need confirming.
+pub const MASK_BITS: [&str; 8] = ["note", "pitch?", "decay?", "distortion?", "eq?", "bit5?", "bit6?", "bit7?"];
+
+// Mask bit 0, set on every hit.
+pub const MASK_NOTE: u8 = 1;
+
+// `choice` value on steps that do not pick a sample variant.
+pub const CHOICE_NONE: u8 = 0xFF;
+
+// Drum samples on the device; a set `choice` picks one of them.
+pub const DRUM_SAMPLES: u8 = 64;
+
+// Whether `value` is plausible for plane `plane` (PLANE_NAMES index): its unset byte,
+// or inside the PLANE_INFO range, read as two's complement on signed planes. Tighten
+// the table as more of each plane is confirmed.
+pub fn plane_value_valid(plane: usize, value: u8) -> bool {
+    PLANE_INFO.get(plane).is_some_and(|info| {
+        let v = if info.signed { value as i8 as i16 } else { value as i16 };
+        info.unset == Some(value) || (info.min..=info.max).contains(&v)
+    })
+}
+
+// Pitch byte that means "no transposition"; stock rests carry it. The plane is a signed
+// semitone offset from here, symmetric within PLANE_INFO's -63..63. The byte for -1 is
+// also PARAM_UNSET, so a lock one semitone down cannot be told from no lock.
+pub const PITCH_CENTER: u8 = 0;
+
+// The eight raw planes in layout order, then the decoded pitch.
+impl Serialize for Step {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut st = serializer.serialize_struct("Step", 9)?;
+        st.serialize_field("velocity", &self.velocity)?;
+        st.serialize_field("probability", &self.probability)?;
+        st.serialize_field("choice", &self.choice)?;
+        st.serialize_field("mask", &self.mask)?;
+        st.serialize_field("pitch", &self.pitch)?;
+        st.serialize_field("decay", &self.decay)?;
+        st.serialize_field("distortion", &self.distortion)?;
+        st.serialize_field("eq", &self.eq)?;
+        st.serialize_field("pitch_semitones", &self.pitch_semitones())?;
+        st.end()
+    }
+}
+
+impl Step {
+    // A step with no hit, encoded like the stock rests in the sample data: no sample
+    // choice, no mask bits, pitch at its centre and the other parameter planes unset.
+    // Used for generated patterns and shifted-in slots.
+    pub fn rest() -> Step {
+        Step {
+            velocity: 0,
+            probability: DEFAULT_PROBABILITY,
+            choice: CHOICE_NONE,
+            mask: 0,
+            pitch: PITCH_CENTER,
+            decay: PARAM_UNSET,
+            distortion: PARAM_UNSET,
+            eq: PARAM_UNSET,
+        }
+    }
+
+    // A plain hit: a rest with `velocity` and the note bit (mask bit 0) set, as every
+    // hit in the sample data has it.
+    pub fn hit(velocity: u8) -> Step {
+        Step { velocity, mask: MASK_NOTE, ..Step::rest() }
+    }
+
+    // Plane values in PLANE_NAMES order
+    pub fn planes(&self) -> [u8; 8] {
+        [self.velocity, self.probability, self.choice, self.mask, self.pitch, self.decay, self.distortion, self.eq]
+    }
+
+    // Plane lookup by name (one of PLANE_NAMES); None for unknown names.
+    pub fn plane(&self, name: &str) -> Option<u8> {
+        PLANE_NAMES.iter().position(|&n| n == name).map(|i| self.planes()[i])
+    }
+
+    // Transposition in semitones: the byte read as a signed offset from PITCH_CENTER.
+    // An unset lock (PARAM_UNSET) plays untransposed. Bytes beyond -63..63 still decode;
+    // plane_value_valid is what flags them.
+    pub fn pitch_semitones(&self) -> i8 {
+        if self.pitch == PARAM_UNSET { 0 } else { self.pitch.wrapping_sub(PITCH_CENTER) as i8 }
+    }
+
+    // MASK_BITS names of the set mask bits, lowest bit first.
+    pub fn locked_params(&self) -> Vec<&'static str> {
+        (0..8).filter(|b| self.mask & (1 << b) != 0).map(|b| MASK_BITS[b]).collect()
+    }
+
+    // Sample variant chosen for this step (provisional reading of `choice`); CHOICE_NONE if unset.
+    pub fn sample_choice(&self) -> u8 {
+        self.choice
+    }
+
+    pub fn plane_mut(&mut self, name: &str) -> Option<&mut u8> {
+        Some(match name {
+            "velocity" => &mut self.velocity,
+            "probability" => &mut self.probability,
+            "choice" => &mut self.choice,
+            "mask" => &mut self.mask,
+            "pitch" => &mut self.pitch,
+            "decay" => &mut self.decay,
+            "distortion" => &mut self.distortion,
+            "eq" => &mut self.eq,
+            _ => return None,
+        })
+    }
+
+    // Returns false (and changes nothing) for unknown plane names.
+    pub fn set_plane(&mut self, name: &str, value: u8) -> bool {
+        match self.plane_mut(name) {
+            Some(v) => { *v = value; true }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Pattern {
+    pub steps: Vec<Step>,
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Pattern { steps: vec![Step::default(); STEPS] }
+    }
+}
+
+// Probability written for generated and imported hits; played steps in the sample data use 7
+pub const DEFAULT_PROBABILITY: u8 = 7;
+
+// One splitmix64 step: advance `state` and return the next value. Any seed, including
+// 0, gives a well-mixed sequence. Small and dependency-free for seeded edits.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Bjorklund's algorithm: spread `pulses` onsets as evenly as possible over `steps`.
+fn bjorklund(pulses: usize, steps: usize) -> Vec<bool> {
+    if pulses == 0 {
+        return vec![false; steps];
+    }
+    let mut a: Vec<Vec<bool>> = vec![vec![true]; pulses];
+    let mut b: Vec<Vec<bool>> = vec![vec![false]; steps - pulses];
+    while b.len() > 1 {
+        let n = a.len().min(b.len());
+        let remainder = if a.len() > n { a[n..].to_vec() } else { b[n..].to_vec() };
+        a = a.iter().zip(&b).map(|(x, y)| [x.as_slice(), y.as_slice()].concat()).collect();
+        b = remainder;
+    }
+    a.into_iter().chain(b).flatten().collect()
+}
+
+impl Pattern {
+    // A pattern of `len` rests.
+    pub fn rests(len: usize) -> Pattern {
+        Pattern { steps: vec![Step::rest(); len] }
+    }
+
+    // Euclidean rhythm E(pulses, steps) over the first `steps` steps of a STEPS-long pattern;
+    // later steps are rests. Pulses beyond `steps` are clamped, and hits get `velocity`
+    // (1..127) with DEFAULT_PROBABILITY.
+    pub fn euclid(pulses: u8, steps: u8, velocity: u8) -> Result<Pattern, NcsError> {
+        if steps as usize > STEPS {
+            return Err(NcsError::OutOfRange { field: format!("Euclidean rhythm steps (0..{})", STEPS), value: steps as i64 });
+        }
+        let mut pattern = Pattern::rests(STEPS);
+        for (st, hit) in pattern.steps.iter_mut().zip(bjorklund(pulses.min(steps) as usize, steps as usize)) {
+            if hit { *st = Step::hit(velocity.clamp(1, 127)); }
+        }
+        Ok(pattern)
+    }
+
+    // Multiply every hit's velocity, rounding and clamping to 1..127 so hits never become rests.
+    // A factor of 1.0 leaves every byte alone, even out-of-range ones.
+    pub fn scale_velocity(&mut self, factor: f32) {
+        if factor == 1.0 { return; }
+        for st in self.steps.iter_mut().filter(|st| st.velocity > 0) {
+            st.velocity = (st.velocity as f32 * factor).round().clamp(1.0, 127.0) as u8;
+        }
+    }
+
+    // Fraction of steps (0.0..=1.0) whose eight plane bytes all match `other`.
+    pub fn step_match(&self, other: &Pattern) -> f64 {
+        let same = self.steps.iter().zip(&other.steps).filter(|(a, b)| a.planes() == b.planes()).count();
+        same as f64 / self.steps.len().max(1) as f64
+    }
+
+    // Fuzzy rhythmic similarity, 0.0..=1.0. Only steps where either pattern has a hit
+    // count. A hit in both scores 1 - |velocity difference| / 127, a hit in only one
+    // scores 0, and the result is the mean over those steps: an onset Jaccard index
+    // weighted by velocity closeness. Parameter bytes are ignored. Identical hits
+    // (and two empty patterns) score exactly 1.0.
+    pub fn similarity(&self, other: &Pattern) -> f32 {
+        let (mut union, mut score) = (0usize, 0f32);
+        for i in 0..self.steps.len().max(other.steps.len()) {
+            let a = self.steps.get(i).map_or(0, |st| st.velocity);
+            let b = other.steps.get(i).map_or(0, |st| st.velocity);
+            if a == 0 && b == 0 { continue; }
+            union += 1;
+            if a > 0 && b > 0 {
+                score += 1.0 - (a.min(127) as f32 - b.min(127) as f32).abs() / 127.0;
+            }
+        }
+        if union == 0 { 1.0 } else { score / union as f32 }
+    }
+
+    // Multiply every hit's probability, rounding and clamping to 0..7. Rests are untouched.
+    pub fn scale_probability(&mut self, factor: f32) {
+        if factor == 1.0 { return; }
+        let max = PLANE_INFO[1].max as f32;
+        for st in self.steps.iter_mut().filter(|st| st.velocity > 0) {
+            st.probability = (st.probability as f32 * factor).round().clamp(0.0, max) as u8;
+        }
+    }
+
+    // Nudge every hit's velocity by a seeded random amount in -amount..=amount, clamped to
+    // 1..127 so hits never become rests. The same seed always gives the same result.
+    pub fn humanize(&mut self, amount: u8, seed: u64) {
+        let mut state = seed;
+        for st in self.steps.iter_mut().filter(|st| st.velocity > 0) {
+            let delta = (splitmix64(&mut state) % (2 * amount as u64 + 1)) as i32 - amount as i32;
+            st.velocity = (st.velocity as i32 + delta).clamp(1, 127) as u8;
+        }
+    }
+
+    // Snap hit probabilities above the 0..7 range to the nearest valid value (7).
+    // Returns (step, old, new) for every step it changed; rests are untouched.
+    pub fn clean_probability(&mut self) -> Vec<(usize, u8, u8)> {
+        let max = PLANE_INFO[1].max as u8;
+        let mut changed = Vec::new();
+        for (i, st) in self.steps.iter_mut().enumerate().filter(|(_, st)| st.velocity > 0 && st.probability > max) {
+            changed.push((i, st.probability, max));
+            st.probability = max;
+        }
+        changed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.iter().all(|st| st.velocity == 0)
+    }
+
+    // Layer two patterns step by step: the louder hit wins with all of its parameter bytes,
+    // a hit beats a rest, and ties (including two rests) keep `self`'s step. The result
+    // has `self`'s length.
+    pub fn overlay(&self, other: &Pattern) -> Pattern {
+        let steps = self.steps.iter().enumerate().map(|(i, a)| match other.steps.get(i) {
+            Some(b) if b.velocity > a.velocity => b.clone(),
+            _ => a.clone(),
+        });
+        Pattern { steps: steps.collect() }
+    }
+
+    // Move every step `n` positions later (earlier for negative n), wrapping around.
+    pub fn rotate(&mut self, n: isize) {
+        let len = self.steps.len();
+        if len == 0 { return; }
+        self.steps.rotate_right(n.rem_euclid(len as isize) as usize);
+    }
+
+    pub fn reverse(&mut self) {
+        self.steps.reverse();
+    }
+
+    // Like rotate, but steps pushed off either end are dropped and the vacated
+    // positions become rests.
+    pub fn shift(&mut self, n: isize) {
+        let len = self.steps.len();
+        let k = n.unsigned_abs().min(len);
+        if n >= 0 {
+            self.steps.rotate_right(k);
+            self.steps[..k].fill_with(Step::rest);
+        } else {
+            self.steps.rotate_left(k);
+            self.steps[len - k..].fill_with(Step::rest);
+        }
+    }
+
+    pub fn stats(&self) -> PatternStats {
+        let hits: Vec<&Step> = self.steps.iter().filter(|st| st.velocity > 0).collect();
+        let mut probability = [0usize; 8];
+        for st in &hits {
+            if let Some(n) = probability.get_mut(st.probability as usize) { *n += 1; }
+        }
+        PatternStats {
+            onsets: hits.len(),
+            density: hits.len() as f64 * 100.0 / self.steps.len().max(1) as f64,
+            // Rests are left out so they do not pull the mean down
+            mean_velocity: if hits.is_empty() { 0.0 } else { hits.iter().map(|st| st.velocity as f64).sum::<f64>() / hits.len() as f64 },
+            probability,
+        }
+    }
+}
+
+// Rhythm summary of one pattern. `probability` counts hits per probability value 0..7;
+// hits with an out-of-range value are not counted there.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PatternStats {
+    pub onsets: usize,
+    pub density: f64,
+    pub mean_velocity: f64,
+    pub probability: [usize; 8],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrumTrack {
+    pub patterns: Vec<Pattern>,
+    pub delay_send: u8,  // 0..127 (provisional offset)
+    pub reverb_send: u8, // 0..127 (provisional offset)
+}
+
+impl Default for DrumTrack {
+    fn default() -> Self {
+        DrumTrack { patterns: vec![Pattern::default(); PATTERNS], delay_send: 0, reverb_send: 0 }
+    }
+}
+
+impl DrumTrack {
+    // (choice, hits) for every choice a hit uses, ascending; CHOICE_NONE counts the hits
+    // that play the track's default sample. Rests are not counted.
+    pub fn choice_histogram(&self) -> Vec<(u8, usize)> {
+        let mut counts = std::collections::BTreeMap::new();
+        for st in self.patterns.iter().flat_map(|p| &p.steps).filter(|st| st.velocity > 0) {
+            *counts.entry(st.sample_choice()).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+}
+
+pub const MAX_SEND: u8 = 127;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrumData {
+    pub tracks: Vec<DrumTrack>,
+}
+
+impl Default for DrumData {
+    fn default() -> Self {
+        DrumData { tracks: vec![DrumTrack::default(); TRACKS] }
+    }
+}
+
+// Iterator behind DrumData::steps; walks (track, pattern, step) cursors so it needs
+// no allocation.
+#[derive(Debug, Clone)]
+pub struct Steps<'a> {
+    drums: &'a DrumData,
+    track: usize,
+    pattern: usize,
+    step: usize,
+    left: usize,
+}
+
+impl<'a> Iterator for Steps<'a> {
+    type Item = (usize, usize, usize, &'a Step);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let track = self.drums.tracks.get(self.track)?;
+            let Some(pattern) = track.patterns.get(self.pattern) else {
+                (self.track, self.pattern) = (self.track + 1, 0);
+                continue;
+            };
+            let Some(st) = pattern.steps.get(self.step) else {
+                (self.pattern, self.step) = (self.pattern + 1, 0);
+                continue;
+            };
+            let item = (self.track, self.pattern, self.step, st);
+            self.step += 1;
+            self.left -= 1;
+            return Some(item);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl ExactSizeIterator for Steps<'_> {}
+
+// One recorded read: where, how wide, which field and what came back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub offset: usize,
+    pub len: usize,
+    pub field: String,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WarningCategory {
+    // A value outside the range the firmware accepts
+    Range,
+    // An end index before its start
+    Order,
+    // Padding or spare bytes that should be zero
+    Padding,
+    // Text that is not printable ASCII
+    Encoding,
+    // File header fields that disagree with the file itself
+    Header,
+    // A value outside a range that is not yet confirmed (PLANE_INFO `validated: false`);
+    // reported, but never fatal in strict mode
+    Provisional,
+}
+
+// A suspicious but parseable value. Parsers collect these on the ByteReader instead
+// of printing them, so callers decide whether to print, ignore or reject.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationWarning {
+    pub category: WarningCategory,
+    pub message: String,
+    pub offset: usize,
+}
+
+impl std::fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// The CLI's default presentation: one `warn` log record per warning.
+pub fn print_warnings(warnings: &[ValidationWarning]) {
+    for w in warnings {
+        log::warn!("{}", w);
+    }
+}
+
+// Error payload of strict mode, so callers can tell "valid file with suspicious
+// values" from a malformed one by downcasting the io::Error.
+#[derive(Debug)]
+pub struct StrictWarnings(pub Vec<ValidationWarning>);
+
+impl std::fmt::Display for StrictWarnings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} validation warning(s):", self.0.len())?;
+        for w in &self.0 {
+            write!(f, "\n{} (at {:#x})", w, w.offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StrictWarnings {}
+
+// Strict mode: any warning becomes an InvalidData error carrying StrictWarnings.
+// Provisional warnings are skipped, since their ranges are still guesses.
+pub fn reject_warnings(warnings: &[ValidationWarning]) -> io::Result<()> {
+    let fatal: Vec<ValidationWarning> = warnings.iter().filter(|w| w.category != WarningCategory::Provisional).cloned().collect();
+    if fatal.is_empty() {
+        return Ok(());
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, StrictWarnings(fatal)))
+}
+
+// One coordinate of a repeated field: a one-letter key ('t'rack, 'p'attern, 's'tep, ...)
+// and its index.
+pub type FieldIndex = (char, usize);
+
+fn field_label(field: &str, index: &[FieldIndex]) -> String {
+    if index.is_empty() {
+        return field.to_string();
+    }
+    let coords: Vec<String> = index.iter().map(|(key, i)| format!("{}{}", key, i)).collect();
+    format!("{}[{}]", field, coords.join(","))
+}
+
+// Bounds-checked byte access shared by all parsers. Tracing is opt-in: without it
+// a read is an index plus one branch, with it every read is appended to the log.
+// Validation warnings raised while parsing are always collected.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    trace: Option<RefCell<Vec<TraceEntry>>>,
+    warnings: RefCell<Vec<ValidationWarning>>,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, trace: None, warnings: RefCell::new(Vec::new()) }
+    }
+
+    pub fn with_trace(data: &'a [u8]) -> Self {
+        ByteReader { data, trace: Some(RefCell::new(Vec::new())), warnings: RefCell::new(Vec::new()) }
+    }
+
+    pub fn warn(&self, category: WarningCategory, offset: usize, message: String) {
+        self.warnings.borrow_mut().push(ValidationWarning { category, message, offset });
+    }
+
+    pub fn take_warnings(&self) -> Vec<ValidationWarning> {
+        self.warnings.take()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes<const N: usize>(&self, offset: usize, field: &str) -> Result<[u8; N], NcsError> {
+        self.bytes_at(offset, field, &[])
+    }
+
+    fn bytes_at<const N: usize>(&self, offset: usize, field: &str, index: &[FieldIndex]) -> Result<[u8; N], NcsError> {
+        let end = offset.checked_add(N).filter(|&e| e <= self.data.len())
+            .ok_or_else(|| NcsError::truncated(field_label(field, index), offset.saturating_add(N), self.data.len()))?;
+        let mut out = [0u8; N];
+        out.copy_from_slice(&self.data[offset..end]);
+        Ok(out)
+    }
+
+    // The label is only built when tracing, so untraced reads never allocate.
+    fn record(&self, offset: usize, len: usize, field: &str, index: &[FieldIndex], value: u32) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().push(TraceEntry { offset, len, field: field_label(field, index), value });
+        }
+    }
+
+    pub fn u8(&self, offset: usize, field: &str) -> Result<u8, NcsError> {
+        self.u8_at(offset, field, &[])
+    }
+
+    // A read inside a repeated block, traced as e.g. `velocity[t2,p2,s0]`.
+    pub fn u8_at(&self, offset: usize, field: &str, index: &[FieldIndex]) -> Result<u8, NcsError> {
+        let [b] = self.bytes_at::<1>(offset, field, index)?;
+        self.record(offset, 1, field, index, b as u32);
+        Ok(b)
+    }
+
+    pub fn u16_le(&self, offset: usize, field: &str) -> Result<u16, NcsError> {
+        self.u16_le_at(offset, field, &[])
+    }
+
+    pub fn u16_le_at(&self, offset: usize, field: &str, index: &[FieldIndex]) -> Result<u16, NcsError> {
+        let v = u16::from_le_bytes(self.bytes_at::<2>(offset, field, index)?);
+        self.record(offset, 2, field, index, v as u32);
+        Ok(v)
+    }
+
+    pub fn u32_le(&self, offset: usize, field: &str) -> Result<u32, NcsError> {
+        let v = u32::from_le_bytes(self.bytes::<4>(offset, field)?);
+        self.record(offset, 4, field, &[], v);
+        Ok(v)
+    }
+
+    pub fn take_trace(&self) -> Vec<TraceEntry> {
+        self.trace.as_ref().map(|t| t.take()).unwrap_or_default()
+    }
+}
+
+// Run one parser over `data` and print its warnings, for the per-block from_bytes.
+fn parse_printing_warnings<T>(data: &[u8], parse: impl FnOnce(&ByteReader) -> Result<T, NcsError>) -> Result<T, NcsError> {
+    let r = ByteReader::new(data);
+    let value = parse(&r)?;
+    print_warnings(&r.take_warnings());
+    Ok(value)
+}
+
+pub fn format_trace(entries: &[TraceEntry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        out.push_str(&format!("{} @ 0x{:05X} = 0x{:0w$X}\n", e.field, e.offset, e.value, w = e.len * 2));
+    }
+    out
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fx {
+    pub delay_preset: u8,  // 0..15
+    pub reverb_preset: u8, // 0..7
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FxOffsets {
+    pub delay_preset: usize,
+    pub reverb_preset: usize,
+}
+
+impl Default for FxOffsets {
+    // Offsets from reverse engineering analysis
+    fn default() -> Self {
+        FxOffsets {
+            delay_preset: 0x00026D0E, // (&DAT_ram_00026d0e)[param1]
+            reverb_preset: 0x00026D0F, // (&DAT_ram_00026d0f)[param1]
+        }
+    }
+}
+
+impl Fx {
+    pub fn from_bytes(data: &[u8], off: &FxOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &FxOffsets) -> Result<Self, NcsError> {
+        if off.delay_preset >= r.len() || off.reverb_preset >= r.len() {
+            return Err(NcsError::truncated("fx", off.delay_preset.max(off.reverb_preset).saturating_add(1), r.len()));
+        }
+        let fx = Fx {
+            delay_preset: r.u8(off.delay_preset, "delay_preset")?,
+            reverb_preset: r.u8(off.reverb_preset, "reverb_preset")?,
+        };
+        // Validate ranges based on validate_fx_presets() from decompiled code
+        if fx.delay_preset >= 0x10 {
+            r.warn(WarningCategory::Range, off.delay_preset, format!("Session's delay preset index is out of range: {} (expected 0..15)", fx.delay_preset));
+        }
+        if fx.reverb_preset >= 0x08 {
+            r.warn(WarningCategory::Range, off.reverb_preset, format!("Session's reverb preset index is out of range: {} (expected 0..7)", fx.reverb_preset));
+        }
+        Ok(fx)
+    }
+
+    pub fn to_bytes(&self, off: &FxOffsets, buf: &mut [u8]) -> io::Result<()> {
+        if off.delay_preset >= buf.len() || off.reverb_preset >= buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "FX offset out of bounds"));
+        }
+        buf[off.delay_preset] = self.delay_preset;
+        buf[off.reverb_preset] = self.reverb_preset;
+        Ok(())
+    }
+}
+
+// Global mix settings. Provisional: 0x26D10 holds 0x40 in every stock session,
+// which looks like a mid-scale master volume; not yet confirmed on hardware.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Globals {
+    pub master_volume: u8, // 0..MAX_MASTER_VOLUME
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlobalsOffsets {
+    pub master_volume: usize,
+}
+
+impl Default for GlobalsOffsets {
+    fn default() -> Self {
+        GlobalsOffsets { master_volume: 0x26D10 }
+    }
+}
+
+pub const MAX_MASTER_VOLUME: u8 = 127;
+
+impl Globals {
+    pub fn from_bytes(data: &[u8], off: &GlobalsOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &GlobalsOffsets) -> Result<Self, NcsError> {
+        let master_volume = r.u8(off.master_volume, "master_volume")?;
+        if master_volume > MAX_MASTER_VOLUME {
+            r.warn(WarningCategory::Range, off.master_volume, format!("Master volume out of range: {} (expected 0..{})", master_volume, MAX_MASTER_VOLUME));
+        }
+        Ok(Globals { master_volume })
+    }
+
+    pub fn set_master_volume(&mut self, level: u8) -> io::Result<()> {
+        if level > MAX_MASTER_VOLUME {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Master volume {} out of range (0..{})", level, MAX_MASTER_VOLUME)));
+        }
+        self.master_volume = level;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self, off: &GlobalsOffsets, buf: &mut [u8]) -> io::Result<()> {
+        let b = buf.get_mut(off.master_volume)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Master volume offset out of bounds"))?;
+        *b = self.master_volume;
+        Ok(())
+    }
+}
+
+// Fixed-length session name in the file header. Stock files pad it with spaces.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct SessionName {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NameOffsets {
+    pub base: usize, // 0x10
+    pub len: usize,  // 32 bytes
+}
+
+impl Default for NameOffsets {
+    fn default() -> Self {
+        NameOffsets { base: 0x10, len: 32 }
+    }
+}
+
+impl SessionName {
+    pub fn new(name: &str, off: &NameOffsets) -> io::Result<Self> {
+        if name.len() > off.len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Session name longer than {} characters", off.len)));
+        }
+        if let Some(c) = name.chars().find(|c| !(' '..='~').contains(c)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Session name contains non-printable character {:?}", c)));
+        }
+        Ok(SessionName { name: name.to_string() })
+    }
+
+    pub fn parse(r: &ByteReader, off: &NameOffsets) -> Result<Self, NcsError> {
+        let mut raw = Vec::with_capacity(off.len);
+        for i in 0..off.len {
+            raw.push(r.u8_at(checked_offset("name", off.base, &[(i, 1)])?, "name", &[('c', i)])?);
+        }
+        let end = raw.iter().rposition(|&b| b != b' ' && b != 0).map_or(0, |i| i + 1);
+        if raw[..end].iter().any(|b| !(0x20..=0x7E).contains(b)) {
+            r.warn(WarningCategory::Encoding, off.base, "Session name contains non-printable bytes".to_string());
+        }
+        Ok(SessionName { name: String::from_utf8_lossy(&raw[..end]).into_owned() })
+    }
+
+    // Writes the name NUL-padded to the full field width. Stock files pad with spaces
+    // and parse treats both alike, so a field already holding this name is left as is
+    // rather than rewriting its padding on every save.
+    pub fn to_bytes(&self, off: &NameOffsets, buf: &mut [u8]) -> io::Result<()> {
+        let end = checked_offset("name", off.base, &[(1, off.len)])?;
+        if end > buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Session name offset out of bounds"));
+        }
+        let field = &mut buf[off.base..end];
+        let used = field.iter().rposition(|&b| b != b' ' && b != 0).map_or(0, |i| i + 1);
+        if field[..used] == *self.name.as_bytes() {
+            return Ok(());
+        }
+        field.fill(0);
+        field[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Timing {
+    pub tempo: u8,           // 40..240 BPM (inclusive)
+    pub swing: u8,           // 20..80 (inclusive)
+    pub swing_sync_rate: u8, // 0..7
+    pub spare1: u32,         // must be 0
+    pub spare2: u32,         // must be 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimingOffsets {
+    pub tempo: usize,           // +0x34
+    pub swing: usize,           // +0x35
+    pub swing_sync_rate: usize, // +0x36
+    pub spare1: usize,          // +0x38 (u32 LE)
+    pub spare2: usize,          // +0x3C (u32 LE)
+}
+
+// How to read the timing block's spare1 dword. Stock files keep it zero, but some
+// firmware revisions may store a format version there instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SpareSemantics {
+    #[default]
+    Zero,
+    Version,
+}
+
+impl Default for TimingOffsets {
+    fn default() -> Self {
+        TimingOffsets { tempo: 0x34, swing: 0x35, swing_sync_rate: 0x36, spare1: 0x38, spare2: 0x3C }
+    }
+}
+
+impl Timing {
+    pub fn from_bytes(data: &[u8], off: &TimingOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &TimingOffsets) -> Result<Self, NcsError> {
+        Self::parse_with(r, off, SpareSemantics::Zero)
+    }
+
+    pub fn parse_with(r: &ByteReader, off: &TimingOffsets, spare: SpareSemantics) -> Result<Self, NcsError> {
+        // Bounds checks
+        for &idx in [off.tempo, off.swing, off.swing_sync_rate].iter() {
+            if idx >= r.len() {
+                return Err(NcsError::truncated("timing", idx.saturating_add(1), r.len()));
+            }
+        }
+        let spare_end = checked_offset("timing spare", off.spare1.max(off.spare2), &[(1, 4)])?;
+        if spare_end > r.len() {
+            return Err(NcsError::truncated("timing spare", spare_end, r.len()));
+        }
+        let tempo = r.u8(off.tempo, "tempo")?;
+        let swing = r.u8(off.swing, "swing")?;
+        let swing_sync_rate = r.u8(off.swing_sync_rate, "swing_sync_rate")?;
+        let spare1 = r.u32_le(off.spare1, "spare1")?;
+        let spare2 = r.u32_le(off.spare2, "spare2")?;
+        // Mirror firmware range checks
+        if !(40..=240).contains(&tempo) {
+            r.warn(WarningCategory::Range, off.tempo, format!("Tempo out of range: {} (expected 40..240)", tempo));
+        }
+        if !(20..=80).contains(&swing) {
+            r.warn(WarningCategory::Range, off.swing, format!("Swing out of range: {} (expected 20..80)", swing));
+        }
+        if swing_sync_rate >= 8 {
+            r.warn(WarningCategory::Range, off.swing_sync_rate, format!("Swing sync rate out of range: {} (expected 0..7)", swing_sync_rate));
+        }
+        let timing = Timing { tempo, swing, swing_sync_rate, spare1, spare2 };
+        for w in timing.spare_warnings(off, spare) {
+            r.warn(w.category, w.offset, w.message);
+        }
+        Ok(timing)
+    }
+
+    pub fn spare_warnings(&self, off: &TimingOffsets, spare: SpareSemantics) -> Vec<ValidationWarning> {
+        let mut out = Vec::new();
+        let mut warn = |offset, message| out.push(ValidationWarning { category: WarningCategory::Padding, message, offset });
+        if spare == SpareSemantics::Zero && self.spare1 != 0 {
+            warn(off.spare1, format!("Session timing spare1 not set to zero: {}", self.spare1));
+        }
+        if self.spare2 != 0 {
+            warn(off.spare2, format!("Session timing spare2 not set to zero: {}", self.spare2));
+        }
+        out
+    }
+
+    pub fn format_version(&self, spare: SpareSemantics) -> Option<u32> {
+        match spare {
+            SpareSemantics::Zero => None,
+            SpareSemantics::Version => Some(self.spare1),
+        }
+    }
+
+    pub fn to_bytes(&self, off: &TimingOffsets, buf: &mut [u8]) -> io::Result<()> {
+        let spare_end = checked_offset("timing spare", off.spare1.max(off.spare2), &[(1, 4)])?;
+        if [off.tempo, off.swing, off.swing_sync_rate].iter().any(|&idx| idx >= buf.len()) || spare_end > buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Timing offset out of bounds"));
+        }
+        buf[off.tempo] = self.tempo;
+        buf[off.swing] = self.swing;
+        buf[off.swing_sync_rate] = self.swing_sync_rate;
+        buf[off.spare1..off.spare1 + 4].copy_from_slice(&self.spare1.to_le_bytes());
+        buf[off.spare2..off.spare2 + 4].copy_from_slice(&self.spare2.to_le_bytes());
+        Ok(())
+    }
+
+    // Checked to_bytes for edits: refuse a tempo outside 40..240, a swing outside 20..80
+    // or a swing sync rate outside 0..7 instead of writing a file the firmware would reject.
+    pub fn write_into(&self, buf: &mut [u8], off: &TimingOffsets) -> io::Result<()> {
+        if !(40..=240).contains(&self.tempo) {
+            return Err(NcsError::OutOfRange { field: "tempo (40..240)".to_string(), value: self.tempo as i64 }.into());
+        }
+        if !(20..=80).contains(&self.swing) {
+            return Err(NcsError::OutOfRange { field: "swing (20..80)".to_string(), value: self.swing as i64 }.into());
+        }
+        if self.swing_sync_rate > 7 {
+            return Err(NcsError::OutOfRange { field: "swing_sync_rate (0..7)".to_string(), value: self.swing_sync_rate as i64 }.into());
+        }
+        self.to_bytes(off, buf)
+    }
+}
+
+// A session's "feel" separated from its notes, stored as a small TOML file.
+// The layout has no per-step micro-timing plane yet, so only the global timing is captured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Groove {
+    pub tempo: u8,
+    pub swing: u8,
+    pub swing_sync_rate: u8,
+}
+
+impl Groove {
+    pub fn from_timing(timing: &Timing) -> Self {
+        Groove { tempo: timing.tempo, swing: timing.swing, swing_sync_rate: timing.swing_sync_rate }
+    }
+
+    // Overwrite only the timing fields of `data`; everything else is preserved. Values
+    // out of range (a hand-edited template) are refused and leave `data` untouched.
+    pub fn apply(&self, data: &mut [u8], off: &TimingOffsets) -> io::Result<()> {
+        let mut timing = Timing::from_bytes(data, off)?;
+        timing.tempo = self.tempo;
+        timing.swing = self.swing;
+        timing.swing_sync_rate = self.swing_sync_rate;
+        timing.write_into(data, off)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SceneEntry { pub start: u8, pub end: u8, pub pad: u16 }
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Scene { pub entries: [SceneEntry; 8] }
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Scenes { pub scenes: [Scene; 16] }
+
+impl SceneEntry {
+    // The ranges the parser warns about: an index past pattern 7 or end before start.
+    pub fn is_valid(&self) -> bool {
+        self.start < 8 && self.end < 8 && self.start <= self.end
+    }
+}
+
+impl Scene {
+    // A scene that was never set keeps every entry at 0..0.
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|e| e.start == 0 && e.end == 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenesOffsets {
+    pub base: usize,          // 0x40
+    pub scene_stride: usize,  // 0x28
+    pub entry_stride: usize,  // 4
+}
+
+impl Default for ScenesOffsets {
+    fn default() -> Self {
+        ScenesOffsets { base: 0x40, scene_stride: 0x28, entry_stride: 4 }
+    }
+}
+
+impl Scenes {
+    pub fn from_bytes(data: &[u8], off: &ScenesOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &ScenesOffsets) -> Result<Self, NcsError> {
+        let mut scenes: [Scene; 16] = Default::default();
+        for (si, scene) in scenes.iter_mut().enumerate() {
+            let mut entries: [SceneEntry; 8] = Default::default();
+            for (ei, entry) in entries.iter_mut().enumerate() {
+                let idx = checked_offset("scenes", off.base, &[(si, off.scene_stride), (ei, off.entry_stride)])?;
+                if idx.saturating_add(4) > r.len() { return Err(NcsError::truncated("scenes", idx.saturating_add(4), r.len())); }
+                let at = [('s', si), ('e', ei)];
+                let start = r.u8_at(idx, "scene.start", &at)?;
+                let end = r.u8_at(idx + 1, "scene.end", &at)?;
+                let pad = r.u16_le_at(idx + 2, "scene.pad", &at)?;
+                // Mirror key firmware checks
+                if start >= 8 { r.warn(WarningCategory::Range, idx, format!("Scene {} entry {} start out of range: {}", si, ei, start)); }
+                if end >= 8 { r.warn(WarningCategory::Range, idx + 1, format!("Scene {} entry {} end out of range: {}", si, ei, end)); }
+                if end < start { r.warn(WarningCategory::Order, idx, format!("Scene {} entry {} end < start ({} < {})", si, ei, end, start)); }
+                if pad != 0 { r.warn(WarningCategory::Padding, idx + 2, format!("Scene {} entry {} padding not zero: {}", si, ei, pad)); }
+                *entry = SceneEntry { start, end, pad };
+            }
+            *scene = Scene { entries };
+        }
+        Ok(Scenes { scenes })
+    }
+
+    pub fn to_bytes(&self, off: &ScenesOffsets, buf: &mut [u8]) -> io::Result<()> {
+        for (si, scene) in self.scenes.iter().enumerate() {
+            for (ei, e) in scene.entries.iter().enumerate() {
+                let idx = checked_offset("scenes", off.base, &[(si, off.scene_stride), (ei, off.entry_stride)])?;
+                if idx.saturating_add(4) > buf.len() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Scenes offset out of bounds")); }
+                buf[idx] = e.start;
+                buf[idx + 1] = e.end;
+                buf[idx + 2..idx + 4].copy_from_slice(&e.pad.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SceneChain { pub start_scene: u8, pub end_scene: u8, pub pad: u16 }
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PatternChainEntry { pub start: u8, pub end: u8, pub pad: u16 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternChains { pub entries: [PatternChainEntry; 8] }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainOffsets {
+    pub scene_chain_base: usize,    // 0x2C0 (start,end,pad u16)
+    pub pattern_chain_base: usize,  // 0x2C4 (array of 8 entries, stride 4)
+    pub pattern_chain_stride: usize // 4
+}
+
+impl Default for ChainOffsets {
+    fn default() -> Self {
+        ChainOffsets { scene_chain_base: 0x2C0, pattern_chain_base: 0x2C4, pattern_chain_stride: 4 }
+    }
+}
+
+impl SceneChain {
+    // Scenes in playback order, start_scene..=end_scene; empty when end < start.
+    pub fn play_order(&self) -> Vec<u8> {
+        (self.start_scene..=self.end_scene).collect()
+    }
+
+    pub fn from_bytes(data: &[u8], off: &ChainOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &ChainOffsets) -> Result<Self, NcsError> {
+        let b = off.scene_chain_base;
+        if b.saturating_add(4) > r.len() { return Err(NcsError::truncated("scene chain", b.saturating_add(4), r.len())); }
+        let start_scene = r.u8(b, "scene_chain.start")?;
+        let end_scene = r.u8(b + 1, "scene_chain.end")?;
+        let pad = r.u16_le(b + 2, "scene_chain.pad")?;
+        if start_scene >= 16 { r.warn(WarningCategory::Range, b, format!("Scene chain start out of range: {} (expected 0..15)", start_scene)); }
+        if end_scene >= 16 { r.warn(WarningCategory::Range, b + 1, format!("Scene chain end out of range: {} (expected 0..15)", end_scene)); }
+        if end_scene < start_scene { r.warn(WarningCategory::Order, b, format!("Scene chain end < start ({} < {})", end_scene, start_scene)); }
+        if pad != 0 { r.warn(WarningCategory::Padding, b + 2, format!("Scene chain padding not set to 0: {}", pad)); }
+        Ok(SceneChain { start_scene, end_scene, pad })
+    }
+
+    pub fn to_bytes(&self, off: &ChainOffsets, buf: &mut [u8]) -> io::Result<()> {
+        let b = off.scene_chain_base;
+        if b.saturating_add(4) > buf.len() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "SceneChain out of bounds")); }
+        buf[b] = self.start_scene;
+        buf[b + 1] = self.end_scene;
+        buf[b + 2..b + 4].copy_from_slice(&self.pad.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl PatternChains {
+    pub fn from_bytes(data: &[u8], off: &ChainOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &ChainOffsets) -> Result<Self, NcsError> {
+        let mut entries: [PatternChainEntry; 8] = Default::default();
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let idx = checked_offset("pattern chains", off.pattern_chain_base, &[(i, off.pattern_chain_stride)])?;
+            if idx.saturating_add(4) > r.len() { return Err(NcsError::truncated("pattern chains", idx.saturating_add(4), r.len())); }
+            let at = [('c', i)];
+            let start = r.u8_at(idx, "pattern_chain.start", &at)?;
+            let end = r.u8_at(idx + 1, "pattern_chain.end", &at)?;
+            let pad = r.u16_le_at(idx + 2, "pattern_chain.pad", &at)?;
+            if start >= 8 { r.warn(WarningCategory::Range, idx, format!("Pattern chain {} start out of range: {} (0..7)", i, start)); }
+            if end >= 8 { r.warn(WarningCategory::Range, idx + 1, format!("Pattern chain {} end out of range: {} (0..7)", i, end)); }
+            if end < start { r.warn(WarningCategory::Order, idx, format!("Pattern chain {} end < start ({} < {})", i, end, start)); }
+            if pad != 0 { r.warn(WarningCategory::Padding, idx + 2, format!("Pattern chain {} padding not set to 0: {}", i, pad)); }
+            *entry = PatternChainEntry { start, end, pad };
+        }
+        Ok(PatternChains { entries })
+    }
+
+    pub fn to_bytes(&self, off: &ChainOffsets, buf: &mut [u8]) -> io::Result<()> {
+        for (i, e) in self.entries.iter().enumerate() {
+            let idx = checked_offset("pattern chains", off.pattern_chain_base, &[(i, off.pattern_chain_stride)])?;
+            if idx.saturating_add(4) > buf.len() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "PatternChains out of bounds")); }
+            buf[idx] = e.start;
+            buf[idx + 1] = e.end;
+            buf[idx + 2..idx + 4].copy_from_slice(&e.pad.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    // Each of the 8 entries belongs to the track at its index, so entries are fixed
+    // in place rather than moved: end < start is un-reversed, and an entry that cannot
+    // be played (an endpoint outside 0..7) is reset to the zeroed 0..0 stock files use.
+    pub fn normalized(&self) -> PatternChains {
+        let entries = self.entries.map(|e| {
+            if e.start < 8 && e.end < 8 {
+                PatternChainEntry { start: e.start.min(e.end), end: e.start.max(e.end), pad: 0 }
+            } else {
+                PatternChainEntry { start: 0, end: 0, pad: 0 }
+            }
+        });
+        PatternChains { entries }
+    }
+
+    // Pattern order of each slot; an end < start entry plays nothing.
+    pub fn play_order(&self) -> [Vec<u8>; 8] {
+        std::array::from_fn(|i| (self.entries[i].start..=self.entries[i].end).collect())
+    }
+}
+
+pub const ROOT_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// Scale types in the order the device's scale page lists them.
+pub const SCALE_TYPE_NAMES: [&str; 16] = [
+    "Natural Minor", "Major", "Dorian", "Phrygian", "Mixolydian", "Melodic Minor", "Harmonic Minor", "Bebop Dorian",
+    "Blues", "Minor Pentatonic", "Hungarian Minor", "Ukrainian Dorian", "Marva", "Todi", "Whole Tone", "Chromatic",
+];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ScaleSettings { pub root: u8, pub scale_type: u8 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScaleOffsets { pub root: usize, pub scale_type: usize }
+
+impl Default for ScaleOffsets {
+    fn default() -> Self {
+        ScaleOffsets { root: 0x26D0C, scale_type: 0x26D0D }
+    }
+}
+
+impl ScaleSettings {
+    pub fn from_bytes(data: &[u8], off: &ScaleOffsets) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse(r, off))
+    }
+
+    pub fn parse(r: &ByteReader, off: &ScaleOffsets) -> Result<Self, NcsError> {
+        if off.root >= r.len() || off.scale_type >= r.len() {
+            return Err(NcsError::truncated("scale", off.root.max(off.scale_type).saturating_add(1), r.len()));
+        }
+        let root = r.u8(off.root, "scale.root")?;
+        let scale_type = r.u8(off.scale_type, "scale.type")?;
+        if root >= 12 { r.warn(WarningCategory::Range, off.root, format!("Scale root out of range: {} (expected 0..11)", root)); }
+        if scale_type >= 16 { r.warn(WarningCategory::Range, off.scale_type, format!("Invalid scale type: {} (expected 0..15)", scale_type)); }
+        Ok(ScaleSettings { root, scale_type })
+    }
+
+    pub fn to_bytes(&self, off: &ScaleOffsets, buf: &mut [u8]) -> io::Result<()> {
+        if off.root >= buf.len() || off.scale_type >= buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Scale offsets out of bounds"));
+        }
+        buf[off.root] = self.root;
+        buf[off.scale_type] = self.scale_type;
+        Ok(())
+    }
+
+    /// Note name of the root, or `?(n)` if the byte is out of range.
+    pub fn root_name(&self) -> Cow<'static, str> {
+        name_or_raw(&ROOT_NAMES, self.root)
+    }
+
+    /// Scale name, or `?(n)` if the byte is out of range.
+    pub fn scale_type_name(&self) -> Cow<'static, str> {
+        name_or_raw(&SCALE_TYPE_NAMES, self.scale_type)
+    }
+}
+
+fn name_or_raw(names: &[&'static str], value: u8) -> Cow<'static, str> {
+    match names.get(value as usize) {
+        Some(name) => Cow::Borrowed(name),
+        None => Cow::Owned(format!("?({})", value)),
+    }
+}
+
+impl DrumData {
+    // PatternStats for every slot, indexed [track][pattern].
+    pub fn pattern_stats(&self) -> Vec<Vec<PatternStats>> {
+        self.tracks.iter().map(|t| t.patterns.iter().map(Pattern::stats).collect()).collect()
+    }
+
+    // Every step as (track, pattern, step index, step), in that nesting order.
+    pub fn steps(&self) -> Steps<'_> {
+        let left = self.tracks.iter().flat_map(|t| &t.patterns).map(|p| p.steps.len()).sum();
+        Steps { drums: self, track: 0, pattern: 0, step: 0, left }
+    }
+
+    // Counts taken from the data itself; a parsed DrumData is always rectangular.
+    pub fn layout(&self) -> Layout {
+        let patterns = self.tracks.first().map_or(0, |t| t.patterns.len());
+        let steps = self.tracks.first().and_then(|t| t.patterns.first()).map_or(0, |p| p.steps.len());
+        Layout { tracks: self.tracks.len(), patterns, steps }
+    }
+
+    fn check_slots(&self, slots: &[(&str, usize, usize)]) -> io::Result<()> {
+        let layout = self.layout();
+        for &(what, t, p) in slots {
+            if t >= layout.tracks || p >= layout.patterns {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                    "{} track {} pattern {} out of range (tracks 0..{}, patterns 0..{})",
+                    what, t, p, layout.tracks.saturating_sub(1), layout.patterns.saturating_sub(1))));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_bytes(data: &[u8], offsets: &Offsets) -> Result<Self, NcsError> {
+        Self::from_bytes_with(data, offsets, &Layout::default())
+    }
+
+    pub fn from_bytes_with(data: &[u8], offsets: &Offsets, layout: &Layout) -> Result<Self, NcsError> {
+        parse_printing_warnings(data, |r| Self::parse_with(r, offsets, layout))
+    }
+
+    pub fn parse(r: &ByteReader, offsets: &Offsets) -> Result<Self, NcsError> {
+        Self::parse_with(r, offsets, &Layout::default())
+    }
+
+    pub fn parse_with(r: &ByteReader, offsets: &Offsets, layout: &Layout) -> Result<Self, NcsError> {
+        let &Layout { tracks: track_count, patterns: pattern_count, steps: step_count } = layout;
+        layout.check_fits(r.len())?;
+        // Check every plane up front so a truncated file fails with the first step it cannot reach
+        for (base, name) in offsets.planes().into_iter().zip(PLANE_NAMES) {
+            for t in 0..track_count {
+                for p in 0..pattern_count {
+                    let row = checked_offset(&format!("{} plane", name), base, &[(t, offsets.track_stride), (p, offsets.pattern_stride)])?;
+                    if row.saturating_add(step_count) > r.len() {
+                        let s = r.len().saturating_sub(row).min(step_count.saturating_sub(1));
+                        return Err(NcsError::truncated(
+                            format!("{} plane at track {} pattern {} step {}", name, t, p, s), row.saturating_add(s + 1), r.len()));
+                    }
+                }
+            }
+        }
+
+        let mut tracks = Vec::with_capacity(track_count);
+        for t in 0..track_count {
+            let patterns = Self::parse_patterns(r, offsets, layout, t)?;
+            let (delay_send, reverb_send) = Self::parse_sends(r, offsets, t)?;
+            tracks.push(DrumTrack { patterns, delay_send, reverb_send });
+        }
+        let drums = DrumData { tracks };
+        drums.warn_param_ranges(r, offsets);
+        Ok(drums)
+    }
+
+    // Salvage a file cut short: every leading track whose plane rows are all inside the
+    // file, stopping at the first that is not. Sends past the end read as 0 with a
+    // warning. Compare `tracks.len()` with `layout.tracks` to see how much was recovered.
+    pub fn parse_partial(r: &ByteReader, offsets: &Offsets, layout: &Layout) -> Result<Self, NcsError> {
+        layout.check_fits(r.len())?;
+        let mut tracks = Vec::with_capacity(layout.tracks);
+        'tracks: for t in 0..layout.tracks {
+            for base in offsets.planes() {
+                for p in 0..layout.patterns {
+                    let row = checked_offset("plane row", base, &[(t, offsets.track_stride), (p, offsets.pattern_stride)])?;
+                    if row.saturating_add(layout.steps) > r.len() {
+                        break 'tracks;
+                    }
+                }
+            }
+            let patterns = Self::parse_patterns(r, offsets, layout, t)?;
+            let (delay_send, reverb_send) = match Self::parse_sends(r, offsets, t) {
+                Err(NcsError::Truncated { needed, .. }) => {
+                    r.warn(WarningCategory::Header, needed.saturating_sub(1), format!("Track {} sends are past the end of the file; using 0", t));
+                    (0, 0)
+                }
+                sends => sends?,
+            };
+            tracks.push(DrumTrack { patterns, delay_send, reverb_send });
+        }
+        let drums = DrumData { tracks };
+        drums.warn_param_ranges(r, offsets);
+        Ok(drums)
+    }
+
+    fn parse_patterns(r: &ByteReader, offsets: &Offsets, layout: &Layout, t: usize) -> Result<Vec<Pattern>, NcsError> {
+        let mut patterns = Vec::with_capacity(layout.patterns);
+
+        for p in 0..layout.patterns {
+            let mut steps = Vec::with_capacity(layout.steps);
+
+            for s in 0..layout.steps {
+                let idx = t * offsets.track_stride + p * offsets.pattern_stride + s;
+
+                let at = [('t', t), ('p', p), ('s', s)];
+                steps.push(Step {
+                    velocity: r.u8_at(offsets.velocity + idx, "velocity", &at)?,
+                    probability: r.u8_at(offsets.probability + idx, "probability", &at)?,
+                    choice: r.u8_at(offsets.choice + idx, "choice", &at)?,
+                    mask: r.u8_at(offsets.mask + idx, "mask", &at)?,
+                    pitch: r.u8_at(offsets.pitch + idx, "pitch", &at)?,
+                    decay: r.u8_at(offsets.decay + idx, "decay", &at)?,
+                    distortion: r.u8_at(offsets.distortion + idx, "distortion", &at)?,
+                    eq: r.u8_at(offsets.eq + idx, "eq", &at)?,
+                });
+            }
+
+            patterns.push(Pattern { steps });
+        }
+        Ok(patterns)
+    }
+
+    fn parse_sends(r: &ByteReader, offsets: &Offsets, t: usize) -> Result<(u8, u8), NcsError> {
+        let delay_off = checked_offset("delay_send", offsets.sends.delay_send, &[(t, offsets.sends.track_stride)])?;
+        let delay_send = r.u8_at(delay_off, "delay_send", &[('t', t)])?;
+        let reverb_off = checked_offset("reverb_send", offsets.sends.reverb_send, &[(t, offsets.sends.track_stride)])?;
+        let reverb_send = r.u8_at(reverb_off, "reverb_send", &[('t', t)])?;
+        if delay_send > MAX_SEND { r.warn(WarningCategory::Range, delay_off, format!("Track {} delay send out of range: {} (expected 0..{})", t, delay_send, MAX_SEND)); }
+        if reverb_send > MAX_SEND { r.warn(WarningCategory::Range, reverb_off, format!("Track {} reverb send out of range: {} (expected 0..{})", t, reverb_send, MAX_SEND)); }
+        Ok((delay_send, reverb_send))
+    }
+
+    // One summary warning per parameter plane rather than one per step, located
+    // at the first offending step
+    fn warn_param_ranges(&self, r: &ByteReader, offsets: &Offsets) {
+        for (plane, _) in PARAM_PLANES {
+            let mut bad = self.steps().filter(|(_, _, _, st)| !param_in_range(plane, st.planes()[plane]));
+            if let Some((t, p, s, _)) = bad.next() {
+                let offset = offsets.planes()[plane] + t * offsets.track_stride + p * offsets.pattern_stride + s;
+                let category = if PLANE_INFO[plane].validated { WarningCategory::Range } else { WarningCategory::Provisional };
+                let info = &PLANE_INFO[plane];
+                r.warn(category, offset, format!("{} {} values out of range (expected {}..{} or 0x{:02X} for unset)", bad.count() + 1, info.name, info.min, info.max, PARAM_UNSET));
+            }
+        }
+    }
+
+    // Deep copy of one pattern (every plane of every step) into another slot.
+    pub fn copy_pattern(&mut self, src_track: usize, src_pat: usize, dst_track: usize, dst_pat: usize) -> io::Result<()> {
+        self.check_slots(&[("Source", src_track, src_pat), ("Destination", dst_track, dst_pat)])?;
+        self.tracks[dst_track].patterns[dst_pat] = self.tracks[src_track].patterns[src_pat].clone();
+        Ok(())
+    }
+
+    // Overlay two patterns (see Pattern::overlay) and store the result in a third slot,
+    // which may be either source.
+    pub fn merge_patterns(&mut self, a: (usize, usize), b: (usize, usize), dst: (usize, usize)) -> io::Result<()> {
+        self.check_slots(&[("First source", a.0, a.1), ("Second source", b.0, b.1), ("Destination", dst.0, dst.1)])?;
+        let merged = self.tracks[a.0].patterns[a.1].overlay(&self.tracks[b.0].patterns[b.1]);
+        self.tracks[dst.0].patterns[dst.1] = merged;
+        Ok(())
+    }
+
+    // Non-panicking lookup; None when any coordinate is out of range.
+    pub fn get(&self, track: usize, pattern: usize, step: usize) -> Option<&Step> {
+        self.tracks.get(track)?.patterns.get(pattern)?.steps.get(step)
+    }
+
+    // Inverse of from_bytes: write every plane back at the offsets it was read from,
+    // for as many tracks, patterns and steps as the data holds.
+    pub fn to_bytes(&self, offsets: &Offsets, buf: &mut [u8]) -> io::Result<()> {
+        let layout = self.layout();
+        if layout.total_steps() > 0 {
+            let terms = [(layout.tracks - 1, offsets.track_stride), (layout.patterns - 1, offsets.pattern_stride), (layout.steps - 1, 1)];
+            for (base, name) in offsets.planes().into_iter().zip(PLANE_NAMES) {
+                if checked_offset(&format!("{} plane", name), base, &terms)? >= buf.len() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Drum plane offset out of bounds"));
+                }
+            }
+        }
+        let sends = &offsets.sends;
+        let last_send = checked_offset("track sends", sends.delay_send.max(sends.reverb_send), &[(layout.tracks.saturating_sub(1), sends.track_stride)])?;
+        if !self.tracks.is_empty() && last_send >= buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Track send offset out of bounds"));
+        }
+        for (t, track) in self.tracks.iter().enumerate() {
+            buf[sends.delay_send + t * sends.track_stride] = track.delay_send;
+            buf[sends.reverb_send + t * sends.track_stride] = track.reverb_send;
+        }
+        for (t, p, s, st) in self.steps() {
+            let idx = t * offsets.track_stride + p * offsets.pattern_stride + s;
+            buf[offsets.velocity + idx] = st.velocity;
+            buf[offsets.probability + idx] = st.probability;
+            buf[offsets.choice + idx] = st.choice;
+            buf[offsets.mask + idx] = st.mask;
+            buf[offsets.pitch + idx] = st.pitch;
+            buf[offsets.decay + idx] = st.decay;
+            buf[offsets.distortion + idx] = st.distortion;
+            buf[offsets.eq + idx] = st.eq;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Offsets {
+    pub velocity: usize,
+    pub probability: usize,
+    pub choice: usize,
+    pub mask: usize,
+    pub pitch: usize,
+    pub decay: usize,
+    pub distortion: usize,
+    pub eq: usize,
+    pub track_stride: usize,
+    pub pattern_stride: usize,
+    pub sends: SendOffsets,
+}
+
+// Per-track FX send levels. Provisional: not yet confirmed against the firmware,
+// chosen from a zeroed region just before the scale/FX globals.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SendOffsets {
+    pub delay_send: usize,
+    pub reverb_send: usize,
+    pub track_stride: usize,
+}
+
+impl Default for SendOffsets {
+    fn default() -> Self {
+        SendOffsets { delay_send: 0x26CFC, reverb_send: 0x26D00, track_stride: 1 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "crc32",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    // Bytes the stored value takes: a little-endian u32 for CRC-32, the raw digest for SHA-256.
+    pub fn width(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc32 => 4,
+            ChecksumAlgorithm::Sha256 => 32,
+        }
+    }
+}
+
+// A checksum the file stores over one of its own byte ranges. No known firmware
+// writes one, so only a profile's `[checksum]` section turns verification on. The
+// covered range should leave out the stored value itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumOffsets {
+    pub algorithm: ChecksumAlgorithm,
+    pub stored: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+// Every offset table for one firmware layout, loadable from TOML. Sections and
+// fields left out of a file keep the built-in defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OffsetProfile {
+    pub name: NameOffsets,
+    pub timing: TimingOffsets,
+    pub scenes: ScenesOffsets,
+    pub chains: ChainOffsets,
+    pub drums: Offsets,
+    pub layout: Layout,
+    pub scale: ScaleOffsets,
+    pub fx: FxOffsets,
+    pub globals: GlobalsOffsets,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<ChecksumOffsets>,
+}
+
+// Built-in profiles by name. `v1` is the layout of the bundled Circuit Tracks sessions.
+pub const BUILTIN_PROFILES: [&str; 1] = ["v1"];
+
+impl OffsetProfile {
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "v1" => Some(OffsetProfile::default()),
+            _ => None,
+        }
+    }
+
+    // A built-in profile name or a path to a TOML profile.
+    pub fn load(spec: &str) -> io::Result<Self> {
+        if let Some(profile) = Self::builtin(spec) {
+            return Ok(profile);
+        }
+        let text = std::fs::read_to_string(spec).map_err(|e| io::Error::new(e.kind(),
+            format!("{} is neither a built-in profile ({}) nor a readable file: {}", spec, BUILTIN_PROFILES.join(", "), e)))?;
+        if spec.to_ascii_lowercase().ends_with(".json") {
+            serde_json::from_str(&text).map_err(invalid_data)
+        } else {
+            toml::from_str(&text).map_err(invalid_data)
+        }
+    }
+
+    // One past the last byte each table touches, labelled like the config fields.
+    pub fn extents(&self) -> Vec<(String, usize)> {
+        let (d, l) = (&self.drums, &self.layout);
+        // Saturating so an absurd profile reports a huge extent instead of overflowing
+        let reach = |base: usize, terms: &[(usize, usize)], width: usize| {
+            checked_offset("", base, terms).map_or(usize::MAX, |end| end.saturating_add(width))
+        };
+        let last_track = l.tracks.saturating_sub(1);
+        let plane_terms = [(last_track, d.track_stride), (l.patterns.saturating_sub(1), d.pattern_stride)];
+        let mut out = vec![
+            ("name.base".to_string(), self.name.base.saturating_add(self.name.len)),
+            ("timing.tempo".to_string(), self.timing.tempo.saturating_add(1)),
+            ("timing.swing".to_string(), self.timing.swing.saturating_add(1)),
+            ("timing.swing_sync_rate".to_string(), self.timing.swing_sync_rate.saturating_add(1)),
+            ("timing.spare1".to_string(), self.timing.spare1.saturating_add(4)),
+            ("timing.spare2".to_string(), self.timing.spare2.saturating_add(4)),
+            ("scenes.base".to_string(), reach(self.scenes.base, &[(15, self.scenes.scene_stride), (7, self.scenes.entry_stride)], 4)),
+            ("chains.scene_chain_base".to_string(), self.chains.scene_chain_base.saturating_add(4)),
+            ("chains.pattern_chain_base".to_string(), reach(self.chains.pattern_chain_base, &[(7, self.chains.pattern_chain_stride)], 4)),
+            ("scale.root".to_string(), self.scale.root.saturating_add(1)),
+            ("scale.scale_type".to_string(), self.scale.scale_type.saturating_add(1)),
+            ("fx.delay_preset".to_string(), self.fx.delay_preset.saturating_add(1)),
+            ("fx.reverb_preset".to_string(), self.fx.reverb_preset.saturating_add(1)),
+            ("globals.master_volume".to_string(), self.globals.master_volume.saturating_add(1)),
+            ("drums.sends.delay_send".to_string(), reach(d.sends.delay_send, &[(last_track, d.sends.track_stride)], 1)),
+            ("drums.sends.reverb_send".to_string(), reach(d.sends.reverb_send, &[(last_track, d.sends.track_stride)], 1)),
+        ];
+        for (base, name) in d.planes().into_iter().zip(PLANE_NAMES) {
+            out.push((format!("drums.{}", name), reach(base, &plane_terms, l.steps)));
+        }
+        if let Some(c) = &self.checksum {
+            out.push(("checksum.stored".to_string(), c.stored.saturating_add(c.algorithm.width())));
+            out.push(("checksum.end".to_string(), c.end));
+        }
+        out
+    }
+
+    // Reject a profile that would read past the end of a `len`-byte file.
+    pub fn check_bounds(&self, len: usize) -> io::Result<()> {
+        match self.extents().into_iter().find(|(_, end)| *end > len) {
+            Some((field, end)) => Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Offset config: {} reaches 0x{:X}, past the end of the file (0x{:X} bytes)", field, end, len))),
+            None => Ok(()),
+        }
+    }
+
+    // Every offset as a dotted field path ("timing.tempo", "drums.sends.delay_send", ...).
+    pub fn fields(&self) -> io::Result<Vec<(String, i64)>> {
+        fn walk(prefix: &str, value: &toml::Value, out: &mut Vec<(String, i64)>) {
+            match value {
+                toml::Value::Table(table) => {
+                    for (key, v) in table {
+                        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                        walk(&path, v, out);
+                    }
+                }
+                toml::Value::Integer(n) => out.push((prefix.to_string(), *n)),
+                _ => {}
+            }
+        }
+        let mut out = Vec::new();
+        walk("", &toml::Value::try_from(self).map_err(invalid_data)?, &mut out);
+        Ok(out)
+    }
+}
+
+// Fields whose offset differs between two profiles: (field, a, b).
+pub fn profile_diff(a: &OffsetProfile, b: &OffsetProfile) -> io::Result<Vec<(String, i64, i64)>> {
+    let b_fields: std::collections::HashMap<String, i64> = b.fields()?.into_iter().collect();
+    Ok(a.fields()?.into_iter()
+        .filter_map(|(field, x)| match b_fields.get(&field) {
+            Some(&y) if y != x => Some((field, x, y)),
+            _ => None,
+        })
+        .collect())
+}
+
+impl Offsets {
+    // Plane base offsets in PLANE_NAMES order
+    pub fn planes(&self) -> [usize; 8] {
+        [self.velocity, self.probability, self.choice, self.mask, self.pitch, self.decay, self.distortion, self.eq]
+    }
+
+    // Strict layout check: every plane's steps must stay clear of the next
+    // plane, all planes must fit in one pattern stride, and the patterns in one
+    // track stride. Every plane is indexed with the same strides, so this is all
+    // it takes for no step of any track/pattern to bleed into another plane.
+    pub fn check_plane_layout(&self, layout: &Layout) -> io::Result<()> {
+        let &Layout { patterns: pattern_count, steps: step_count, .. } = layout;
+        let bad = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        let mut planes: Vec<(usize, &str)> = self.planes().into_iter().zip(PLANE_NAMES).collect();
+        planes.sort();
+        for w in planes.windows(2) {
+            let ((a, name_a), (b, name_b)) = (w[0], w[1]);
+            if b - a < step_count {
+                return bad(format!("Plane {} at 0x{:05X} overlaps plane {} at 0x{:05X} ({} steps need 0x{:X} bytes)",
+                                   name_a, a, name_b, b, step_count, step_count));
+            }
+        }
+        // Sorted, so the difference cannot underflow; saturating so absurd counts are rejected, not wrapped
+        let span = (planes[planes.len() - 1].0 - planes[0].0).saturating_add(step_count);
+        if span > self.pattern_stride {
+            return bad(format!("Planes span 0x{:X} bytes, more than the pattern stride 0x{:X}", span, self.pattern_stride));
+        }
+        if pattern_count.saturating_mul(self.pattern_stride) > self.track_stride {
+            return bad(format!("{} patterns of stride 0x{:X} do not fit in the track stride 0x{:X}",
+                               pattern_count, self.pattern_stride, self.track_stride));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Offsets {
+    // Example offsets, adjust for your NCS layout
+    fn default() -> Self {
+        Offsets {
+            velocity: 0x0CD74,
+            probability: 0x0CD94,
+            choice: 0x0CDB4,
+            mask: 0x0CDD4,
+            pitch: 0x0CDF4,
+            decay: 0x0CE14,
+            distortion: 0x0CE34,
+            eq: 0x0CE54,
+            track_stride: 0x3540,
+            pattern_stride: 0x06A8,
+            sends: SendOffsets::default(),
+        }
+    }
+}
+
+// Everything the parsers understand about one file, read with one offset profile.
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub name: SessionName,
+    pub timing: Timing,
+    pub scale: ScaleSettings,
+    pub fx: Fx,
+    pub globals: Globals,
+    pub scenes: Scenes,
+    pub scene_chain: SceneChain,
+    pub pattern_chains: PatternChains,
+    pub drums: DrumData,
+}
+
+// Every session file starts with this tag, followed by its total length as a u32 LE.
+pub const MAGIC: &[u8; 4] = b"USER";
+pub const HEADER_LENGTH_OFFSET: usize = 4;
+
+// Reject buffers that cannot be a session before any field is parsed: the magic
+// must match and the file must reach every offset of `profile`. A file shorter than
+// its own header length is truncated; a complete file the profile overruns means the
+// offset config is wrong, and the error says so.
+pub fn validate_header(data: &[u8], profile: &OffsetProfile) -> Result<(), NcsError> {
+    check_magic(data)?;
+    let Some((field, needed)) = profile.extents().into_iter().filter(|&(_, end)| end > data.len()).max_by_key(|&(_, end)| end) else {
+        return Ok(());
+    };
+    let declared = data.get(HEADER_LENGTH_OFFSET..HEADER_LENGTH_OFFSET + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    if declared.is_some_and(|declared| data.len() >= declared) {
+        profile.check_bounds(data.len())?;
+    }
+    Err(NcsError::truncated(field, needed, data.len()))
+}
+
+// Only the magic, for callers that accept files shorter than the full layout.
+pub fn check_magic(data: &[u8]) -> Result<(), NcsError> {
+    if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+        let found = &data[..data.len().min(MAGIC.len())];
+        return Err(NcsError::BadMagic { found: String::from_utf8_lossy(found).into_owned() });
+    }
+    Ok(())
+}
+
+// The length stored in the header should match the file; a mismatch is only suspicious.
+pub fn check_header_length(r: &ByteReader) -> Result<u32, NcsError> {
+    let declared = r.u32_le(HEADER_LENGTH_OFFSET, "header.length")?;
+    if declared as usize != r.len() {
+        r.warn(WarningCategory::Header, HEADER_LENGTH_OFFSET, format!("Header length {} does not match the file size {}", declared, r.len()));
+    }
+    Ok(declared)
+}
+
+// What files of each built-in layout look like, for guessing the layout of a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatVersion {
+    pub name: &'static str,
+    pub length: usize,
+}
+
+pub const FORMAT_VERSIONS: [FormatVersion; 1] = [FormatVersion { name: "v1", length: 160780 }];
+
+// Versions a file could be: the magic and length match and tempo and scale read sane
+// values at that version's offsets. Anything but exactly one candidate is ambiguous.
+pub fn detect_versions(data: &[u8]) -> Vec<&'static str> {
+    FORMAT_VERSIONS.iter().filter(|v| {
+        let Some(profile) = OffsetProfile::builtin(v.name) else { return false };
+        let at = |idx: usize| data.get(idx).copied();
+        data.len() == v.length
+            && validate_header(data, &profile).is_ok()
+            && at(profile.timing.tempo).is_some_and(|t| (40..=240).contains(&t))
+            && at(profile.scale.root).is_some_and(|r| r < 12)
+            && at(profile.scale.scale_type).is_some_and(|t| t < 16)
+    }).map(|v| v.name).collect()
+}
+
+impl Session {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, NcsError> {
+        Self::from_bytes_with(data, &OffsetProfile::default())
+    }
+
+    pub fn from_bytes_with(data: &[u8], profile: &OffsetProfile) -> Result<Self, NcsError> {
+        let (session, warnings) = Self::from_bytes_checked(data, profile)?;
+        print_warnings(&warnings);
+        Ok(session)
+    }
+
+    // Read one session from a stream: the header, then as many bytes as it declares (at
+    // least every byte the profile reads), leaving anything after that unread.
+    pub fn from_reader<R: Read>(r: &mut R, profile: &OffsetProfile) -> Result<Self, NcsError> {
+        let header_len = HEADER_LENGTH_OFFSET + 4;
+        let mut data = Vec::with_capacity(header_len);
+        r.by_ref().take(header_len as u64).read_to_end(&mut data)?;
+        check_magic(&data)?;
+        if data.len() < header_len {
+            return Err(NcsError::truncated("header.length", header_len, data.len()));
+        }
+        let declared = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let needed = profile.extents().into_iter().map(|(_, end)| end).max().unwrap_or_default().max(declared);
+        r.take(needed.saturating_sub(header_len) as u64).read_to_end(&mut data)?;
+        Self::from_bytes_with(&data, profile)
+    }
+
+    // Parse without printing anything; the warnings come back with the session.
+    pub fn from_bytes_checked(data: &[u8], profile: &OffsetProfile) -> Result<(Self, Vec<ValidationWarning>), NcsError> {
+        validate_header(data, profile)?;
+        let r = ByteReader::new(data);
+        let session = Self::parse(&r, profile)?;
+        Ok((session, r.take_warnings()))
+    }
+
+    // Every block through one reader, so a tracing reader sees each field the session reads.
+    pub fn parse(r: &ByteReader, profile: &OffsetProfile) -> Result<Self, NcsError> {
+        check_header_length(r)?;
+        Ok(Session {
+            name: SessionName::parse(r, &profile.name)?,
+            timing: Timing::parse(r, &profile.timing)?,
+            scale: ScaleSettings::parse(r, &profile.scale)?,
+            fx: Fx::parse(r, &profile.fx)?,
+            globals: Globals::parse(r, &profile.globals)?,
+            scenes: Scenes::parse(r, &profile.scenes)?,
+            scene_chain: SceneChain::parse(r, &profile.chains)?,
+            pattern_chains: PatternChains::parse(r, &profile.chains)?,
+            drums: DrumData::parse_with(r, &profile.drums, &profile.layout)?,
+        })
+    }
+
+    pub fn to_bytes(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.to_bytes_with(&OffsetProfile::default(), buf)
+    }
+
+    // Write every parsed field back at the profile's offsets. `buf` is normally the
+    // original file, so bytes the parsers do not understand are carried through.
+    pub fn to_bytes_with(&self, profile: &OffsetProfile, buf: &mut [u8]) -> io::Result<()> {
+        self.name.to_bytes(&profile.name, buf)?;
+        self.timing.to_bytes(&profile.timing, buf)?;
+        self.scale.to_bytes(&profile.scale, buf)?;
+        self.fx.to_bytes(&profile.fx, buf)?;
+        self.globals.to_bytes(&profile.globals, buf)?;
+        self.scenes.to_bytes(&profile.scenes, buf)?;
+        self.scene_chain.to_bytes(&profile.chains, buf)?;
+        self.pattern_chains.to_bytes(&profile.chains, buf)?;
+        self.drums.to_bytes(&profile.drums, buf)
+    }
+}
@@ -0,0 +1,42 @@
+// Diagnostics go through the `log` facade to stderr as `[level] message`, so batch runs
+// can silence them; normal output stays on stdout. Verbosity comes from -v/-q, or from
+// RUST_LOG (env_logger syntax, so `RUST_LOG=ncs_tui=debug` works) when neither is given.
+
+use std::io::Write;
+
+use env_logger::{Builder, Target};
+use log::LevelFilter;
+
+// Target of the `--trace` lines, so asking for them doesn't turn on every other debug line
+pub const TRACE_TARGET: &str = "ncs_tui::trace";
+
+// Warnings by default; each -v adds a level, -q keeps only errors. None leaves it to RUST_LOG.
+pub fn level(verbose: u8, quiet: bool) -> Option<LevelFilter> {
+    match (quiet, verbose) {
+        (true, _) => Some(LevelFilter::Error),
+        (false, 0) => None,
+        (false, 1) => Some(LevelFilter::Info),
+        (false, 2) => Some(LevelFilter::Debug),
+        (false, _) => Some(LevelFilter::Trace),
+    }
+}
+
+pub fn builder(verbose: u8, quiet: bool, trace: bool, rust_log: Option<&str>) -> Builder {
+    let mut builder = Builder::new();
+    builder.filter_level(LevelFilter::Warn);
+    match level(verbose, quiet) {
+        Some(level) => { builder.filter_level(level); }
+        None => if let Some(spec) = rust_log { builder.parse_filters(spec); },
+    }
+    // Asking for the trace means wanting to see it, whatever the verbosity
+    if trace { builder.filter_module(TRACE_TARGET, LevelFilter::Debug); }
+    builder
+        .target(Target::Stderr)
+        .format(|buf, record| writeln!(buf, "[{}] {}", record.level().as_str().to_ascii_lowercase(), record.args()));
+    builder
+}
+
+pub fn init(mut builder: Builder) {
+    // Only fails when a logger is already set, which leaves that one in charge
+    let _ = builder.try_init();
+}
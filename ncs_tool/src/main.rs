@@ -1,508 +1,5427 @@
+// Command-line front end over the ncs_tui library.
+#![forbid(unsafe_code)]
+
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, IsTerminal, Read};
 
-const TRACKS: usize = 4;
-const PATTERNS: usize = 8;
-const STEPS: usize = 32;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use log::{debug, info, warn};
+use serde::Deserialize;
 
-#[derive(Debug, Clone)]
-struct Step {
-    velocity: u8,
-    probability: u8,
-    choice: u8,
-    mask: u8,
-    pitch: u8,
-    decay: u8,
-    distortion: u8,
-    eq: u8,
+use ncs_tui::*;
+
+mod digest;
+mod fragment;
+mod gzip;
+mod journal;
+mod logging;
+mod midi;
+mod preview;
+mod proto;
+mod tap;
+
+fn render_plane_list(offsets: &Offsets) -> String {
+    let mut out = format!("{:<12} {:>8} {:>9} {:>8}  {}\n", "plane", "offset", "range", "signed", "status");
+    for (info, base) in PLANE_INFO.iter().zip(offsets.planes()) {
+        out.push_str(&format!("{:<12} {:>8} {:>9} {:>8}  {}\n",
+            info.name,
+            format!("0x{:04X}", base),
+            format!("{}..{}", info.min, info.max),
+            if info.signed { "yes" } else { "no" },
+            if info.validated { "validated" } else { "unverified" }));
+    }
+    out
 }
 
-#[derive(Debug, Clone)]
-struct Pattern {
-    steps: [Step; STEPS],
+// Role a candidate plane slot plays, inferred from how its values line up with velocity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PlaneRole {
+    Velocity,
+    // Nearly constant with a small value in the probability digit range
+    ProbabilityLike,
+    // Leaves its rest value exactly on the steps where velocity is nonzero
+    Step,
+    // Varies without following velocity
+    Parameter,
+    // Nearly constant outside the probability range (e.g. 0xFF defaults)
+    Filler,
 }
 
 #[derive(Debug, Clone)]
-struct DrumTrack {
-    patterns: [Pattern; PATTERNS],
+struct PlaneSlotGuess {
+    slot: usize,
+    offset: usize,
+    role: PlaneRole,
+    agreement: f64,
 }
 
-#[derive(Debug, Clone)]
-struct DrumData {
-    tracks: [DrumTrack; TRACKS],
+// Fraction of steps a locator lets contradict its heuristic before it rejects a
+// candidate: mismatches against velocity for detect-plane-order, non-zero rests for
+// planes-window. Raise it for noisy dumps, lower it to only accept clean fits.
+const DEFAULT_TOLERANCE: f64 = 0.05;
 
+// Treat the step-sized slots after `off.velocity` as candidate planes and guess each
+// slot's role. Returned in the inferred order: velocity, probability-like, step
+// planes, parameters, fillers (slot order within a role). A slot counts as a step
+// plane or as constant only when at most `tolerance` of its steps disagree.
+fn detect_plane_order(data: &[u8], off: &Offsets, layout: &Layout, tolerance: f64) -> io::Result<Vec<PlaneSlotGuess>> {
+    let steps = layout.steps;
+    let mut blocks = Vec::new();
+    for t in 0..layout.tracks {
+        for p in 0..layout.patterns {
+            let at = |k: usize| [(t, off.track_stride), (p, off.pattern_stride), (k, steps)];
+            let block: Vec<&[u8]> = (0..8).map(|k| plane_row(data, off.velocity, &at(k), steps, "plane slot")).collect::<io::Result<_>>()?;
+            blocks.push(block);
+        }
+    }
+    let total = (blocks.len() * steps) as f64;
+    let mut guesses: Vec<PlaneSlotGuess> = (0..8).map(|k| {
+        let mut all = [0usize; 256];
+        let mut rest = [0usize; 256];
+        for b in &blocks {
+            for (&v, &vel) in b[k].iter().zip(b[0]) {
+                all[v as usize] += 1;
+                if vel == 0 { rest[v as usize] += 1; }
+            }
+        }
+        let mode = |h: &[usize; 256]| (0..256).max_by_key(|&v| h[v]).unwrap_or(0) as u8;
+        let (rest_mode, all_mode) = (mode(&rest), mode(&all));
+        let (mut agree, mut active, mut active_set) = (0usize, 0usize, 0usize);
+        for b in &blocks {
+            for (&v, &vel) in b[k].iter().zip(b[0]) {
+                let set = v != rest_mode;
+                let hit = vel > 0;
+                if set == hit { agree += 1; }
+                if hit { active += 1; if set { active_set += 1; } }
+            }
+        }
+        let agreement = agree as f64 / total;
+        let mostly_constant = all[all_mode as usize] as f64 / total >= 1.0 - tolerance;
+        let role = if k == 0 {
+            PlaneRole::Velocity
+        } else if active > 0 && active_set * 2 >= active && agreement >= 1.0 - tolerance {
+            PlaneRole::Step
+        } else if mostly_constant && (1..=PLANE_INFO[1].max).contains(&(all_mode as i16)) {
+            PlaneRole::ProbabilityLike
+        } else if mostly_constant {
+            PlaneRole::Filler
+        } else {
+            PlaneRole::Parameter
+        };
+        PlaneSlotGuess { slot: k, offset: off.velocity.saturating_add(k * steps), role, agreement }
+    }).collect();
+    guesses.sort_by_key(|g| (g.role, g.slot));
+    Ok(guesses)
 }
 
+fn render_plane_order(guesses: &[PlaneSlotGuess]) -> String {
+    let mut out = format!("{:<6} {:>8} {:<16} {:>9}\n", "slot", "offset", "role", "agreement");
+    for g in guesses {
+        out.push_str(&format!("{:<6} {:>8} {:<16} {:>9.3}\n", g.slot, format!("0x{:04X}", g.offset), format!("{:?}", g.role), g.agreement));
+    }
+    let order: Vec<String> = guesses.iter().map(|g| g.slot.to_string()).collect();
+    out.push_str(&format!("Inferred order: {}\n", order.join(", ")));
+    out
+}
 
+// Largest |value| still counted as a micro-timing nudge.
+const NUDGE_RANGE: i32 = 24;
 
+// Score each candidate plane offset in `from..to` (per track/pattern layout of `off`)
+// by how much it looks like a signed micro-timing plane: small nudges around zero on
+// active steps, zero on rests. 1.0 is a perfect fit. Candidates with more than
+// `tolerance` of their rests non-zero are dropped. Sorted best first.
+fn micro_timing_scores(data: &[u8], off: &Offsets, layout: &Layout, from: usize, to: usize, tolerance: f64) -> io::Result<Vec<(usize, f64)>> {
+    let slots: Vec<Vec<(usize, usize)>> = (0..layout.tracks)
+        .flat_map(|t| (0..layout.patterns).map(move |p| vec![(t, off.track_stride), (p, off.pattern_stride)]))
+        .collect();
+    let velocity = slots.iter().map(|at| plane_row(data, off.velocity, at, layout.steps, "velocity")).collect::<io::Result<Vec<_>>>()?;
+    let mut scores = Vec::new();
+    for cand in from..to {
+        let (mut active, mut small, mut nonzero, mut sum, mut abs_sum) = (0usize, 0usize, 0usize, 0i64, 0i64);
+        let (mut rests, mut rest_zero) = (0usize, 0usize);
+        for (at, vel) in slots.iter().zip(&velocity) {
+            let values = plane_row(data, cand, at, layout.steps, "candidate")?;
+            for (s, &raw) in values.iter().enumerate() {
+                let v = raw as i8 as i32;
+                if vel[s] == 0 {
+                    rests += 1;
+                    if v == 0 { rest_zero += 1; }
+                    continue;
+                }
+                active += 1;
+                if v.abs() <= NUDGE_RANGE { small += 1; }
+                if v != 0 { nonzero += 1; }
+                sum += v as i64;
+                abs_sum += v.abs() as i64;
+            }
+        }
+        if active == 0 { continue; }
+        let centered = if abs_sum > 0 { 1.0 - (sum.abs() as f64 / abs_sum as f64) } else { 0.0 };
+        let rest_clean = if rests > 0 { rest_zero as f64 / rests as f64 } else { 1.0 };
+        if 1.0 - rest_clean > tolerance { continue; }
+        let score = small as f64 / active as f64 * nonzero as f64 / active as f64 * centered * rest_clean;
+        scores.push((cand, score));
+    }
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    Ok(scores)
+}
 
+// The `len`-byte row at `base` plus the (index, stride) terms, or an error when the
+// offset overflows or the row runs past the end of `data`.
+fn plane_row<'a>(data: &'a [u8], base: usize, at: &[(usize, usize)], len: usize, field: &str) -> io::Result<&'a [u8]> {
+    let start = checked_offset(field, base, at)?;
+    start.checked_add(len).and_then(|end| data.get(start..end)).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, format!(
+        "{} row at 0x{:X} runs past the end of the file (0x{:X} bytes)", field, start, data.len())))
+}
 
+// Accept offsets as 0x-prefixed hex or decimal on the command line.
+fn parse_offset(s: &str) -> Result<usize, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }.map_err(|e| format!("invalid offset '{}': {}", s, e))
+}
 
-
-fn render_ascii_bool(steps: &[bool]) -> String {
-    let mut out = String::new();
-    for (i, on) in steps.iter().enumerate() {
-        if i > 0 {
-            if i % 8 == 0 { out.push('\n'); } else { out.push(' '); }
-        }
-        out.push_str(if *on { "█" } else { "." });
-    }
-    out
+fn parse_tolerance(s: &str) -> Result<f64, String> {
+    let t: f64 = s.parse().map_err(|e| format!("invalid tolerance '{}': {}", s, e))?;
+    if (0.0..=1.0).contains(&t) { Ok(t) } else { Err(format!("tolerance {} is outside 0..1", t)) }
 }
 
 
 
-#[derive(Debug, Clone)]
-struct Fx {
-    delay_preset: u8,  // 0..15
-    reverb_preset: u8, // 0..7
+// One field that differs between two sessions.
+#[derive(Debug, Clone, PartialEq)]
+struct FieldDiff {
+    field: String,
+    before: String,
+    after: String,
 }
 
-#[derive(Debug, Clone)]
-struct FxOffsets {
-    delay_preset: usize,
-    reverb_preset: usize,
+impl std::fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}→{}", self.field, self.before, self.after)
+    }
 }
 
-impl Fx {
-    fn from_bytes(data: &[u8], off: &FxOffsets) -> io::Result<Self> {
-        if off.delay_preset >= data.len() || off.reverb_preset >= data.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "FX offset out of bounds"));
-        }
-        let fx = Fx {
-            delay_preset: data[off.delay_preset],
-            reverb_preset: data[off.reverb_preset],
-        };
-        // Validate ranges based on validate_fx_presets() from decompiled code
-        if fx.delay_preset >= 0x10 {
-            eprintln!("[warn] Session's delay preset index is out of range: {} (expected 0..15)", fx.delay_preset);
+// Field-level comparison of two raw files: their lengths, then every parsed field.
+// Each file is parsed with the profile `profile` picks for it.
+fn diff_files(a: &[u8], b: &[u8], profile: &ProfileArgs) -> io::Result<Vec<FieldDiff>> {
+    let mut out = Vec::new();
+    if a.len() != b.len() {
+        out.push(FieldDiff { field: "file length".into(), before: a.len().to_string(), after: b.len().to_string() });
+    }
+    out.extend(diff_sessions(&profile.session(a)?, &profile.session(b)?));
+    Ok(out)
+}
+
+// Field-level comparison of two parsed sessions.
+fn diff_sessions(a: &Session, b: &Session) -> Vec<FieldDiff> {
+    let mut out = Vec::new();
+    if a.name != b.name {
+        out.push(FieldDiff { field: "name".into(), before: a.name.name.clone(), after: b.name.name.clone() });
+    }
+    let (la, lb) = (a.drums.layout(), b.drums.layout());
+    if la != lb {
+        let shape = |l: Layout| format!("{}x{}x{}", l.tracks, l.patterns, l.steps);
+        out.push(FieldDiff { field: "drum layout (tracks x patterns x steps)".into(), before: shape(la), after: shape(lb) });
+    }
+    let mut cmp = |field: String, x: u32, y: u32| {
+        if x != y { out.push(FieldDiff { field, before: x.to_string(), after: y.to_string() }); }
+    };
+    cmp("tempo".into(), a.timing.tempo as u32, b.timing.tempo as u32);
+    cmp("swing".into(), a.timing.swing as u32, b.timing.swing as u32);
+    cmp("swing_sync_rate".into(), a.timing.swing_sync_rate as u32, b.timing.swing_sync_rate as u32);
+    cmp("spare1".into(), a.timing.spare1, b.timing.spare1);
+    cmp("spare2".into(), a.timing.spare2, b.timing.spare2);
+    cmp("scale root".into(), a.scale.root as u32, b.scale.root as u32);
+    cmp("scale type".into(), a.scale.scale_type as u32, b.scale.scale_type as u32);
+    cmp("delay preset".into(), a.fx.delay_preset as u32, b.fx.delay_preset as u32);
+    cmp("reverb preset".into(), a.fx.reverb_preset as u32, b.fx.reverb_preset as u32);
+    cmp("master volume".into(), a.globals.master_volume as u32, b.globals.master_volume as u32);
+    for si in 0..16 {
+        for ei in 0..8 {
+            let (x, y) = (a.scenes.scenes[si].entries[ei], b.scenes.scenes[si].entries[ei]);
+            cmp(format!("Scene {} entry {} start", si, ei), x.start as u32, y.start as u32);
+            cmp(format!("Scene {} entry {} end", si, ei), x.end as u32, y.end as u32);
         }
-        if fx.reverb_preset >= 0x08 {
-            eprintln!("[warn] Session's reverb preset index is out of range: {} (expected 0..7)", fx.reverb_preset);
+    }
+    cmp("scene chain start".into(), a.scene_chain.start_scene as u32, b.scene_chain.start_scene as u32);
+    cmp("scene chain end".into(), a.scene_chain.end_scene as u32, b.scene_chain.end_scene as u32);
+    for i in 0..8 {
+        let (x, y) = (a.pattern_chains.entries[i], b.pattern_chains.entries[i]);
+        cmp(format!("Pattern chain {} start", i), x.start as u32, y.start as u32);
+        cmp(format!("Pattern chain {} end", i), x.end as u32, y.end as u32);
+    }
+    // Only the slots both layouts have are compared step by step
+    for (t, (x, y)) in a.drums.tracks.iter().zip(&b.drums.tracks).enumerate() {
+        cmp(format!("Track {} delay send", t), x.delay_send as u32, y.delay_send as u32);
+        cmp(format!("Track {} reverb send", t), x.reverb_send as u32, y.reverb_send as u32);
+        for (p, (pa, pb)) in x.patterns.iter().zip(&y.patterns).enumerate() {
+            for (s, (sa, sb)) in pa.steps.iter().zip(&pb.steps).enumerate() {
+                let (sa, sb) = (sa.planes(), sb.planes());
+                for (plane, name) in PLANE_NAMES.iter().enumerate() {
+                    cmp(format!("Track {} Pattern {} Step {}: {}", t, p, s, name), sa[plane] as u32, sb[plane] as u32);
+                }
+            }
         }
-        Ok(fx)
     }
+    out
 }
 
-
-#[derive(Debug, Clone)]
-struct Timing {
-    tempo: u8,           // 40..240 BPM (inclusive)
-    swing: u8,           // 20..80 (inclusive)
-    swing_sync_rate: u8, // 0..7
-    spare1: u32,         // must be 0
-    spare2: u32,         // must be 0
+// Outcome of pairing two directories of sessions by file name.
+#[derive(Debug, Default)]
+struct DirDiff {
+    changed: Vec<(String, usize)>,
+    unchanged: Vec<String>,
+    only_a: Vec<String>,
+    only_b: Vec<String>,
+    errors: Vec<(String, String)>,
 }
 
-#[derive(Debug, Clone, Copy)]
-struct TimingOffsets {
-    tempo: usize,           // +0x34
-    swing: usize,           // +0x35
-    swing_sync_rate: usize, // +0x36
-    spare1: usize,          // +0x38 (u32 LE)
-    spare2: usize,          // +0x3C (u32 LE)
+fn ncs_file_names(dir: &std::path::Path) -> io::Result<std::collections::BTreeSet<String>> {
+    let mut names = std::collections::BTreeSet::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e.eq_ignore_ascii_case("ncs")) {
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) { names.insert(name.to_string()); }
+        }
+    }
+    Ok(names)
 }
 
-impl Timing {
-    fn from_bytes(data: &[u8], off: &TimingOffsets) -> io::Result<Self> {
-        // Bounds checks
-        for &idx in [off.tempo, off.swing, off.swing_sync_rate].iter() {
-            if idx >= data.len() {
-                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Timing byte offset out of bounds"));
+fn diff_dirs(a: &std::path::Path, b: &std::path::Path, profile: &ProfileArgs) -> io::Result<DirDiff> {
+    let (names_a, names_b) = (ncs_file_names(a)?, ncs_file_names(b)?);
+    let mut out = DirDiff::default();
+    for name in names_a.union(&names_b) {
+        match (names_a.contains(name), names_b.contains(name)) {
+            (true, false) => out.only_a.push(name.clone()),
+            (false, true) => out.only_b.push(name.clone()),
+            _ => {
+                let pair = (|| -> io::Result<usize> {
+                    Ok(diff_files(&std::fs::read(a.join(name))?, &std::fs::read(b.join(name))?, profile)?.len())
+                })();
+                match pair {
+                    Ok(0) => out.unchanged.push(name.clone()),
+                    Ok(n) => out.changed.push((name.clone(), n)),
+                    Err(e) => out.errors.push((name.clone(), e.to_string())),
+                }
             }
         }
-        if off.spare1 + 4 > data.len() || off.spare2 + 4 > data.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Timing dword offset out of bounds"));
-        }
-        let tempo = data[off.tempo];
-        let swing = data[off.swing];
-        let swing_sync_rate = data[off.swing_sync_rate];
-        let spare1 = u32::from_le_bytes([data[off.spare1], data[off.spare1 + 1], data[off.spare1 + 2], data[off.spare1 + 3]]);
-        let spare2 = u32::from_le_bytes([data[off.spare2], data[off.spare2 + 1], data[off.spare2 + 2], data[off.spare2 + 3]]);
-        // Mirror firmware range checks
-        if !(40..=240).contains(&tempo) {
-            eprintln!("[warn] Tempo out of range: {} (expected 40..240)", tempo);
-        }
-        if !(20..=80).contains(&swing) {
-            eprintln!("[warn] Swing out of range: {} (expected 20..80)", swing);
-        }
-        if swing_sync_rate >= 8 {
-            eprintln!("[warn] Swing sync rate out of range: {} (expected 0..7)", swing_sync_rate);
-        }
-        if spare1 != 0 {
-            eprintln!("[warn] Session timing spare1 not set to zero: {}", spare1);
-        }
-        if spare2 != 0 {
-            eprintln!("[warn] Session timing spare2 not set to zero: {}", spare2);
-        }
-        Ok(Timing { tempo, swing, swing_sync_rate, spare1, spare2 })
     }
+    Ok(out)
 }
 
-#[derive(Debug, Clone, Copy)]
-struct SceneEntry { start: u8, end: u8, pad: u16 }
-
-#[derive(Debug, Clone)]
-struct Scene { entries: [SceneEntry; 8] }
-
-#[derive(Debug, Clone)]
-struct Scenes { scenes: [Scene; 16] }
+// A single-byte field that `validate` checks. Out-of-range values are clamped into
+// `range`, or reset to `fallback` for index-like fields where clamping is meaningless.
+struct ByteRule {
+    field: String,
+    offset: usize,
+    range: std::ops::RangeInclusive<u8>,
+    fallback: Option<u8>,
+}
 
-#[derive(Debug, Clone, Copy)]
-struct ScenesOffsets {
-    base: usize,          // 0x40
-    scene_stride: usize,  // 0x28
-    entry_stride: usize,  // 4
+// Only fields with confirmed offsets: the sends and master volume offsets are still
+// provisional, so `--fix` must not rewrite whatever those bytes really hold.
+fn byte_rules(profile: &OffsetProfile) -> Vec<ByteRule> {
+    let (timing, fx, scale) = (&profile.timing, &profile.fx, &profile.scale);
+    let rule = |field: &str, offset, range, fallback| ByteRule { field: field.to_string(), offset, range, fallback };
+    vec![
+        rule("tempo", timing.tempo, 40..=240, None),
+        rule("swing", timing.swing, 20..=80, None),
+        rule("swing_sync_rate", timing.swing_sync_rate, 0..=7, Some(0)),
+        rule("delay preset", fx.delay_preset, 0..=15, Some(0)),
+        rule("reverb preset", fx.reverb_preset, 0..=7, Some(0)),
+        rule("scale root", scale.root, 0..=11, Some(0)),
+        rule("scale type", scale.scale_type, 0..=15, Some(0)),
+    ]
 }
 
-impl Scenes {
-    fn from_bytes(data: &[u8], off: &ScenesOffsets) -> io::Result<Self> {
-        let mut scenes: [Scene; 16] = unsafe { std::mem::zeroed() };
-        for si in 0..16 {
-            let mut entries: [SceneEntry; 8] = unsafe { std::mem::zeroed() };
-            for ei in 0..8 {
-                let idx = off.base + si * off.scene_stride + ei * off.entry_stride;
-                if idx + 4 > data.len() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Scenes offset out of bounds")); }
-                let start = data[idx];
-                let end = data[idx + 1];
-                let pad = u16::from_le_bytes([data[idx + 2], data[idx + 3]]);
-                // Mirror key firmware checks
-                if start >= 8 { eprintln!("[warn] Scene {} entry {} start out of range: {}", si, ei, start); }
-                if end >= 8 { eprintln!("[warn] Scene {} entry {} end out of range: {}", si, ei, end); }
-                if end < start { eprintln!("[warn] Scene {} entry {} end < start ({} < {})", si, ei, end, start); }
-                if pad != 0 { eprintln!("[warn] Scene {} entry {} padding not zero: {}", si, ei, pad); }
-                entries[ei] = SceneEntry { start, end, pad };
-            }
-            scenes[si] = Scene { entries };
-        }
-        Ok(Scenes { scenes })
+// Repair every illegal byte field in place and return what was changed.
+fn repair_session(data: &mut [u8], profile: &OffsetProfile) -> io::Result<Vec<FieldDiff>> {
+    let mut fixes = Vec::new();
+    for rule in byte_rules(profile) {
+        let b = data.get_mut(rule.offset)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} offset out of bounds", rule.field)))?;
+        if rule.range.contains(b) { continue; }
+        let fixed = rule.fallback.unwrap_or_else(|| (*b).clamp(*rule.range.start(), *rule.range.end()));
+        fixes.push(FieldDiff { field: rule.field, before: b.to_string(), after: fixed.to_string() });
+        *b = fixed;
     }
+    Ok(fixes)
 }
 
-#[derive(Debug, Clone, Copy)]
-struct SceneChain { start_scene: u8, end_scene: u8, pad: u16 }
-
-#[derive(Debug, Clone, Copy)]
-struct PatternChainEntry { start: u8, end: u8, pad: u16 }
+// Replace `path` with `data` without ever leaving a half-written session behind:
+// write and fsync a sibling temp file, keep the original as `<path>.bak`, then rename.
+fn replace_atomically(path: &std::path::Path, data: &[u8]) -> io::Result<std::path::PathBuf> {
+    use std::io::Write;
+    let with_suffix = |suffix: &str| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        std::path::PathBuf::from(name)
+    };
+    let (tmp, bak) = (with_suffix(".tmp"), with_suffix(".bak"));
+    let replaced = (|| {
+        let mut f = File::create(&tmp)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+        std::fs::copy(path, &bak)?;
+        std::fs::rename(&tmp, path)
+    })();
+    // Don't leave the temp file behind when any step fails; the original is untouched
+    if let Err(e) = replaced {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    // Persist the rename itself; directories cannot be opened for syncing on every platform
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        if let Ok(d) = File::open(dir) { let _ = d.sync_all(); }
+    }
+    Ok(bak)
+}
 
-#[derive(Debug, Clone)]
-struct PatternChains { entries: [PatternChainEntry; 8] }
+// Validate and, with `fix`, repair the session in place. Returns the fixes that were
+// (or, without `fix`, would be) applied. Gzip files are only validated: writing the
+// repaired bytes back would replace the archive with a raw session under its name.
+fn validate_session(path: &str, offsets: Option<&str>, force_version: Option<&str>, fix: bool) -> io::Result<Vec<FieldDiff>> {
+    let raw = read_raw(path)?;
+    if fix && (gzip::is_gzip(&raw) || path.to_ascii_lowercase().ends_with(".gz")) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("{} is gzip-compressed; decompress it before repairing with --fix", path)));
+    }
+    let mut data = gunzip_if_compressed(path, raw)?;
+    check_magic(&data)?;
+    let (profile, _) = select_profile(&data, offsets, force_version)?;
+    validate_header(&data, &profile)?;
+    let fixes = repair_session(&mut data, &profile)?;
+    if fix && !fixes.is_empty() {
+        replace_atomically(std::path::Path::new(path), &data)?;
+    }
+    Ok(fixes)
+}
 
-#[derive(Debug, Clone, Copy)]
-struct ChainOffsets {
-    scene_chain_base: usize,    // 0x2C0 (start,end,pad u16)
-    pattern_chain_base: usize,  // 0x2C4 (array of 8 entries, stride 4)
-    pattern_chain_stride: usize // 4
+// `-` reads the whole session from stdin; anything else is a file path.
+// Gzip-compressed input is recognised by its magic and decompressed first.
+fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    gunzip_if_compressed(path, read_raw(path)?)
 }
 
-impl SceneChain {
-    fn from_bytes(data: &[u8], off: &ChainOffsets) -> io::Result<Self> {
-        let b = off.scene_chain_base;
-        if b + 4 > data.len() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "SceneChain out of bounds")); }
-        let start_scene = data[b];
-        let end_scene = data[b + 1];
-        let pad = u16::from_le_bytes([data[b + 2], data[b + 3]]);
-        if start_scene >= 16 { eprintln!("[warn] Scene chain start out of range: {} (expected 0..15)", start_scene); }
-        if end_scene >= 16 { eprintln!("[warn] Scene chain end out of range: {} (expected 0..15)", end_scene); }
-        if end_scene < start_scene { eprintln!("[warn] Scene chain end < start ({} < {})", end_scene, start_scene); }
-        if pad != 0 { eprintln!("[warn] Scene chain padding not set to 0: {}", pad); }
-        Ok(SceneChain { start_scene, end_scene, pad })
+// The file's bytes exactly as stored, without gzip decompression.
+fn read_raw(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        return read_stdin(io::stdin().lock());
     }
+    let mut file = File::open(path).map_err(|e| io::Error::new(e.kind(), format!("Cannot open {}: {}", path, e)))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
 }
 
-impl PatternChains {
-    fn from_bytes(data: &[u8], off: &ChainOffsets) -> io::Result<Self> {
-        let mut entries: [PatternChainEntry; 8] = unsafe { std::mem::zeroed() };
-        for i in 0..8 {
-            let idx = off.pattern_chain_base + i * off.pattern_chain_stride;
-            if idx + 4 > data.len() { return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "PatternChains out of bounds")); }
-            let start = data[idx];
-            let end = data[idx + 1];
-            let pad = u16::from_le_bytes([data[idx + 2], data[idx + 3]]);
-            if start >= 8 { eprintln!("[warn] Pattern chain {} start out of range: {} (0..7)", i, start); }
-            if end >= 8 { eprintln!("[warn] Pattern chain {} end out of range: {} (0..7)", i, end); }
-            if end < start { eprintln!("[warn] Pattern chain {} end < start ({} < {})", i, end, start); }
-            if pad != 0 { eprintln!("[warn] Pattern chain {} padding not set to 0: {}", i, pad); }
-            entries[i] = PatternChainEntry { start, end, pad };
-        }
-        Ok(PatternChains { entries })
+fn gunzip_if_compressed(path: &str, buffer: Vec<u8>) -> io::Result<Vec<u8>> {
+    if gzip::is_gzip(&buffer) {
+        return gzip::decompress(&buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+            format!("{} is gzip-compressed but could not be decompressed: {}", path, e)));
+    }
+    if path.to_ascii_lowercase().ends_with(".gz") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has a .gz extension but is not gzip data", path)));
     }
+    Ok(buffer)
 }
 
-#[derive(Debug, Clone, Copy)]
-struct ScaleSettings { root: u8, scale_type: u8 }
+fn read_stdin(mut input: impl Read) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    input.read_to_end(&mut buffer)?;
+    if buffer.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "No session data on stdin"));
+    }
+    Ok(buffer)
+}
 
-#[derive(Debug, Clone, Copy)]
-struct ScaleOffsets { root: usize, scale_type: usize }
 
-impl ScaleSettings {
-    fn from_bytes(data: &[u8], off: &ScaleOffsets) -> io::Result<Self> {
-        if off.root >= data.len() || off.scale_type >= data.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Scale offsets out of bounds"));
+// Step planes whose every in-file byte passes plane_value_valid, as (plane, bytes
+// present). A plane with one implausible value is not understood and is left out.
+fn validated_planes(data: &[u8], off: &Offsets, layout: &Layout) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    'planes: for (plane, base) in off.planes().into_iter().enumerate() {
+        let mut c = 0usize;
+        for t in 0..layout.tracks {
+            for p in 0..layout.patterns {
+                for s in 0..layout.steps {
+                    let idx = checked_offset("plane", base, &[(t, off.track_stride), (p, off.pattern_stride), (s, 1)]);
+                    if let Some(&v) = idx.ok().and_then(|i| data.get(i)) {
+                        if !plane_value_valid(plane, v) { continue 'planes; }
+                        c += 1;
+                    }
+                }
+            }
         }
-        let root = data[off.root];
-        let scale_type = data[off.scale_type];
-        if root >= 12 { eprintln!("[warn] Scale root out of range: {} (expected 0..11)", root); }
-        if scale_type >= 16 { eprintln!("[warn] Invalid scale type: {} (expected 0..15)", scale_type); }
-        Ok(ScaleSettings { root, scale_type })
+        out.push((plane, c));
     }
+    out
 }
 
+// Simple coverage metric: count bytes we can confidently interpret: the step planes
+// that pass validation + 2 FX preset bytes
+fn compute_known_bytes(data: &[u8], off: &Offsets, layout: &Layout, fx: &FxOffsets) -> usize {
+    let mut known: usize = validated_planes(data, off, layout).iter().map(|&(_, c)| c).sum();
+    // FX bytes
+    if fx.delay_preset < data.len() { known += 1; }
+    if fx.reverb_preset < data.len() { known += 1; }
+    known
+}
 
+// Glyphs used by the ASCII grid. The default reproduces the classic output:
+// '.' rests, four block levels for hits, one bar (8 steps) per line.
+#[derive(Debug, Clone)]
+struct RenderStyle {
+    rest: String,
+    hits: Vec<char>,
+    // When set, bars stay on one line joined by this separator
+    bar_sep: Option<String>,
+    prob_digit: ProbDigit,
+    // Wrap hits in ANSI colour by velocity (off: output is plain text)
+    color: bool,
+    // Velocity at which each hit glyph takes over
+    scale: SymbolScale,
+    // Step numbers over each bar, cells padded to a fixed width
+    ruler: bool,
+}
 
+// Lowest velocity of each hit glyph after the first, ascending. Empty keeps the classic
+// even split of 0..128 over however many glyphs there are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SymbolScale(Vec<u8>);
 
+impl SymbolScale {
+    // Ghost notes below 40, normal hits below 80, strong hits below 110, accents above.
+    const MUSICAL: [u8; 3] = [40, 80, 110];
 
+    // `even`, `musical`, or comma-separated ascending thresholds in 1..=127 like "30,70,105".
+    fn parse(spec: &str) -> Result<Self, String> {
+        match spec {
+            "even" => return Ok(SymbolScale::default()),
+            "musical" => return Ok(SymbolScale(Self::MUSICAL.to_vec())),
+            _ => {}
+        }
+        let bounds = spec.split(',')
+            .map(|v| match v.trim().parse::<u8>() {
+                Ok(b @ 1..=127) => Ok(b),
+                Ok(b) => Err(format!("threshold {} is outside 1..=127", b)),
+                Err(e) => Err(format!("{:?}: {}", v, e)),
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        if bounds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!("thresholds must be strictly ascending, got {}", spec));
+        }
+        Ok(SymbolScale(bounds))
+    }
 
+    // Index into `levels` hit glyphs for a non-zero velocity.
+    fn level(&self, velocity: u8, levels: usize) -> usize {
+        let idx = if self.0.is_empty() {
+            (velocity as usize * levels) / 128
+        } else {
+            self.0.iter().take_while(|&&b| velocity >= b).count()
+        };
+        idx.min(levels - 1)
+    }
 
+    // Explicit thresholds need exactly one glyph more than they have bounds.
+    fn check(&self, levels: usize) -> io::Result<()> {
+        if !self.0.is_empty() && self.0.len() + 1 != levels {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "--velocity-levels has {} thresholds but --hit-glyph-set has {} glyphs (needs {})",
+                self.0.len(), levels, self.0.len() + 1)));
+        }
+        Ok(())
+    }
+}
 
-impl DrumData {
-    fn from_bytes(data: &[u8], offsets: &Offsets) -> io::Result<Self> {
-        let mut tracks: [DrumTrack; TRACKS] = unsafe { std::mem::zeroed() };
-
-        for t in 0..TRACKS {
-            let mut patterns: [Pattern; PATTERNS] = unsafe { std::mem::zeroed() };
+// How the probability after a hit glyph is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProbDigit {
+    // One digit in base max+1, so every legal value 0..=max is distinct; '?' beyond max
+    Range(u8),
+    // The old `probability % 10`
+    Decimal,
+    // Share of max as a percentage, right-aligned in 4 columns; '?' beyond max
+    Percent(u8),
+    // The stored value, right-aligned in 3 columns
+    Raw,
+}
 
-            for p in 0..PATTERNS {
-                let mut steps: [Step; STEPS] = unsafe { std::mem::zeroed() };
+impl ProbDigit {
+    fn label(self, probability: u8) -> String {
+        match self {
+            ProbDigit::Range(max) if probability <= max => {
+                char::from_digit(probability as u32, max as u32 + 1).unwrap_or('?').to_string()
+            }
+            ProbDigit::Range(_) => "?".to_string(),
+            ProbDigit::Decimal => char::from_digit(probability as u32 % 10, 10).unwrap_or('?').to_string(),
+            ProbDigit::Percent(max) if probability <= max => {
+                format!("{:>3}%", (probability as u32 * 100 + max as u32 / 2) / (max as u32).max(1))
+            }
+            ProbDigit::Percent(_) => format!("{:>4}", "?"),
+            ProbDigit::Raw => format!("{:>3}", probability),
+        }
+    }
 
-                for s in 0..STEPS {
-                    let idx = t * offsets.track_stride + p * offsets.pattern_stride + s;
-
-                    steps[s] = Step {
-                        velocity: data[offsets.velocity + idx],
-                        probability: data[offsets.probability + idx],
-                        choice: data[offsets.choice + idx],
-                        mask: data[offsets.mask + idx],
-                        pitch: data[offsets.pitch + idx],
-                        decay: data[offsets.decay + idx],
-                        distortion: data[offsets.distortion + idx],
-                        eq: data[offsets.eq + idx],
-                    };
-                }
+    // Columns the label takes, so rests can be padded to line up with hits.
+    fn width(self) -> usize {
+        match self {
+            ProbDigit::Range(_) | ProbDigit::Decimal => 1,
+            ProbDigit::Percent(_) => 4,
+            ProbDigit::Raw => 3,
+        }
+    }
+}
 
-                patterns[p] = Pattern { steps };
-            }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ProbFormat {
+    /// One digit per hit, 0..7
+    Digit,
+    /// Share of the highest probability, so 7 is 100% and 4 is 57%
+    Percent,
+    /// The stored value as a number
+    Raw,
+}
 
-            tracks[t] = DrumTrack { patterns };
+impl ProbFormat {
+    fn style(self, legacy_digit: bool) -> ProbDigit {
+        let max = PLANE_INFO[1].max as u8;
+        match self {
+            ProbFormat::Digit if legacy_digit => ProbDigit::Decimal,
+            ProbFormat::Digit => ProbDigit::Range(max),
+            ProbFormat::Percent => ProbDigit::Percent(max),
+            ProbFormat::Raw => ProbDigit::Raw,
         }
+    }
+}
 
-        Ok(DrumData { tracks })
+impl Default for RenderStyle {
+    fn default() -> Self {
+        RenderStyle {
+            rest: ".".into(),
+            hits: vec!['▁', '▃', '▅', '█'],
+            bar_sep: None,
+            prob_digit: ProbDigit::Range(PLANE_INFO[1].max as u8),
+            color: false,
+            scale: SymbolScale::default(),
+            ruler: false,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Offsets {
-    velocity: usize,
-    probability: usize,
-    choice: usize,
-    mask: usize,
-    pitch: usize,
-    decay: usize,
-    distortion: usize,
-    eq: usize,
-    track_stride: usize,
-    pattern_stride: usize,
+fn step_symbol_styled(velocity: u8, probability: u8, style: &RenderStyle) -> String {
+    if velocity == 0 {
+        // Multi-column probability labels pad rests to the same width; one digit keeps the classic grid
+        let width = style.prob_digit.width();
+        return if width > 1 { format!("{:<w$}", style.rest, w = width + 1) } else { style.rest.clone() };
+    }
+    let levels = &style.hits;
+    let ch = levels[style.scale.level(velocity, levels.len())];
+    // Append the probability label, by default a single digit like the Python/TUI helpers
+    colorize(&format!("{}{}", ch, style.prob_digit.label(probability)), velocity, style)
 }
 
-fn read_file(path: &str) -> io::Result<Vec<u8>> {
-    let mut file = File::open(path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    Ok(buffer)
+// Grey ramp from the 256-colour palette: quiet hits dim, loud hits bright white.
+fn colorize(sym: &str, velocity: u8, style: &RenderStyle) -> String {
+    if !style.color || velocity == 0 {
+        return sym.to_string();
+    }
+    let shade = 240 + (velocity.min(127) as u32 * 15) / 127;
+    format!("\x1b[38;5;{}m{}\x1b[0m", shade, sym)
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Colour when stdout is a terminal and NO_COLOR is unset
+    Auto,
+    Always,
+    Never,
+}
 
-// Simple coverage metric: count bytes we can confidently interpret (validated via firmware)
-// Currently: per-step velocity/probability/choice/mask (4 planes) + 2 FX preset bytes
-fn compute_known_bytes(data: &[u8], off: &Offsets, fx: &FxOffsets) -> usize {
-    let mut known: usize = 0;
-    // Helper to count per-step plane
-    let mut count_plane = |base: usize| {
-        let mut c = 0usize;
-        for t in 0..TRACKS {
-            for p in 0..PATTERNS {
-                for s in 0..STEPS {
-                    let idx = base + t * off.track_stride + p * off.pattern_stride + s;
-                    if idx < data.len() { c += 1; }
-                }
-            }
+impl ColorChoice {
+    fn enabled(self, no_color: Option<&str>, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(str::is_empty),
         }
-        c
-    };
-    known += count_plane(off.velocity);
-    known += count_plane(off.probability);
-    known += count_plane(off.choice);
-    known += count_plane(off.mask);
-    // FX bytes
-    if fx.delay_preset < data.len() { known += 1; }
-    if fx.reverb_preset < data.len() { known += 1; }
-    known
+    }
 }
 
-fn step_symbol(velocity: u8, probability: u8) -> String {
-    if velocity == 0 {
-        return ".".into();
+// One parameter plane on a single line, bars split by '|'. Pitch is shown as a signed
+// semitone offset (raw byte if out of range), unset steps as "--". Rests keep their stored values so parameter
+// locks on silent steps stay visible.
+fn render_param_row(steps: &[Step], plane: usize) -> String {
+    let mut out = String::new();
+    for (i, st) in steps.iter().enumerate() {
+        if i > 0 { out.push_str(if i % 8 == 0 { " | " } else { " " }); }
+        let v = st.planes()[plane];
+        let cell = if v == PARAM_UNSET {
+            "--".to_string()
+        } else if PLANE_NAMES[plane] == "pitch" {
+            if plane_value_valid(plane, v) { format!("{:+}", st.pitch_semitones()) } else { v.to_string() }
+        } else {
+            v.to_string()
+        };
+        out.push_str(&format!("{:>3}", cell));
     }
-    const LEVELS: &[char] = &['▁', '▃', '▅', '█'];
-    let idx = ((velocity as usize * LEVELS.len()) / 128).min(LEVELS.len() - 1);
-    let ch = LEVELS[idx];
-    // Append a single probability digit similar to the Python/TUI helpers
-    format!("{}{}", ch, probability % 10)
+    out
 }
 
 fn render_ascii(steps: &[Step], show_prob: bool) -> String {
+    render_ascii_styled(steps, show_prob, &RenderStyle::default())
+}
+
+fn render_ascii_styled(steps: &[Step], show_prob: bool, style: &RenderStyle) -> String {
+    if style.ruler {
+        return render_ascii_ruled(steps, show_prob, style);
+    }
     let mut out = String::new();
     for (i, st) in steps.iter().enumerate() {
         if i > 0 {
-            if i % 8 == 0 { out.push('\n'); } else { out.push(' '); }
+            if i % 8 == 0 {
+                match &style.bar_sep {
+                    Some(sep) => { out.push(' '); out.push_str(sep); out.push(' '); }
+                    None => out.push('\n'),
+                }
+            } else {
+                out.push(' ');
+            }
         }
-        let sym = if show_prob { step_symbol(st.velocity, st.probability) } else { if st.velocity == 0 { ".".into() } else { "█".into() } };
-        out.push_str(&sym);
+        out.push_str(&grid_symbol(st, show_prob, style));
     }
     out
 }
 
+fn grid_symbol(st: &Step, show_prob: bool, style: &RenderStyle) -> String {
+    if show_prob {
+        step_symbol_styled(st.velocity, st.probability, style)
+    } else if st.velocity == 0 {
+        style.rest.clone()
+    } else {
+        colorize(&style.hits[style.hits.len() - 1].to_string(), st.velocity, style)
+    }
+}
 
-fn main() -> io::Result<()> {
-    let file_path = std::env::args().nth(1).expect("Usage: <program> <ncs file>");
-    let data = read_file(&file_path)?;
-
-    // Example offsets, adjust for your NCS layout
-    let offsets = Offsets {
-        velocity: 0x0CD74,
-        probability: 0x0CD94,
-        choice: 0x0CDB4,
-        mask: 0x0CDD4,
-        pitch: 0x0CDF4,
-        decay: 0x0CE14,
-        distortion: 0x0CE34,
-        eq: 0x0CE54,
-        track_stride: 0x3540,
-        pattern_stride: 0x06A8,
-    };
+// Columns a grid cell takes on screen, not counting ANSI colour escapes.
+fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
 
-    // Offsets from reverse engineering analysis
-    let fx_offsets = FxOffsets {
-        delay_preset: 0x00026D0E, // (&DAT_ram_00026d0e)[param1]
-        reverb_preset: 0x00026D0F, // (&DAT_ram_00026d0f)[param1]
-    };
-    let timing_offsets = TimingOffsets { tempo: 0x34, swing: 0x35, swing_sync_rate: 0x36, spare1: 0x38, spare2: 0x3C };
-    let scale_offsets = ScaleOffsets { root: 0x26D0C, scale_type: 0x26D0D };
+// The grid with a "1 2 ... 8" ruler over every bar. Every cell is padded to the
+// widest symbol the style can draw, so hits, rests and ruler numbers line up with or
+// without probability labels. Bars on their own lines are split by a dashed rule;
+// with bar_sep the ruler is one line over the one-line grid.
+fn render_ascii_ruled(steps: &[Step], show_prob: bool, style: &RenderStyle) -> String {
+    let hit = if show_prob { 1 + style.prob_digit.width() } else { 1 };
+    let cell = hit.max(style.rest.chars().count());
+    let bars: Vec<(String, String)> = steps.chunks(8).map(|bar| {
+        let ruler: Vec<String> = (1..=bar.len()).map(|n| format!("{:<w$}", n, w = cell)).collect();
+        let cells: Vec<String> = bar.iter().map(|st| {
+            let sym = grid_symbol(st, show_prob, style);
+            let pad = cell.saturating_sub(visible_width(&sym));
+            format!("{}{}", sym, " ".repeat(pad))
+        }).collect();
+        (ruler.join(" "), cells.join(" "))
+    }).collect();
+    match &style.bar_sep {
+        Some(sep) => {
+            let join = format!(" {} ", sep);
+            let ruler: Vec<&str> = bars.iter().map(|(r, _)| r.as_str()).collect();
+            let cells: Vec<&str> = bars.iter().map(|(_, c)| c.as_str()).collect();
+            format!("{}\n{}", ruler.join(&join).trim_end(), cells.join(&join).trim_end())
+        }
+        None => {
+            let rule = "-".repeat(8 * cell + 7);
+            bars.iter()
+                .map(|(r, c)| format!("{}\n{}", r.trim_end(), c.trim_end()))
+                .collect::<Vec<_>>()
+                .join(&format!("\n{}\n", rule))
+        }
+    }
+}
 
+// Sparse form of a pattern: active steps as `step:velocity`, space separated.
+fn render_rle(steps: &[Step]) -> String {
+    steps.iter().enumerate()
+        .filter(|(_, st)| st.velocity > 0)
+        .map(|(i, st)| format!("{}:{}", i, st.velocity))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    let timing = Timing::from_bytes(&data, &timing_offsets)?;
-    let scale = ScaleSettings::from_bytes(&data, &scale_offsets)?;
+// Per track, how many of its patterns have each step active (0..=patterns), indexed [track][step].
+fn step_heatmap(drums: &DrumData) -> Vec<Vec<u8>> {
+    let layout = drums.layout();
+    let mut heat = vec![vec![0u8; layout.steps]; layout.tracks];
+    for (t, _, s, st) in drums.steps() {
+        if st.velocity > 0 { heat[t][s] += 1; }
+    }
+    heat
+}
 
-    let fx = Fx::from_bytes(&data, &fx_offsets)?;
+// Intensity glyph for `count` out of `max`; only a zero count renders as '.'.
+fn heat_glyph(count: usize, max: usize) -> char {
+    const HEAT: &[char] = &['.', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let levels = HEAT.len() - 1;
+    HEAT[((count.min(max) * levels).div_ceil(max.max(1))).min(levels)]
+}
 
-    let drums = DrumData::from_bytes(&data, &offsets)?;
+fn render_heatmap_row(counts: &[u8], max: usize) -> String {
+    let mut out = String::new();
+    for (i, &c) in counts.iter().enumerate() {
+        if i > 0 && i % 8 == 0 { out.push(' '); }
+        out.push(heat_glyph(c as usize, max));
+    }
+    out
+}
 
-    // Simple coverage metric
-    let known = compute_known_bytes(&data, &offsets, &fx_offsets)
-        + 3  // timing bytes: tempo, swing, swing_sync_rate
-        + 8  // timing dwords: spare1, spare2
-        + (16 * 8 * 4)  // scenes table bytes
-        + 4              // scene chain: start,end,pad u16
-        + (8 * 4);       // pattern chains: 8 entries x 4 bytes
+// One step_heatmap row per track, labelled "T<n>" or with the track's name, padded so
+// the rows line up.
+fn render_step_heatmap(heat: &[Vec<u8>], max: usize, names: &TrackNames) -> String {
+    let labels = names.row_labels(heat.len());
+    let mut out = String::new();
+    for (label, row) in labels.iter().zip(heat) {
+        out.push_str(&format!("{} {}\n", label, render_heatmap_row(row, max)));
+    }
+    out
+}
 
-    let total = data.len();
+// Session overview: one cell per (track, pattern), shaded by onset density with the
+// same hit glyphs as the step grid. Empty patterns show the rest glyph.
+fn render_density_heatmap(stats: &[Vec<PatternStats>], style: &RenderStyle, names: &TrackNames) -> String {
+    let patterns = stats.first().map_or(0, Vec::len);
+    let labels = names.row_labels(stats.len());
+    let mut out = " ".repeat(labels.first().map_or(3, |l| l.chars().count()));
+    for p in 0..patterns { out.push_str(&format!(" P{}", p)); }
+    out.push('\n');
+    for (label, row) in labels.iter().zip(stats) {
+        out.push_str(label);
+        for st in row {
+            let cell = if st.onsets == 0 {
+                style.rest.clone()
+            } else {
+                let levels = style.hits.len();
+                let idx = ((st.density * levels as f64 / 100.0) as usize).min(levels - 1);
+                style.hits[idx].to_string()
+            };
+            out.push_str(&format!("  {}", cell));
+        }
+        out.push('\n');
+    }
+    out
+}
 
-    println!(
-        "Known bytes: {} / {} ({:.2}%) | fields: steps[velocity,probability,choice,mask], fx[delay,reverb], timing[tempo,swing,swing_sync_rate,spare1,spare2], scale[root,type], scenes+chains",
-        known,
-        total,
-        (known as f64) * 100.0 / (total.max(1) as f64)
-    );
+// For each plane, how many steps differ in every (track, pattern) cell (0..=steps),
+// indexed [plane][track][pattern]. Only the layout both sides share is compared.
+fn diff_heatmap(a: &DrumData, b: &DrumData) -> [Vec<Vec<u8>>; 8] {
+    let layout = a.layout();
+    let mut heat: [Vec<Vec<u8>>; 8] = std::array::from_fn(|_| vec![vec![0u8; layout.patterns]; layout.tracks]);
+    for ((t, p, _, x), (_, _, _, y)) in a.steps().zip(b.steps()) {
+        let (x, y) = (x.planes(), y.planes());
+        for plane in 0..8 {
+            if x[plane] != y[plane] { heat[plane][t][p] += 1; }
+        }
+    }
+    heat
+}
 
-    // ASCII/debug header
-    println!("Timing: tempo={} swing={} swing_sync_rate={} spare1={} spare2={}", timing.tempo, timing.swing, timing.swing_sync_rate, timing.spare1, timing.spare2);
-    println!("Scale: root={} type={}", scale.root, scale.scale_type);
+fn render_diff_heatmap(heat: &[Vec<Vec<u8>>; 8], steps: usize, names: &TrackNames) -> String {
+    let mut out = String::new();
+    let patterns = heat[0].first().map_or(0, Vec::len);
+    let labels = names.row_labels(heat[0].len());
+    for (plane, grid) in heat.iter().enumerate() {
+        out.push_str(&format!("{} (changed steps per track x pattern, 0..{}):\n", PLANE_NAMES[plane], steps));
+        out.push_str(&" ".repeat(labels.first().map_or(3, |l| l.chars().count())));
+        for p in 0..patterns { out.push_str(&format!(" P{}", p)); }
+        out.push('\n');
+        for (label, row) in labels.iter().zip(grid) {
+            out.push_str(label);
+            for &c in row { out.push_str(&format!("  {}", heat_glyph(c as usize, steps))); }
+            out.push('\n');
+        }
+    }
+    out
+}
 
-    println!("FX: delay_preset={} reverb_preset={}", fx.delay_preset, fx.reverb_preset);
+// Pearson correlation between every pair of planes, taken over active (velocity > 0)
+// steps. Pairs involving a constant plane have no defined correlation and are NaN.
+fn plane_correlation(drums: &DrumData) -> [[f64; 8]; 8] {
+    let samples: Vec<[u8; 8]> = drums.steps()
+        .filter(|(_, _, _, st)| st.velocity > 0)
+        .map(|(_, _, _, st)| st.planes())
+        .collect();
+    // Integer sums keep the mean of a constant plane exact, so its variance is 0
+    let mut sums = [0u64; 8];
+    for smp in &samples {
+        for (sum, &v) in sums.iter_mut().zip(smp) { *sum += v as u64; }
+    }
+    let n = samples.len().max(1) as f64;
+    let mean = sums.map(|sum| sum as f64 / n);
+    let mut corr = [[f64::NAN; 8]; 8];
+    for a in 0..8 {
+        for b in a..8 {
+            let (mut cov, mut va, mut vb) = (0f64, 0f64, 0f64);
+            for smp in &samples {
+                let da = smp[a] as f64 - mean[a];
+                let db = smp[b] as f64 - mean[b];
+                cov += da * db;
+                va += da * da;
+                vb += db * db;
+            }
+            if va > 0.0 && vb > 0.0 {
+                let r = (cov / (va.sqrt() * vb.sqrt())).clamp(-1.0, 1.0);
+                corr[a][b] = r;
+                corr[b][a] = r;
+            }
+        }
+    }
+    corr
+}
 
-    // Scenes & chains
-    let scenes_offsets = ScenesOffsets { base: 0x40, scene_stride: 0x28, entry_stride: 4 };
-    let _scenes = Scenes::from_bytes(&data, &scenes_offsets)?;
-    let chain_offsets = ChainOffsets { scene_chain_base: 0x2C0, pattern_chain_base: 0x2C4, pattern_chain_stride: 4 };
-    let scene_chain = SceneChain::from_bytes(&data, &chain_offsets)?;
-    let _pattern_chains = PatternChains::from_bytes(&data, &chain_offsets)?;
-    println!("Scenes: 16x8 parsed | SceneChain: {}..{} | PatternChains: 8 entries",
-             scene_chain.start_scene, scene_chain.end_scene);
+// Per-plane summary used to triage which planes carry real per-step data.
+// Value metrics are over active steps; rest_contamination is the share of rest
+// steps whose value differs from the most common value on rests.
+#[derive(Debug, Clone)]
+struct PlaneStats {
+    name: &'static str,
+    min: u8,
+    max: u8,
+    mean: f64,
+    distinct: usize,
+    constant: bool,
+    nonzero: f64,
+    rest_contamination: f64,
+}
 
+// Shannon entropy (bits) of each plane's values over active steps, ranked highest
+// first. Constant planes come out at 0.
+fn plane_entropy(drums: &DrumData) -> Vec<(&'static str, f64)> {
+    let mut hist = [[0usize; 256]; 8];
+    for (_, _, _, st) in drums.steps().filter(|(_, _, _, st)| st.velocity > 0) {
+        for (plane, v) in st.planes().iter().enumerate() { hist[plane][*v as usize] += 1; }
+    }
+    let mut out: Vec<(&'static str, f64)> = hist.iter().enumerate().map(|(plane, h)| {
+        let total: usize = h.iter().sum();
+        let bits = h.iter().filter(|&&c| c > 0)
+            .map(|&c| { let p = c as f64 / total as f64; -p * p.log2() })
+            .sum::<f64>();
+        (PLANE_NAMES[plane], bits.abs()) // abs: a single value yields -0.0
+    }).collect();
+    out.sort_by(|a, b| b.1.total_cmp(&a.1));
+    out
+}
 
+// Track pairs whose exact step match, averaged over all pattern slots, is at least
+// `threshold`. Pairs of completely empty tracks are not reported.
+fn mirrored_tracks(drums: &DrumData, threshold: f64) -> Vec<(usize, usize, f64)> {
+    let mut out = Vec::new();
+    for (a, ta) in drums.tracks.iter().enumerate() {
+        for (b, tb) in drums.tracks.iter().enumerate().skip(a + 1) {
+            if ta.patterns.iter().chain(&tb.patterns).all(Pattern::is_empty) { continue; }
+            let score = ta.patterns.iter().zip(&tb.patterns).map(|(x, y)| x.step_match(y)).sum::<f64>() / ta.patterns.len().max(1) as f64;
+            if score >= threshold { out.push((a, b, score)); }
+        }
+    }
+    out
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PatternUse {
+    Used,
+    Empty,
+    // Same bytes in all eight planes as the earlier slot
+    DuplicateOf(usize),
+}
 
-    // Drums (ASCII)
-    for t in 0..TRACKS {
-        println!("\n=== DRUM TRACK {} ===", t);
-        for p in 0..PATTERNS {
-            let patt = &drums.tracks[t].patterns[p];
-            let ascii = render_ascii(&patt.steps, true);
-            let label = format!("P{:02}: ", p);
-            let mut lines = ascii.lines();
-            if let Some(first) = lines.next() {
-                println!("{}{}", label, first);
-                let pad = " ".repeat(label.len());
-                for line in lines { println!("{}{}", pad, line); }
+fn classify_patterns(drums: &DrumData) -> Vec<Vec<PatternUse>> {
+    let layout = drums.layout();
+    let mut out = vec![vec![PatternUse::Used; layout.patterns]; layout.tracks];
+    for (t, track) in drums.tracks.iter().enumerate() {
+        for (p, patt) in track.patterns.iter().enumerate() {
+            out[t][p] = if patt.is_empty() {
+                PatternUse::Empty
+            } else if let Some(q) = (0..p).find(|&q| out[t][q] == PatternUse::Used && track.patterns[q].step_match(patt) == 1.0) {
+                PatternUse::DuplicateOf(q)
             } else {
-                println!("{}", label);
-            }
+                PatternUse::Used
+            };
         }
     }
+    out
+}
 
-    Ok(())
+fn render_pattern_use(uses: &[Vec<PatternUse>], names: &TrackNames) -> String {
+    let mut out = String::new();
+    for (t, row) in uses.iter().enumerate() {
+        let notes: Vec<String> = row.iter().enumerate().filter_map(|(p, u)| match u {
+            PatternUse::Used => None,
+            PatternUse::Empty => Some(format!("P{:02} empty", p)),
+            PatternUse::DuplicateOf(q) => Some(format!("P{:02} == P{:02}", p, q)),
+        }).collect();
+        let notes = if notes.is_empty() { "all patterns used and distinct".to_string() } else { notes.join(", ") };
+        out.push_str(&format!("{}: {}\n", names.name(t), notes));
+    }
+    out
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Keep planes with at least `min_distinct` values and at least `min_nonzero`
+// percent of active steps carrying a nonzero value.
+fn filter_plane_stats(stats: Vec<PlaneStats>, min_distinct: usize, min_nonzero: f64) -> Vec<PlaneStats> {
+    stats.into_iter().filter(|st| st.distinct >= min_distinct && st.nonzero * 100.0 >= min_nonzero).collect()
+}
 
-    fn load_drums(path: &str) -> DrumData {
-        let data = read_file(path).expect("failed to read test ncs file");
-        let offsets = Offsets {
-            velocity: 0x0CD74,
-            probability: 0x0CD94,
-            choice: 0x0CDB4,
-            mask: 0x0CDD4,
-            pitch: 0x0CDF4,
-            decay: 0x0CE14,
-            distortion: 0x0CE34,
-            eq: 0x0CE54,
-            track_stride: 0x3540,
-            pattern_stride: 0x06A8,
-        };
-        DrumData::from_bytes(&data, &offsets).expect("parse drums")
-    }
+fn plane_stats(drums: &DrumData) -> Vec<PlaneStats> {
+    (0..8).map(|plane| {
+        let mut hist = [0usize; 256];
+        let mut rest_hist = [0usize; 256];
+        for (_, _, _, st) in drums.steps() {
+            let v = st.planes()[plane] as usize;
+            if st.velocity > 0 { hist[v] += 1; } else { rest_hist[v] += 1; }
+        }
+        let active: usize = hist.iter().sum();
+        let rests: usize = rest_hist.iter().sum();
+        let min = hist.iter().position(|&c| c > 0).unwrap_or(0) as u8;
+        let max = hist.iter().rposition(|&c| c > 0).unwrap_or(0) as u8;
+        let sum: usize = hist.iter().enumerate().map(|(v, &c)| v * c).sum();
+        let distinct = hist.iter().filter(|&&c| c > 0).count();
+        let nonzero = active - hist[0];
+        let rest_mode = rest_hist.iter().copied().max().unwrap_or(0);
+        PlaneStats {
+            name: PLANE_NAMES[plane],
+            min,
+            max,
+            mean: if active > 0 { sum as f64 / active as f64 } else { 0.0 },
+            distinct,
+            constant: distinct <= 1,
+            nonzero: if active > 0 { nonzero as f64 / active as f64 } else { 0.0 },
+            rest_contamination: if rests > 0 { (rests - rest_mode) as f64 / rests as f64 } else { 0.0 },
+        }
+    }).collect()
+}
 
-    #[test]
+fn render_plane_stats(stats: &[PlaneStats], format: ReportFormat) -> String {
+    let mut out = String::new();
+    match format {
+        ReportFormat::Table => {
+            out.push_str(&format!("{:<12} {:>4} {:>4} {:>8} {:>8} {:>8} {:>8} {:>8}\n", "plane", "min", "max", "mean", "distinct", "constant", "nonzero", "rest_ctm"));
+            for st in stats {
+                out.push_str(&format!("{:<12} {:>4} {:>4} {:>8.2} {:>8} {:>8} {:>8.3} {:>8.3}\n",
+                    st.name, st.min, st.max, st.mean, st.distinct, st.constant, st.nonzero, st.rest_contamination));
+            }
+        }
+        ReportFormat::Csv => {
+            out.push_str("plane,min,max,mean,distinct,constant,nonzero,rest_contamination\n");
+            for st in stats {
+                out.push_str(&format!("{},{},{},{:.4},{},{},{:.4},{:.4}\n",
+                    st.name, st.min, st.max, st.mean, st.distinct, st.constant, st.nonzero, st.rest_contamination));
+            }
+        }
+    }
+    out
+}
+
+// One plane as a tracks*patterns x steps matrix: a row per (track, pattern)
+// with the two index columns first, then one column per step.
+// With `binarize` every cell is 1/0 by the step's velocity instead of the plane value.
+fn plane_matrix_csv(drums: &DrumData, plane: usize, binarize: bool) -> String {
+    let mut out = String::from("track,pattern");
+    for s in 0..drums.layout().steps { out.push_str(&format!(",{}", s)); }
+    out.push('\n');
+    for (t, track) in drums.tracks.iter().enumerate() {
+        for (p, patt) in track.patterns.iter().enumerate() {
+            out.push_str(&format!("{},{}", t, p));
+            for st in &patt.steps {
+                let value = if binarize { (st.velocity > 0) as u8 } else { st.planes()[plane] };
+                out.push_str(&format!(",{}", value));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+.summary td, .summary th { padding: 2px 8px; text-align: left; }
+.pattern td { width: 18px; height: 18px; border: 1px solid #ccc; }
+.pattern td.bar { border-left: 2px solid #666; }
+pre { background: #f4f4f4; padding: 0.5em; display: inline-block; }";
+
+// Self-contained report for a browser: summary, scene table and one table per
+// non-empty pattern, cells shaded by velocity. Inline CSS only, no external assets.
+fn render_html(session: &Session, names: &TrackNames) -> String {
+    let title = html_escape(&session.name.name);
+    let mut out = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n", title, HTML_STYLE, title);
+    let (t, sc, fx) = (&session.timing, &session.scale, &session.fx);
+    out.push_str("<table class=\"summary\">\n");
+    for (field, value) in [
+        ("Tempo", t.tempo.to_string()),
+        ("Swing", format!("{} (sync rate {})", t.swing, t.swing_sync_rate)),
+        ("Scale", format!("{} {}", sc.root_name(), sc.scale_type_name())),
+        ("FX", format!("delay preset {}, reverb preset {}", fx.delay_preset, fx.reverb_preset)),
+        ("Master volume", session.globals.master_volume.to_string()),
+    ] {
+        out.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", field, html_escape(&value)));
+    }
+    out.push_str("</table>\n<h2>Scenes</h2>\n");
+    out.push_str(&format!("<pre>{}</pre>\n<h2>Patterns</h2>\n", html_escape(&render_scenes(&session.scenes, true))));
+    for (ti, track) in session.drums.tracks.iter().enumerate() {
+        for (p, pattern) in track.patterns.iter().enumerate().filter(|(_, p)| !p.is_empty()) {
+            out.push_str(&format!("<h3>{} P{:02}</h3>\n<table class=\"pattern\"><tr>", html_escape(&names.name(ti)), p));
+            for (s, st) in pattern.steps.iter().enumerate() {
+                let class = if s > 0 && s % 8 == 0 { " class=\"bar\"" } else { "" };
+                if st.velocity == 0 {
+                    out.push_str(&format!("<td{}></td>", class));
+                } else {
+                    out.push_str(&format!("<td{} style=\"background: rgba(0,0,0,{:.3})\" title=\"step {}: velocity {}, probability {}\"></td>",
+                        class, st.velocity.min(127) as f64 / 127.0, s, st.velocity, st.probability));
+                }
+            }
+            out.push_str("</tr></table>\n");
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+// Piano-roll SVG of one pattern slot: a row of one cell per step for each track, a filled rect per
+// hit with opacity following velocity, thin lines per step and bold lines per bar.
+fn render_svg(drums: &DrumData, tracks: &[usize], pattern: usize, names: &TrackNames) -> String {
+    const CELL: usize = 20;
+    const TITLE: usize = 24;
+    // Wide enough for the longest row label at ~8px per monospace character
+    let labels: Vec<String> = tracks.iter().map(|&t| names.label(t)).collect();
+    let label = labels.iter().map(|l| l.chars().count() * 8 + 8).max().unwrap_or(0).max(40);
+    let steps = drums.layout().steps;
+    let (width, height) = (label + steps * CELL, TITLE + tracks.len() * CELL);
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"monospace\" font-size=\"12\">\n",
+        w = width, h = height
+    );
+    out.push_str(&format!("<text x=\"{}\" y=\"16\">Pattern {}</text>\n", label, pattern));
+    for (row, &t) in tracks.iter().enumerate() {
+        let y = TITLE + row * CELL;
+        out.push_str(&format!("<text x=\"4\" y=\"{}\">{}</text>\n", y + CELL - 6, html_escape(&labels[row])));
+        for (s, st) in drums.tracks[t].patterns[pattern].steps.iter().enumerate() {
+            if st.velocity == 0 { continue; }
+            out.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"black\" fill-opacity=\"{:.3}\"/>\n",
+                label + s * CELL, y, CELL, CELL, st.velocity.min(127) as f64 / 127.0
+            ));
+        }
+    }
+    for s in 0..=steps {
+        let x = label + s * CELL;
+        let w = if s % 8 == 0 { 2 } else { 1 };
+        out.push_str(&format!("<line x1=\"{x}\" y1=\"{}\" x2=\"{x}\" y2=\"{}\" stroke=\"grey\" stroke-width=\"{}\"/>\n", TITLE, height, w, x = x));
+    }
+    for row in 0..=tracks.len() {
+        let y = TITLE + row * CELL;
+        out.push_str(&format!("<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"grey\" stroke-width=\"1\"/>\n", label, width, y = y));
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+// Steps that carry a mask or a sample choice, with the raw mask bits next to the
+// guessed names so the decoding can be checked against the device. A named choice
+// reads `c2(Kick 3)`.
+fn render_locks(steps: &[Step], track: usize, choices: &ChoiceNames) -> String {
+    let cells: Vec<String> = steps.iter().enumerate()
+        .filter(|(_, st)| st.mask != 0 || st.sample_choice() != CHOICE_NONE)
+        .map(|(i, st)| {
+            let choice = match st.sample_choice() {
+                CHOICE_NONE => "c-".to_string(),
+                c => choices.label(track, c),
+            };
+            format!("{}:0b{:08b}[{}]{}", i, st.mask, st.locked_params().join(","), choice)
+        })
+        .collect();
+    if cells.is_empty() { "-".to_string() } else { cells.join(" ") }
+}
+
+// A CSV field, quoted (with inner quotes doubled) when it holds a comma, quote or line
+// break, so free-form names cannot shift the columns after them.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Lossless long-format grid: one row per step with every plane's raw byte in layout
+// order, then the derived columns (decoded pitch_semitones, and choice_name when a
+// name table is loaded) so importers can take the raw planes positionally.
+fn steps_csv(drums: &DrumData, choices: &ChoiceNames) -> String {
+    let mut header: Vec<&str> = PLANE_NAMES.to_vec();
+    header.push("pitch_semitones");
+    // Only with a name table, so the default columns stay as they were
+    let named = !choices.is_empty();
+    if named { header.push("choice_name"); }
+    let mut out = format!("track,pattern,step,{}\n", header.join(","));
+    for (t, track) in drums.tracks.iter().enumerate() {
+        for (p, patt) in track.patterns.iter().enumerate() {
+            for (s, st) in patt.steps.iter().enumerate() {
+                let mut values: Vec<String> = st.planes().iter().map(|v| v.to_string()).collect();
+                values.push(st.pitch_semitones().to_string());
+                if named { values.push(csv_field(choices.get(t, st.sample_choice()).unwrap_or_default())); }
+                out.push_str(&format!("{},{},{},{}\n", t, p, s, values.join(",")));
+            }
+        }
+    }
+    out
+}
+
+fn render_correlation(corr: &[[f64; 8]; 8]) -> String {
+    let mut out = format!("{:>12}", "");
+    for name in PLANE_NAMES { out.push_str(&format!("{:>12}", name)); }
+    out.push('\n');
+    for (a, row) in corr.iter().enumerate() {
+        out.push_str(&format!("{:>12}", PLANE_NAMES[a]));
+        for r in row {
+            if r.is_nan() { out.push_str(&format!("{:>12}", "-")); } else { out.push_str(&format!("{:>12.3}", r)); }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Collapse runs of identical rendered bars (one per line) into a single line
+// annotated with an `xN` multiplier, e.g. four identical bars become `... x4`.
+fn fold_bars(rendered: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut lines = rendered.lines().peekable();
+    while let Some(line) = lines.next() {
+        let mut count = 1;
+        while lines.peek() == Some(&line) {
+            lines.next();
+            count += 1;
+        }
+        if count > 1 { out.push(format!("{} x{}", line, count)); } else { out.push(line.to_string()); }
+    }
+    out.join("\n")
+}
+
+// Quantize a MIDI drum clip into one pattern slot of `base` and return the edited bytes.
+// The clip is stretched over the pattern's steps: on a 32-step layout a one-bar clip maps
+// each beat to 8 steps, a two-bar clip to 4. With `into_track` every note lands on that track, otherwise notes are
+// routed through `drum_notes` (index = track). Off-grid notes snap to the nearest step.
+fn import_midi(base: &[u8], offsets: &Offsets, layout: &Layout, clip: &midi::MidiClip, into_track: Option<usize>,
+               into_pattern: usize, drum_notes: &[u8]) -> io::Result<Vec<u8>> {
+    if into_pattern >= layout.patterns {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Pattern {} out of range (0..{})", into_pattern, layout.patterns.saturating_sub(1))));
+    }
+    if let Some(t) = into_track {
+        if t >= layout.tracks {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Track {} out of range (0..{})", t, layout.tracks.saturating_sub(1))));
+        }
+    }
+    let mut drums = DrumData::from_bytes_with(base, offsets, layout)?;
+
+    let tpq = clip.ticks_per_quarter as u64;
+    let bar = 4 * tpq;
+    let last_tick = clip.notes.iter().map(|n| n.tick as u64).max().unwrap_or(0);
+    let bars: u64 = if last_tick < bar { 1 } else { 2 };
+    if last_tick >= 2 * bar {
+        warn!("MIDI clip is longer than two bars; notes past bar 2 are dropped");
+    }
+    let span = bars * bar; // ticks covered by the pattern's steps
+
+    let target_tracks: Vec<usize> = match into_track {
+        Some(t) => vec![t],
+        None => (0..layout.tracks).filter(|&t| t < drum_notes.len()).collect(),
+    };
+    for &t in &target_tracks {
+        for st in drums.tracks[t].patterns[into_pattern].steps.iter_mut() {
+            *st = Step::rest();
+        }
+    }
+
+    for n in &clip.notes {
+        let track = match into_track {
+            Some(t) => t,
+            None => match drum_notes.iter().position(|&d| d == n.note) {
+                Some(t) if t < layout.tracks => t,
+                _ => {
+                    warn!("MIDI note {} at tick {} is not mapped to a drum track; skipped", n.note, n.tick);
+                    continue;
+                }
+            },
+        };
+        let scaled = n.tick as u64 * layout.steps as u64;
+        let step = ((scaled + span / 2) / span) as usize;
+        if step >= layout.steps { continue; }
+        if !scaled.is_multiple_of(span) {
+            warn!("MIDI note {} at tick {} is off-grid; snapped to step {}", n.note, n.tick, step);
+        }
+        drums.tracks[track].patterns[into_pattern].steps[step] = Step::hit(n.velocity.clamp(1, 127));
+    }
+
+    let mut out = base.to_vec();
+    drums.to_bytes(offsets, &mut out)?;
+    Ok(out)
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Inspect Novation Circuit Tracks .ncs session files")]
+#[command(after_help = "Exit codes:\n  0  success\n  1  I/O error or malformed file\n  2  invalid command-line arguments\n  3  --strict: the file parsed but has validation warnings")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    dump: DumpArgs,
+    /// More diagnostics on stderr: -v parsed field values, -vv debug, -vvv trace; overrides RUST_LOG
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only report errors on stderr: no warnings, no -v field values
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Args, Debug)]
+struct DumpArgs {
+    /// Path to the .ncs session file, or `-` for stdin
+    #[arg(required = true)]
+    file: Option<String>,
+    /// Collapse consecutive identical bars into one line with an `xN` multiplier
+    #[arg(long)]
+    fold_bars: bool,
+    /// Print each pattern's hits as `step:velocity` pairs instead of the glyph grid
+    #[arg(long, conflicts_with = "fold_bars")]
+    rle: bool,
+    /// Print each step's mask bits (with guessed lock names) and sample choice under each pattern
+    #[arg(long)]
+    show_locks: bool,
+    /// Print the pitch/decay/distortion/eq planes under each pattern
+    #[arg(long)]
+    show_params: bool,
+    #[command(flatten)]
+    profile: ProfileArgs,
+    /// Print the whole parsed session as one JSON document instead of the dump
+    #[arg(long)]
+    json: bool,
+    /// Print one CSV row per step (track, pattern, step, then every plane) instead of the dump
+    #[arg(long, conflicts_with = "json")]
+    csv: bool,
+    /// Write the --csv rows to this file instead of stdout
+    #[arg(long, conflicts_with = "json")]
+    csv_out: Option<String>,
+    /// Draw one pattern slot as an SVG piano roll to this path
+    #[arg(long)]
+    svg: Option<String>,
+    /// Write a self-contained HTML report (summary, scenes, every non-empty pattern) to this path
+    #[arg(long)]
+    html: Option<String>,
+    /// Only this track in the SVG (all tracks when omitted)
+    #[arg(long, requires = "svg")]
+    svg_track: Option<usize>,
+    /// Pattern slot drawn in the SVG
+    #[arg(long, requires = "svg", default_value_t = 0)]
+    svg_pattern: usize,
+    /// Fail (exit code 3) instead of warning when a value is out of range
+    #[arg(long)]
+    strict: bool,
+    /// Refuse to parse if the step planes overlap or outgrow their strides
+    #[arg(long)]
+    strict_planes: bool,
+    /// Print per-pattern onset count, density, mean hit velocity and probability spread
+    /// (with --json, add them to the document)
+    #[arg(long)]
+    stats: bool,
+    /// Report empty pattern slots and slots that duplicate an earlier one
+    #[arg(long)]
+    analyze: bool,
+    /// List contiguous runs of identified and unidentified bytes with their offset ranges
+    #[arg(long)]
+    coverage_map: bool,
+    /// Show, per track, how many patterns hit each step (0..8) as a heatmap
+    #[arg(long)]
+    planes_heatmap: bool,
+    /// Show a tracks x patterns grid shaded by each pattern's onset density
+    #[arg(long)]
+    heatmap: bool,
+    /// Print the scene table: each scene's 8 entries as start..end pattern ranges
+    #[arg(long)]
+    scenes: bool,
+    /// With --scenes, leave out scenes whose entries are all 0..0
+    #[arg(long, requires = "scenes")]
+    nonempty_scenes: bool,
+    /// Expand the scene chain and pattern chains into the order they play in
+    #[arg(long)]
+    play_order: bool,
+    /// How to interpret the timing spare1 dword
+    #[arg(long, value_enum, default_value_t = SpareSemantics::Zero)]
+    spare_semantics: SpareSemantics,
+    /// Glyph drawn for rests
+    #[arg(long, default_value = ".")]
+    rest_glyph: String,
+    /// Hit glyphs from quietest to loudest, one character per level
+    #[arg(long, default_value = "▁▃▅█")]
+    hit_glyph_set: String,
+    /// Velocity where each hit glyph starts: `even` (default), `musical` (40,80,110),
+    /// or one comma-separated threshold per glyph after the first
+    #[arg(long, value_parser = SymbolScale::parse)]
+    velocity_levels: Option<SymbolScale>,
+    /// Keep each pattern on one line with this separator between bars
+    #[arg(long)]
+    bar_sep: Option<String>,
+    /// Print step numbers 1..8 over every bar and a dashed rule between bars
+    #[arg(long, conflicts_with_all = ["fold_bars", "rle"])]
+    ruler: bool,
+    /// Colour hits by velocity with ANSI escapes
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Write the probability digit as `probability % 10` like older releases
+    #[arg(long)]
+    legacy_prob_digit: bool,
+    /// How to write each hit's probability in the grid
+    #[arg(long, value_enum, default_value_t = ProbFormat::Digit)]
+    prob_format: ProbFormat,
+    /// Drum track names: `default` for the built-in kit, or comma-separated names in track order
+    #[arg(long, value_parser = TrackNames::parse)]
+    track_names: Option<TrackNames>,
+    /// TOML file naming each track's sample choices: `tracks = [["Kick 1", ...], ...]`
+    #[arg(long, value_parser = ChoiceNames::load)]
+    choice_names: Option<ChoiceNames>,
+    /// Log every field read during parsing to stderr at debug level, e.g. `velocity[t2,p2,s0] @ 0x0DE5C = 0x60`
+    #[arg(long)]
+    trace: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the human-readable dump (the default when only a file is given)
+    Dump(DumpArgs),
+    /// Print the whole parsed session as one JSON document
+    Json(JsonArgs),
+    /// Print how many bytes of the file the parsers understand
+    Coverage(SessionArgs),
+    /// Print only the drum pattern grids
+    Render(DumpArgs),
+    /// Import a clip into a pattern slot of a base session
+    Import(ImportArgs),
+    /// Export part of a session in another format
+    Export(ExportArgs),
+    /// Write the timing feel from a groove template into a session
+    ApplyGroove(ApplyGrooveArgs),
+    /// Change the tempo (and optionally swing), leaving every other byte alone
+    SetTempo(SetTempoArgs),
+    /// Tap Enter along with a reference track and print its tempo for `set-tempo`
+    Tap,
+    /// Statistics over the step planes
+    #[command(subcommand)]
+    Stats(StatsCommand),
+    /// Make every track's pattern chain a contiguous start..end run: unplayable entries
+    /// become 0..0 and end < start ones are un-reversed.
+    ///
+    /// The 8 entries are one per track, so entries are fixed in place; nothing is packed
+    /// towards the front, since that would move a chain onto another track.
+    CompactChains(EditArgs),
+    /// Field-level differences between two sessions (or two directories with --dir)
+    Diff(DiffArgs),
+    /// List the step planes with their offsets, ranges and validation status
+    Planes,
+    /// Check single-byte fields with confirmed offsets against their legal ranges, optionally repairing them in place
+    Validate(ValidateArgs),
+    /// Slide over candidate offsets looking for a signed micro-timing plane
+    PlanesWindow(PlanesWindowArgs),
+    /// List offsets that differ between two profiles (built-in name or TOML path)
+    ProfileDiff {
+        /// First profile, e.g. `v1`
+        a: String,
+        /// Second profile
+        b: String,
+    },
+    /// Guess the order of the step planes that follow a known velocity offset
+    DetectPlaneOrder(DetectPlaneOrderArgs),
+    /// One-line summary of every .ncs file in a directory
+    Batch(BatchArgs),
+    /// List every track/pattern whose onsets match a rhythm like "x..x..x."
+    Find(FindArgs),
+    /// List pattern pairs whose rhythms are at least this similar (see Pattern::similarity)
+    Similar(SimilarArgs),
+    /// Print the ASCII grids of two sessions side by side, marking bars whose velocities differ
+    Compare(CompareArgs),
+    /// Count each byte value of one plane over every track, pattern and step
+    Histogram(HistogramArgs),
+    /// Report every reserved, spare or padding field that is not zero
+    Integrity(SessionArgs),
+    /// Recover the drum tracks that fit in a truncated file and report where it ends
+    Salvage(SessionArgs),
+    /// Print a CRC-32 or SHA-256 of the file and verify the profile's stored checksum, if any
+    Checksum(ChecksumArgs),
+    /// Copy one pattern (all planes) to another track/slot
+    Copy(CopyArgs),
+    /// Save one pattern (all eight planes) as a fragment file for a pattern bank
+    Extract(ExtractArgs),
+    /// Write a fragment saved by `extract` into a pattern slot
+    Inject(InjectArgs),
+    /// Layer two patterns into a destination slot; the louder hit wins on each step
+    Merge(MergeArgs),
+    /// Rotate, shift or reverse the steps of one pattern
+    Edit(PatternEditArgs),
+    /// Multiply the velocity of every hit on a track, keeping rests as rests
+    ScaleVelocity(ScaleVelocityArgs),
+    /// Synthesize a rough WAV of a pattern at the session tempo/swing
+    Preview(PreviewArgs),
+    /// Change the session name stored in the file header
+    Rename(RenameArgs),
+    /// Set the global master volume (provisional offset)
+    SetVolume(SetVolumeArgs),
+    /// Revert the last change recorded in an edit journal
+    Undo(UndoArgs),
+}
+
+#[derive(Args, Debug)]
+struct SessionArgs {
+    /// Path to the .ncs session file
+    file: String,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+// Which offset profile (and so which layout) a session is parsed with. With neither flag
+// the version is detected from each file.
+#[derive(Args, Debug, Clone, Default)]
+struct ProfileArgs {
+    /// Offset profile: a built-in name (v1) or a TOML/JSON file; defaults to the detected version
+    #[arg(long)]
+    offsets: Option<String>,
+    /// Parse as this format version instead of detecting it from the file
+    #[arg(long, conflicts_with = "offsets", value_parser = clap::builder::PossibleValuesParser::new(BUILTIN_PROFILES))]
+    force_version: Option<String>,
+}
+
+impl ProfileArgs {
+    // Read a session and the profile it should be parsed with, as load_session_file does.
+    fn load(&self, path: &str) -> io::Result<(Vec<u8>, OffsetProfile)> {
+        let (data, profile, _) = load_session_file(path, self.offsets.as_deref(), self.force_version.as_deref())?;
+        Ok((data, profile))
+    }
+
+    // The profile for a session already in memory.
+    fn select(&self, data: &[u8]) -> io::Result<OffsetProfile> {
+        check_magic(data)?;
+        let (profile, _) = select_profile(data, self.offsets.as_deref(), self.force_version.as_deref())?;
+        validate_header(data, &profile)?;
+        Ok(profile)
+    }
+
+    fn session(&self, data: &[u8]) -> io::Result<Session> {
+        Ok(Session::from_bytes_with(data, &self.select(data)?)?)
+    }
+
+    fn drums(&self, path: &str) -> io::Result<DrumData> {
+        let (data, profile) = self.load(path)?;
+        Ok(DrumData::from_bytes_with(&data, &profile.drums, &profile.layout)?)
+    }
+}
+
+#[derive(Args, Debug)]
+struct JsonArgs {
+    #[command(flatten)]
+    session: SessionArgs,
+    /// Add per-pattern onset/density/velocity/probability statistics
+    #[arg(long)]
+    stats: bool,
+    /// Fail (exit code 3) instead of warning when a value is out of range
+    #[arg(long)]
+    strict: bool,
+    /// Drum track names: `default` for the built-in kit, or comma-separated names in track order
+    #[arg(long, value_parser = TrackNames::parse)]
+    track_names: Option<TrackNames>,
+    /// TOML file naming each track's sample choices: `tracks = [["Kick 1", ...], ...]`
+    #[arg(long, value_parser = ChoiceNames::load)]
+    choice_names: Option<ChoiceNames>,
+}
+
+#[derive(Args, Debug)]
+struct SetTempoArgs {
+    #[command(flatten)]
+    edit: EditArgs,
+    /// Tempo in BPM (40..240)
+    #[arg(long)]
+    tempo: u8,
+    /// Swing percentage (20..80); unchanged when omitted
+    #[arg(long)]
+    swing: Option<u8>,
+}
+
+#[derive(Args, Debug)]
+struct SetVolumeArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Master volume level (0..127)
+    level: u8,
+    /// Output .ncs path
+    #[arg(short, long)]
+    out: String,
+    /// Append the applied change to this journal so `undo` can revert it
+    #[arg(long)]
+    journal: Option<String>,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct ValidateArgs {
+    #[command(flatten)]
+    session: SessionArgs,
+    /// Repair illegal fields and atomically replace the file (original kept as <file>.bak)
+    #[arg(long)]
+    fix: bool,
+}
+
+#[derive(Args, Debug)]
+struct PlanesWindowArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// First candidate offset for track 0 pattern 0 (default: just past the eq plane)
+    #[arg(long, value_parser = parse_offset)]
+    from: Option<usize>,
+    /// End of the candidate window, exclusive (default: end of the pattern block)
+    #[arg(long, value_parser = parse_offset)]
+    to: Option<usize>,
+    /// How many of the best candidates to print
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+    /// Largest fraction of rests a candidate may leave non-zero (0..1)
+    #[arg(long, default_value_t = DEFAULT_TOLERANCE, value_parser = parse_tolerance)]
+    tolerance: f64,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct DetectPlaneOrderArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Velocity plane offset of track 0 pattern 0 (hex with 0x or decimal; default: the profile's)
+    #[arg(long, value_parser = parse_offset)]
+    velocity: Option<usize>,
+    /// Largest fraction of steps a step or constant plane may disagree on (0..1)
+    #[arg(long, default_value_t = DEFAULT_TOLERANCE, value_parser = parse_tolerance)]
+    tolerance: f64,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct UndoArgs {
+    /// Session the journaled edit was written to
+    file: String,
+    /// Journal written by --journal on an edit command
+    #[arg(long)]
+    journal: String,
+    /// Output .ncs path
+    #[arg(short, long)]
+    out: String,
+}
+
+#[derive(Args, Debug)]
+struct RenameArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// New name (printable ASCII, up to 32 characters; the rest of the field is NUL-padded)
+    name: String,
+    /// Output .ncs path
+    #[arg(short, long)]
+    out: String,
+    /// Append the applied change to this journal so `undo` can revert it
+    #[arg(long)]
+    journal: Option<String>,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct PreviewArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Only this drum track (all tracks mixed when omitted)
+    #[arg(long)]
+    track: Option<usize>,
+    /// Pattern slot (0..7)
+    #[arg(long)]
+    pattern: usize,
+    /// Output .wav path
+    #[arg(short, long)]
+    out: String,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct ScaleVelocityArgs {
+    #[command(flatten)]
+    edit: EditArgs,
+    /// Drum track (0..3)
+    #[arg(long)]
+    track: usize,
+    /// Velocity multiplier, e.g. 0.8
+    #[arg(long)]
+    factor: f32,
+}
+
+#[derive(Args, Debug)]
+struct BatchArgs {
+    /// Directory of .ncs session files
+    dir: String,
+}
+
+#[derive(Args, Debug)]
+struct HistogramArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Plane to count
+    #[arg(long, default_value = "velocity", value_parser = clap::builder::PossibleValuesParser::new(PLANE_NAMES))]
+    plane: String,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct SimilarArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Minimum score, 0.0..1.0: onsets shared by both patterns, weighted by how close their velocities are
+    #[arg(long, default_value_t = 0.8)]
+    threshold: f32,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct ChecksumArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Hash a gzip backup's decompressed session instead of the bytes on disk
+    #[arg(long)]
+    decompressed: bool,
+    /// Digest of the whole file to print
+    #[arg(long, value_enum, default_value_t = ChecksumAlgorithm::Sha256)]
+    algorithm: ChecksumAlgorithm,
+    /// Offset profile (TOML/JSON) whose `[checksum]` section locates a stored checksum to verify
+    #[arg(long)]
+    offsets: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// Left-hand session
+    a: String,
+    /// Right-hand session
+    b: String,
+    /// Profile for both sessions (each is detected on its own by default)
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct FindArgs {
+    /// Query over the first steps: `x` a hit, `X` a hit at velocity 64 or more, `.` a rest
+    #[arg(long)]
+    rhythm: String,
+    /// Session files to search
+    #[arg(required = true)]
+    files: Vec<String>,
+    /// Match on hit placement only, so `X` matches quiet hits too
+    #[arg(long)]
+    ignore_velocity: bool,
+    /// Profile for every file (each is detected on its own by default)
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct CopyArgs {
+    #[command(flatten)]
+    edit: EditArgs,
+    /// Source as track:pattern, e.g. 1:0
+    #[arg(long, value_parser = parse_slot)]
+    from: (usize, usize),
+    /// Destination as track:pattern
+    #[arg(long, value_parser = parse_slot)]
+    to: (usize, usize),
+}
+
+#[derive(Args, Debug)]
+struct ExtractArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Drum track (0..3)
+    #[arg(long)]
+    track: usize,
+    /// Pattern slot (0..7)
+    #[arg(long)]
+    pattern: usize,
+    /// Output fragment path
+    #[arg(short, long)]
+    out: String,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct InjectArgs {
+    #[command(flatten)]
+    edit: EditArgs,
+    /// Fragment written by `extract`
+    #[arg(long)]
+    fragment: String,
+    /// Destination track (default: the track the fragment came from)
+    #[arg(long)]
+    track: Option<usize>,
+    /// Destination pattern slot (default: the slot the fragment came from)
+    #[arg(long)]
+    pattern: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    #[command(flatten)]
+    edit: EditArgs,
+    /// First pattern as track:pattern; wins ties
+    #[arg(long, value_parser = parse_slot)]
+    a: (usize, usize),
+    /// Second pattern as track:pattern
+    #[arg(long, value_parser = parse_slot)]
+    b: (usize, usize),
+    /// Destination as track:pattern (may be one of the sources)
+    #[arg(long, value_parser = parse_slot)]
+    to: (usize, usize),
+}
+
+fn parse_slot(s: &str) -> Result<(usize, usize), String> {
+    let (t, p) = s.split_once(':').ok_or_else(|| format!("expected track:pattern, got {:?}", s))?;
+    let num = |v: &str| v.trim().parse::<usize>().map_err(|e| format!("{:?}: {}", v, e));
+    Ok((num(t)?, num(p)?))
+}
+
+#[derive(Args, Debug)]
+struct PatternEditArgs {
+    #[command(flatten)]
+    edit: EditArgs,
+    /// Drum track (0..3); --clean-prob covers every track when omitted
+    #[arg(long, required_unless_present = "clean_prob")]
+    track: Option<usize>,
+    /// Pattern slot (0..7); --clean-prob covers every slot when omitted
+    #[arg(long, required_unless_present = "clean_prob")]
+    pattern: Option<usize>,
+    /// Random seed for --humanize (default 0); the same seed always gives the same output
+    #[arg(long, conflicts_with_all = ["rotate", "shift", "reverse", "vel_scale", "prob_scale", "clean_prob"])]
+    seed: Option<u64>,
+    #[command(flatten)]
+    op: PatternOp,
+}
+
+// Exactly one transform per invocation.
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+struct PatternOp {
+    /// Move steps this many positions later, wrapping around (negative: earlier)
+    #[arg(long, allow_hyphen_values = true)]
+    rotate: Option<isize>,
+    /// Move steps this many positions later, filling with rests (negative: earlier)
+    #[arg(long, allow_hyphen_values = true)]
+    shift: Option<isize>,
+    /// Play the pattern backwards
+    #[arg(long)]
+    reverse: bool,
+    /// Multiply hit velocities (clamped to 1..127)
+    #[arg(long)]
+    vel_scale: Option<f32>,
+    /// Multiply hit probabilities (clamped to 0..7)
+    #[arg(long)]
+    prob_scale: Option<f32>,
+    /// Nudge each hit's velocity by up to this much either way (clamped to 1..127)
+    #[arg(long)]
+    humanize: Option<u8>,
+    /// Snap hit probabilities outside 0..7 to the nearest valid value, warning for each
+    #[arg(long)]
+    clean_prob: bool,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// First session (or directory with --dir)
+    a: String,
+    /// Second session (or directory with --dir)
+    b: String,
+    /// Compare two directories, pairing .ncs files by file name
+    #[arg(long)]
+    dir: bool,
+    /// Show per-plane track x pattern heatmaps of how many steps changed
+    #[arg(long, conflicts_with = "dir")]
+    heatmap: bool,
+    /// Drum track names for the heatmap rows: `default` for the built-in kit, or comma-separated names in track order
+    #[arg(long, value_parser = TrackNames::parse, requires = "heatmap")]
+    track_names: Option<TrackNames>,
+    /// Profile for both sessions (each is detected on its own by default)
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+// Common shape of in-place editors: read a session, write the edited copy elsewhere.
+#[derive(Args, Debug)]
+struct EditArgs {
+    /// Path to the .ncs session file
+    file: String,
+    /// Output .ncs path
+    #[arg(short, long)]
+    out: String,
+    /// Append the applied change to this journal so `undo` can revert it
+    #[arg(long)]
+    journal: Option<String>,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+impl EditArgs {
+    fn load(&self) -> io::Result<(Vec<u8>, OffsetProfile)> {
+        self.profile.load(&self.file)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum StatsCommand {
+    /// Pairwise correlation of the eight planes over active steps
+    Corr {
+        /// Path to the .ncs session file
+        file: String,
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+    /// Per-plane min/max/mean, distinct values, constancy and rest contamination
+    Planes {
+        /// Path to the .ncs session file
+        file: String,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+        /// Hide planes with fewer distinct values than this
+        #[arg(long, default_value_t = 0)]
+        min_distinct: usize,
+        /// Hide planes where fewer than this percentage of hits carry a nonzero value
+        #[arg(long, default_value_t = 0.0)]
+        min_nonzero: f64,
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+    /// Planes ranked by Shannon entropy of their values over active steps
+    Entropy {
+        /// Path to the .ncs session file
+        file: String,
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+    /// Report track pairs that duplicate each other's programming
+    Mirrors {
+        /// Path to the .ncs session file
+        file: String,
+        /// Minimum fraction of matching steps (1.0 = identical tracks)
+        #[arg(long, default_value_t = 1.0)]
+        threshold: f64,
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ImportFormat {
+    Midi,
+}
+
+#[derive(Args, Debug)]
+struct ImportArgs {
+    /// Clip to import
+    input: String,
+    #[arg(long, value_enum, default_value_t = ImportFormat::Midi)]
+    format: ImportFormat,
+    /// Put every note on this track instead of routing by --drum-notes
+    #[arg(long)]
+    into_track: Option<usize>,
+    /// Destination pattern slot (0..7)
+    #[arg(long)]
+    into_pattern: usize,
+    /// Session used as the template for the output
+    #[arg(long)]
+    base: String,
+    /// Output .ncs path
+    #[arg(short, long)]
+    out: String,
+    /// Append the applied change to this journal so `undo` can revert it
+    #[arg(long)]
+    journal: Option<String>,
+    /// MIDI note per drum track, in track order
+    #[arg(long, value_delimiter = ',', default_values_t = [60u8, 62, 64, 65])]
+    drum_notes: Vec<u8>,
+    /// Profile for the base session
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ExportFormat {
+    /// Tempo/swing template as TOML (see `apply-groove`)
+    Groove,
+    /// One CSV matrix per plane (rows = track/pattern, columns = steps) into the -o directory
+    Planes,
+    /// Pitch/decay/distortion/eq planes of one pattern as MIDI CC lanes (.mid)
+    MidiCc,
+    /// Whole session as a protobuf message (schema in proto/session.proto)
+    Protobuf,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// Path to the .ncs session file
+    file: String,
+    #[arg(long, value_enum)]
+    format: ExportFormat,
+    /// Output path (stdout when omitted)
+    #[arg(short, long)]
+    out: Option<String>,
+    /// Only this drum track (midi-cc; all tracks when omitted)
+    #[arg(long)]
+    track: Option<usize>,
+    /// Pattern slot to export (midi-cc)
+    #[arg(long, default_value_t = 0)]
+    pattern: usize,
+    /// Write 1 for active steps and 0 for rests instead of plane values (planes)
+    #[arg(long)]
+    binarize: bool,
+    /// Drum track names: `default` for the built-in kit, or comma-separated names in track order
+    #[arg(long, value_parser = TrackNames::parse)]
+    track_names: Option<TrackNames>,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+#[derive(Args, Debug)]
+struct ApplyGrooveArgs {
+    /// Groove template written by `export --format groove`
+    groove: String,
+    /// Session to apply the groove to
+    file: String,
+    /// Output .ncs path
+    #[arg(short, long)]
+    out: String,
+    /// Append the applied change to this journal so `undo` can revert it
+    #[arg(long)]
+    journal: Option<String>,
+    #[command(flatten)]
+    profile: ProfileArgs,
+}
+
+// Exit code for `--strict` warnings; 2 is taken by clap for usage errors.
+const EXIT_STRICT: i32 = 3;
+
+fn main() {
+    let cli = Cli::parse();
+    let trace = match &cli.command {
+        Some(Command::Dump(args) | Command::Render(args)) => args.trace,
+        Some(_) => false,
+        None => cli.dump.trace,
+    };
+    logging::init(logging::builder(cli.verbose, cli.quiet, trace, std::env::var("RUST_LOG").ok().as_deref()));
+    if let Err(e) = run(cli) {
+        eprintln!("error: {}", error_message(&e));
+        std::process::exit(exit_code(&e));
+    }
+}
+
+// Parse failures get a hint on what to check; everything else prints as-is.
+fn error_message(e: &io::Error) -> String {
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<NcsError>()) {
+        Some(NcsError::Truncated { .. }) => format!("{} (is the file truncated or the offset config wrong?)", e),
+        Some(NcsError::BadMagic { .. }) => format!("{} (is this a .ncs file saved by Components?)", e),
+        Some(NcsError::OutOfRange { .. }) => format!("{} (check the value passed on the command line)", e),
+        Some(NcsError::OffsetOverflow { .. }) => format!("{} (is the offset config for this file version?)", e),
+        Some(NcsError::Io(_)) | None => e.to_string(),
+    }
+}
+
+fn exit_code(e: &io::Error) -> i32 {
+    if e.get_ref().is_some_and(|inner| inner.is::<StrictWarnings>()) { EXIT_STRICT } else { 1 }
+}
+
+// Print the warnings as usual, or fail with them in strict mode. Provisional ones are
+// still printed under --strict, as they never fail it.
+fn report_warnings(warnings: &[ValidationWarning], strict: bool) -> io::Result<()> {
+    if !strict {
+        print_warnings(warnings);
+        return Ok(());
+    }
+    let provisional: Vec<ValidationWarning> = warnings.iter().filter(|w| w.category == WarningCategory::Provisional).cloned().collect();
+    print_warnings(&provisional);
+    reject_warnings(warnings)
+}
+
+fn run(cli: Cli) -> io::Result<()> {
+    match cli.command {
+        Some(Command::Dump(args)) => dump(&args, true),
+        Some(Command::Render(args)) => dump(&args, false),
+        Some(Command::Json(args)) => {
+            let (data, profile) = args.session.profile.load(&args.session.file)?;
+            let (session, warnings) = Session::from_bytes_checked(&data, &profile)?;
+            report_warnings(&warnings, args.strict)?;
+            println!("{}", session_json(&session, args.stats, &args.track_names.clone().unwrap_or_default(), &args.choice_names.clone().unwrap_or_default())?);
+            Ok(())
+        }
+        Some(Command::Coverage(args)) => {
+            let (data, profile) = args.profile.load(&args.file)?;
+            println!("{}", coverage_line(&data, &profile.drums, &profile.layout, &profile.fx));
+            Ok(())
+        }
+        Some(Command::Import(args)) => import(&args),
+        Some(Command::Export(args)) => export(&args),
+        Some(Command::ApplyGroove(args)) => apply_groove(&args),
+        Some(Command::SetTempo(args)) => set_tempo(&args),
+        Some(Command::Tap) => tap::run(),
+        Some(Command::Stats(cmd)) => stats(&cmd),
+        Some(Command::CompactChains(args)) => compact_chains(&args),
+        Some(Command::Diff(args)) => diff(&args),
+        Some(Command::ScaleVelocity(args)) => scale_velocity(&args),
+        Some(Command::Edit(args)) => edit_pattern(&args),
+        Some(Command::Copy(args)) => copy_pattern(&args),
+        Some(Command::Extract(args)) => {
+            let (data, profile) = args.profile.load(&args.file)?;
+            let frag = fragment::Fragment::extract(&data, &profile.drums, &profile.layout, args.track, args.pattern)?;
+            std::fs::write(&args.out, frag.to_bytes())
+        }
+        Some(Command::Inject(args)) => inject_fragment(&args),
+        Some(Command::Merge(args)) => merge_patterns(&args),
+        Some(Command::Batch(args)) => batch(&args),
+        Some(Command::Find(args)) => find(&args),
+        Some(Command::Similar(args)) => {
+            let pairs = similar_patterns(&args.profile.drums(&args.file)?, args.threshold);
+            if pairs.is_empty() { println!("No pattern pairs at or above {:.2}", args.threshold); }
+            for ((ta, pa), (tb, pb), score) in pairs {
+                println!("T{} P{:02} ~ T{} P{:02}  {:.3}", ta, pa, tb, pb, score);
+            }
+            Ok(())
+        }
+        Some(Command::Compare(args)) => {
+            print!("{}", render_compare(&args.profile.drums(&args.a)?, &args.profile.drums(&args.b)?));
+            Ok(())
+        }
+        Some(Command::Salvage(args)) => salvage(&args),
+        Some(Command::Checksum(args)) => checksum(&args),
+        Some(Command::Integrity(args)) => {
+            let (data, profile) = args.profile.load(&args.file)?;
+            print!("{}", render_integrity(&reserved_warnings(&data, &profile)?));
+            Ok(())
+        }
+        Some(Command::Histogram(args)) => {
+            let plane = PLANE_NAMES.iter().position(|n| *n == args.plane).unwrap_or(0);
+            print!("{}", render_histogram(&plane_histogram(&args.profile.drums(&args.file)?, plane)));
+            Ok(())
+        }
+        Some(Command::Preview(args)) => preview(&args),
+        Some(Command::Rename(args)) => rename(&args),
+        Some(Command::SetVolume(args)) => set_volume(&args),
+        Some(Command::Undo(args)) => undo(&args),
+        Some(Command::Validate(args)) => validate(&args),
+        Some(Command::ProfileDiff { a, b }) => {
+            let diffs = profile_diff(&OffsetProfile::load(&a)?, &OffsetProfile::load(&b)?)?;
+            if diffs.is_empty() { println!("Profiles are identical"); }
+            for (field, x, y) in diffs {
+                let delta = y - x;
+                println!("{:<36} 0x{:05X} -> 0x{:05X} ({}0x{:X})", field, x, y, if delta < 0 { "-" } else { "+" }, delta.abs());
+            }
+            Ok(())
+        }
+        Some(Command::PlanesWindow(args)) => planes_window(&args),
+        Some(Command::DetectPlaneOrder(args)) => {
+            let (data, profile) = args.profile.load(&args.file)?;
+            let off = Offsets { velocity: args.velocity.unwrap_or(profile.drums.velocity), ..profile.drums };
+            print!("{}", render_plane_order(&detect_plane_order(&data, &off, &profile.layout, args.tolerance)?));
+            Ok(())
+        }
+        Some(Command::Planes) => { print!("{}", render_plane_list(&Offsets::default())); Ok(()) }
+        None => dump(&cli.dump, true),
+    }
+}
+
+fn planes_window(args: &PlanesWindowArgs) -> io::Result<()> {
+    let (data, profile) = args.profile.load(&args.file)?;
+    let off = &profile.drums;
+    let from = args.from.unwrap_or(off.eq.saturating_add(profile.layout.steps));
+    let to = args.to.unwrap_or(off.velocity.saturating_add(off.pattern_stride));
+    let scores = micro_timing_scores(&data, off, &profile.layout, from, to, args.tolerance)?;
+    println!("{:>8} {:>7}", "offset", "score");
+    for (cand, score) in scores.iter().take(args.top) {
+        println!("{:>8} {:>7.3}", format!("0x{:04X}", cand), score);
+    }
+    if let Some((best, _)) = scores.first() {
+        println!("Best micro-timing candidate: 0x{:04X}", best);
+    }
+    Ok(())
+}
+
+fn validate(args: &ValidateArgs) -> io::Result<()> {
+    let s = &args.session;
+    let fixes = validate_session(&s.file, s.profile.offsets.as_deref(), s.profile.force_version.as_deref(), args.fix)?;
+    if fixes.is_empty() {
+        println!("No illegal fields");
+        return Ok(());
+    }
+    for f in &fixes {
+        println!("{} {}", if args.fix { "fixed" } else { "illegal" }, f);
+    }
+    if args.fix {
+        println!("{} fields repaired; original saved as {}.bak", fixes.len(), s.file);
+    }
+    Ok(())
+}
+
+// Like dump for a file that is too short for its layout: no bounds check, just the
+// leading tracks that are fully present, then their patterns.
+fn salvage(args: &SessionArgs) -> io::Result<()> {
+    let data = read_file(&args.file)?;
+    check_magic(&data)?;
+    let (profile, _) = select_profile(&data, args.profile.offsets.as_deref(), args.profile.force_version.as_deref())?;
+    let r = ByteReader::new(&data);
+    let drums = DrumData::parse_partial(&r, &profile.drums, &profile.layout)?;
+    print_warnings(&r.take_warnings());
+    println!("{}", salvage_summary(drums.tracks.len(), profile.layout.tracks, data.len()));
+    for (t, track) in drums.tracks.iter().enumerate() {
+        for (p, patt) in track.patterns.iter().enumerate().filter(|(_, patt)| !patt.is_empty()) {
+            println!("=== T{} P{:02} ===\n{}", t, p, render_ascii(&patt.steps, true));
+        }
+    }
+    Ok(())
+}
+
+fn salvage_summary(parsed: usize, total: usize, len: usize) -> String {
+    if parsed == total {
+        format!("Parsed all {} tracks; the drum data is complete", total)
+    } else {
+        format!("Parsed {} of {} tracks; file truncated at offset 0x{:X}", parsed, total, len)
+    }
+}
+
+fn checksum(args: &ChecksumArgs) -> io::Result<()> {
+    let hashed = if args.decompressed { read_file(&args.file)? } else { read_raw(&args.file)? };
+    println!("{}  {}  {}", args.algorithm.name(), digest::hex(&file_digest(args.algorithm, &hashed)), args.file);
+    // The built-in profiles have no checksum field, so only an explicit profile can add one
+    let Some(region) = args.offsets.as_deref().map(OffsetProfile::load).transpose()?.and_then(|p| p.checksum) else {
+        println!("No checksum field in this layout; whole-file digest only");
+        return Ok(());
+    };
+    // A stored checksum belongs to the session itself, so it is always checked decompressed
+    let data = if args.decompressed { hashed } else { read_file(&args.file)? };
+    let (stored, computed) = stored_checksum(&data, &region)?;
+    let pass = stored == computed;
+    println!("Stored {} at 0x{:05X} over 0x{:05X}..0x{:05X}: stored {}, computed {}: {}",
+        region.algorithm.name(), region.stored, region.start, region.end, digest::hex(&stored), digest::hex(&computed),
+        if pass { "pass" } else { "FAIL" });
+    if !pass {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Stored checksum does not match the file"));
+    }
+    Ok(())
+}
+
+// CRC-32 as its big-endian value so it prints the way CRC tools show it.
+fn file_digest(algorithm: ChecksumAlgorithm, data: &[u8]) -> Vec<u8> {
+    match algorithm {
+        ChecksumAlgorithm::Crc32 => digest::crc32(data).to_be_bytes().to_vec(),
+        ChecksumAlgorithm::Sha256 => digest::sha256(data).to_vec(),
+    }
+}
+
+// (stored, computed) digests for a profile's checksum field, in file_digest's byte order.
+fn stored_checksum(data: &[u8], region: &ChecksumOffsets) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let width = region.algorithm.width();
+    if region.start > region.end || region.end > data.len() || region.stored.saturating_add(width) > data.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "Checksum field 0x{:X}+{} or range 0x{:X}..0x{:X} does not fit a 0x{:X}-byte file",
+            region.stored, width, region.start, region.end, data.len())));
+    }
+    let mut stored = data[region.stored..region.stored + width].to_vec();
+    if region.algorithm == ChecksumAlgorithm::Crc32 {
+        stored.reverse();
+    }
+    Ok((stored, file_digest(region.algorithm, &data[region.start..region.end])))
+}
+
+fn stats(cmd: &StatsCommand) -> io::Result<()> {
+    match cmd {
+        StatsCommand::Corr { file, profile } => print!("{}", render_correlation(&plane_correlation(&profile.drums(file)?))),
+        StatsCommand::Planes { file, format, min_distinct, min_nonzero, profile } => {
+            let stats = filter_plane_stats(plane_stats(&profile.drums(file)?), *min_distinct, *min_nonzero);
+            print!("{}", render_plane_stats(&stats, *format));
+        }
+        StatsCommand::Entropy { file, profile } => {
+            for (name, bits) in plane_entropy(&profile.drums(file)?) {
+                println!("{:<12} {:>6.3} bits", name, bits);
+            }
+        }
+        StatsCommand::Mirrors { file, threshold, profile } => {
+            let mirrors = mirrored_tracks(&profile.drums(file)?, *threshold);
+            if mirrors.is_empty() { println!("No mirrored tracks"); }
+            for (a, b, score) in mirrors {
+                println!("Track {} mirrors track {} ({:.1}% of steps match)", a, b, score * 100.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn diff(args: &DiffArgs) -> io::Result<()> {
+    if args.dir {
+        let d = diff_dirs(std::path::Path::new(&args.a), std::path::Path::new(&args.b), &args.profile)?;
+        for (name, n) in &d.changed { println!("changed   {} ({} fields)", name, n); }
+        for name in &d.unchanged { println!("same      {}", name); }
+        for name in &d.only_a { println!("only in {}: {}", args.a, name); }
+        for name in &d.only_b { println!("only in {}: {}", args.b, name); }
+        for (name, e) in &d.errors { println!("error     {}: {}", name, e); }
+        println!("{} changed, {} unchanged, {} unpaired, {} errors",
+                 d.changed.len(), d.unchanged.len(), d.only_a.len() + d.only_b.len(), d.errors.len());
+        return Ok(());
+    }
+    let (a, b) = (read_file(&args.a)?, read_file(&args.b)?);
+    if args.heatmap {
+        let (sa, sb) = (args.profile.session(&a)?, args.profile.session(&b)?);
+        print!("{}", render_diff_heatmap(&diff_heatmap(&sa.drums, &sb.drums), sa.drums.layout().steps, &args.track_names.clone().unwrap_or_default()));
+        return Ok(());
+    }
+    let diffs = diff_files(&a, &b, &args.profile)?;
+    if diffs.is_empty() { println!("No differences"); }
+    for d in diffs { println!("{}", d); }
+    Ok(())
+}
+
+fn rename_session(data: &mut [u8], name: &str, off: &NameOffsets) -> io::Result<()> {
+    SessionName::new(name, off)?.to_bytes(off, data)
+}
+
+fn rename(args: &RenameArgs) -> io::Result<()> {
+    let (mut data, profile) = args.profile.load(&args.file)?;
+    let original = data.clone();
+    rename_session(&mut data, &args.name, &profile.name)?;
+    write_edit(&args.out, &original, &data, args.journal.as_deref(), "rename")
+}
+
+fn set_master_volume(data: &mut [u8], level: u8, off: &GlobalsOffsets) -> io::Result<()> {
+    let mut globals = Globals::from_bytes(data, off)?;
+    globals.set_master_volume(level)?;
+    globals.to_bytes(off, data)
+}
+
+fn set_volume(args: &SetVolumeArgs) -> io::Result<()> {
+    let (mut data, profile) = args.profile.load(&args.file)?;
+    let original = data.clone();
+    set_master_volume(&mut data, args.level, &profile.globals)?;
+    write_edit(&args.out, &original, &data, args.journal.as_deref(), "set-volume")
+}
+
+fn preview_samples(session: &Session, track: Option<usize>, pattern: usize) -> io::Result<Vec<i16>> {
+    let layout = session.drums.layout();
+    if pattern >= layout.patterns || track.is_some_and(|t| t >= layout.tracks) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Track or pattern out of range"));
+    }
+    let tracks: Vec<(usize, Vec<u8>)> = (0..layout.tracks)
+        .filter(|&t| track.is_none_or(|only| only == t))
+        .map(|t| (t, session.drums.tracks[t].patterns[pattern].steps.iter().map(|st| st.velocity).collect()))
+        .collect();
+    Ok(preview::render(&tracks, session.timing.tempo, session.timing.swing))
+}
+
+fn preview(args: &PreviewArgs) -> io::Result<()> {
+    let (data, profile) = args.profile.load(&args.file)?;
+    let session = Session::from_bytes_with(&data, &profile)?;
+    let samples = preview_samples(&session, args.track, args.pattern)?;
+    let spec = hound::WavSpec { channels: 1, sample_rate: preview::SAMPLE_RATE, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut wav = hound::WavWriter::create(&args.out, spec).map_err(invalid_data)?;
+    for s in samples {
+        wav.write_sample(s).map_err(invalid_data)?;
+    }
+    wav.finalize().map_err(invalid_data)
+}
+
+fn scale_velocity(args: &ScaleVelocityArgs) -> io::Result<()> {
+    if !args.factor.is_finite() || args.factor < 0.0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid velocity factor: {}", args.factor)));
+    }
+    let (mut data, profile) = args.edit.load()?;
+    let original = data.clone();
+    let offsets = &profile.drums;
+    let mut drums = DrumData::from_bytes_with(&data, offsets, &profile.layout)?;
+    if args.track >= drums.tracks.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Track {} out of range (0..{})", args.track, drums.tracks.len().saturating_sub(1))));
+    }
+    for patt in drums.tracks[args.track].patterns.iter_mut() {
+        patt.scale_velocity(args.factor);
+    }
+    drums.to_bytes(offsets, &mut data)?;
+    write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), "scale-velocity")
+}
+
+fn copy_pattern(args: &CopyArgs) -> io::Result<()> {
+    let (mut data, profile) = args.edit.load()?;
+    let original = data.clone();
+    let mut drums = DrumData::from_bytes_with(&data, &profile.drums, &profile.layout)?;
+    drums.copy_pattern(args.from.0, args.from.1, args.to.0, args.to.1)?;
+    drums.to_bytes(&profile.drums, &mut data)?;
+    write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), "copy")
+}
+
+fn inject_fragment(args: &InjectArgs) -> io::Result<()> {
+    let frag = fragment::Fragment::from_bytes(&std::fs::read(&args.fragment)?)?;
+    let (mut data, profile) = args.edit.load()?;
+    let original = data.clone();
+    let track = args.track.unwrap_or(frag.track as usize);
+    let pattern = args.pattern.unwrap_or(frag.pattern as usize);
+    frag.inject(&mut data, &profile.drums, &profile.layout, track, pattern)?;
+    write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), "inject")
+}
+
+fn merge_patterns(args: &MergeArgs) -> io::Result<()> {
+    let (mut data, profile) = args.edit.load()?;
+    let original = data.clone();
+    let mut drums = DrumData::from_bytes_with(&data, &profile.drums, &profile.layout)?;
+    drums.merge_patterns(args.a, args.b, args.to)?;
+    drums.to_bytes(&profile.drums, &mut data)?;
+    write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), "merge")
+}
+
+// The padding checks the section parsers already run, without the range/order noise.
+fn reserved_warnings(data: &[u8], profile: &OffsetProfile) -> Result<Vec<ValidationWarning>, NcsError> {
+    let (_, warnings) = Session::from_bytes_checked(data, profile)?;
+    Ok(warnings.into_iter().filter(|w| w.category == WarningCategory::Padding).collect())
+}
+
+fn render_integrity(warnings: &[ValidationWarning]) -> String {
+    if warnings.is_empty() {
+        return "All reserved fields are zero\n".to_string();
+    }
+    let mut out = format!("{} reserved field(s) not zero:\n", warnings.len());
+    for w in warnings {
+        out.push_str(&format!("  0x{:05X}  {}\n", w.offset, w.message));
+    }
+    out
+}
+
+// Clean probabilities in place on the selected tracks/patterns (all when None) and
+// return one warning per changed step, located at its probability byte.
+fn clean_probabilities(data: &mut [u8], offsets: &Offsets, layout: &Layout, track: Option<usize>, pattern: Option<usize>) -> io::Result<Vec<ValidationWarning>> {
+    let mut drums = DrumData::from_bytes_with(data, offsets, layout)?;
+    let mut warnings = Vec::new();
+    for (t, tr) in drums.tracks.iter_mut().enumerate().filter(|(t, _)| track.is_none_or(|only| only == *t)) {
+        for (p, patt) in tr.patterns.iter_mut().enumerate().filter(|(p, _)| pattern.is_none_or(|only| only == *p)) {
+            for (s, old, new) in patt.clean_probability() {
+                warnings.push(ValidationWarning {
+                    category: WarningCategory::Range,
+                    offset: offsets.probability + t * offsets.track_stride + p * offsets.pattern_stride + s,
+                    message: format!("Track {} pattern {} step {}: probability {} snapped to {}", t, p, s, old, new),
+                });
+            }
+        }
+    }
+    drums.to_bytes(offsets, data)?;
+    Ok(warnings)
+}
+
+fn edit_pattern(args: &PatternEditArgs) -> io::Result<()> {
+    if let Some(f) = args.op.vel_scale.or(args.op.prob_scale).filter(|f| !f.is_finite() || *f < 0.0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid scale factor: {}", f)));
+    }
+    let (mut data, profile) = args.edit.load()?;
+    let original = data.clone();
+    let (offsets, layout) = (&profile.drums, &profile.layout);
+    if args.track.is_some_and(|t| t >= layout.tracks) || args.pattern.is_some_and(|p| p >= layout.patterns) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "Track {} pattern {} out of range (0..{}, 0..{})", args.track.unwrap_or(0), args.pattern.unwrap_or(0),
+            layout.tracks.saturating_sub(1), layout.patterns.saturating_sub(1))));
+    }
+    if args.op.clean_prob {
+        print_warnings(&clean_probabilities(&mut data, offsets, layout, args.track, args.pattern)?);
+        return write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), "clean-prob");
+    }
+    // clap requires both selectors for every other operation
+    let (Some(track), Some(pattern)) = (args.track, args.pattern) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--track and --pattern are required"));
+    };
+    let mut drums = DrumData::from_bytes_with(&data, offsets, layout)?;
+    let patt = &mut drums.tracks[track].patterns[pattern];
+    let op = &args.op;
+    let command = if let Some(n) = op.rotate {
+        patt.rotate(n);
+        "rotate"
+    } else if let Some(n) = op.shift {
+        patt.shift(n);
+        "shift"
+    } else if let Some(f) = op.vel_scale {
+        patt.scale_velocity(f);
+        "vel-scale"
+    } else if let Some(f) = op.prob_scale {
+        patt.scale_probability(f);
+        "prob-scale"
+    } else if let Some(n) = op.humanize {
+        patt.humanize(n, args.seed.unwrap_or(0));
+        "humanize"
+    } else {
+        patt.reverse();
+        "reverse"
+    };
+    drums.to_bytes(offsets, &mut data)?;
+    write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), command)
+}
+
+fn compact_chains(args: &EditArgs) -> io::Result<()> {
+    let (mut data, profile) = args.load()?;
+    let original = data.clone();
+    let off = &profile.chains;
+    let chains = PatternChains::from_bytes(&data, off)?.normalized();
+    chains.to_bytes(off, &mut data)?;
+    // Re-read to make sure every entry now references a valid pattern
+    let check = PatternChains::from_bytes(&data, off)?;
+    if check.entries.iter().any(|e| e.start >= 8 || e.end >= 8 || e.end < e.start) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Normalized pattern chain still references invalid patterns"));
+    }
+    write_edit(&args.out, &original, &data, args.journal.as_deref(), "compact-chains")
+}
+
+fn import(args: &ImportArgs) -> io::Result<()> {
+    let (base, profile) = args.profile.load(&args.base)?;
+    let out = match args.format {
+        ImportFormat::Midi => {
+            let clip = midi::read_smf(&read_file(&args.input)?)?;
+            import_midi(&base, &profile.drums, &profile.layout, &clip, args.into_track, args.into_pattern, &args.drum_notes)?
+        }
+    };
+    write_edit(&args.out, &base, &out, args.journal.as_deref(), "import")
+}
+
+fn export(args: &ExportArgs) -> io::Result<()> {
+    let (data, profile) = args.profile.load(&args.file)?;
+    let text = match args.format {
+        ExportFormat::Planes => return export_planes(&data, &profile, args.out.as_deref(), args.binarize),
+        ExportFormat::MidiCc => {
+            let path = args.out.as_deref().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--format midi-cc needs -o <file.mid>"))?;
+            let drums = DrumData::from_bytes_with(&data, &profile.drums, &profile.layout)?;
+            return std::fs::write(path, sonify_planes(&drums, args.track, args.pattern, &args.track_names.clone().unwrap_or_default())?);
+        }
+        ExportFormat::Protobuf => {
+            let path = args.out.as_deref().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--format protobuf needs -o <file.pb>"))?;
+            return std::fs::write(path, proto::encode(&Session::from_bytes_with(&data, &profile)?));
+        }
+        ExportFormat::Groove => {
+            let timing = Timing::from_bytes(&data, &profile.timing)?;
+            toml::to_string(&Groove::from_timing(&timing)).map_err(invalid_data)?
+        }
+    };
+    match &args.out {
+        Some(path) => std::fs::write(path, text),
+        None => { print!("{}", text); Ok(()) }
+    }
+}
+
+// MIDI ticks per quarter note for exports; a step is a 16th note.
+const MIDI_TPQ: u16 = 96;
+const TICKS_PER_STEP: u32 = MIDI_TPQ as u32 / 4;
+
+// General-purpose controllers used for the parameter planes (pitch, decay, distortion, eq).
+const SONIFY_PLANES: [(usize, u8); 4] = [(4, 14), (5, 15), (6, 16), (7, 17)];
+
+// One MTrk per drum track (channel = track index, named after the track) with a CC per
+// sonified plane at every active step, the plane byte scaled from 0..255 to 0..127.
+fn sonify_planes(drums: &DrumData, track: Option<usize>, pattern: usize, names: &TrackNames) -> io::Result<Vec<u8>> {
+    Ok(midi::write_smf(MIDI_TPQ, &sonify_lanes(drums, track, pattern, names)?))
+}
+
+// The (tick, event) lists sonify_planes writes, one per MTrk.
+fn sonify_lanes(drums: &DrumData, track: Option<usize>, pattern: usize, names: &TrackNames) -> io::Result<Vec<midi::TrackEvents>> {
+    let layout = drums.layout();
+    if pattern >= layout.patterns || track.is_some_and(|t| t >= layout.tracks) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Track or pattern out of range"));
+    }
+    let mut lanes = Vec::new();
+    for t in (0..layout.tracks).filter(|&t| track.is_none_or(|only| only == t)) {
+        let mut events = vec![(0, midi::track_name(&names.name(t)))];
+        for (s, st) in drums.tracks[t].patterns[pattern].steps.iter().enumerate() {
+            if st.velocity == 0 { continue; }
+            let planes = st.planes();
+            for (plane, cc) in SONIFY_PLANES {
+                let value = (planes[plane] as u32 * 127 / 255) as u8;
+                events.push((s as u32 * TICKS_PER_STEP, vec![0xB0 | t as u8, cc, value]));
+            }
+        }
+        lanes.push(events);
+    }
+    Ok(lanes)
+}
+
+fn export_planes(data: &[u8], profile: &OffsetProfile, dir: Option<&str>, binarize: bool) -> io::Result<()> {
+    let dir = dir.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--format planes needs -o <directory>"))?;
+    let drums = DrumData::from_bytes_with(data, &profile.drums, &profile.layout)?;
+    std::fs::create_dir_all(dir)?;
+    for (plane, name) in PLANE_NAMES.iter().enumerate() {
+        std::fs::write(std::path::Path::new(dir).join(format!("{}.csv", name)), plane_matrix_csv(&drums, plane, binarize))?;
+    }
+    Ok(())
+}
+
+fn apply_groove(args: &ApplyGrooveArgs) -> io::Result<()> {
+    let groove: Groove = toml::from_str(&std::fs::read_to_string(&args.groove)?).map_err(invalid_data)?;
+    let (mut data, profile) = args.profile.load(&args.file)?;
+    let original = data.clone();
+    groove.apply(&mut data, &profile.timing)?;
+    write_edit(&args.out, &original, &data, args.journal.as_deref(), "apply-groove")
+}
+
+fn set_tempo(args: &SetTempoArgs) -> io::Result<()> {
+    let (mut data, profile) = args.edit.load()?;
+    let original = data.clone();
+    let off = &profile.timing;
+    let mut timing = Timing::from_bytes(&data, off)?;
+    timing.tempo = args.tempo;
+    timing.swing = args.swing.unwrap_or(timing.swing);
+    timing.write_into(&mut data, off)?;
+    write_edit(&args.edit.out, &original, &data, args.edit.journal.as_deref(), "set-tempo")
+}
+
+// Write an edited session and, when asked, journal the bytes that changed.
+fn write_edit(out: &str, original: &[u8], edited: &[u8], journal_path: Option<&str>, command: &str) -> io::Result<()> {
+    let record = journal_path.map(|_| journal::Record::between(command, original, edited)).transpose()?;
+    std::fs::write(out, edited)?;
+    if let (Some(path), Some(record)) = (journal_path, record) {
+        journal::append(std::path::Path::new(path), &record)?;
+    }
+    Ok(())
+}
+
+// Pop the newest journal record and revert it on `data`.
+fn undo_last(data: &mut [u8], records: &mut Vec<journal::Record>) -> io::Result<journal::Record> {
+    let last = records.pop().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Journal is empty, nothing to undo"))?;
+    last.revert(data)?;
+    Ok(last)
+}
+
+fn undo(args: &UndoArgs) -> io::Result<()> {
+    let path = std::path::Path::new(&args.journal);
+    let mut data = read_file(&args.file)?;
+    let mut records = journal::read(path)?;
+    let record = undo_last(&mut data, &mut records)?;
+    std::fs::write(&args.out, data)?;
+    // Only shorten the journal once the reverted file is safely written
+    journal::write_all(path, &records)?;
+    println!("Reverted {} ({} changed runs)", record.command, record.changes.len());
+    Ok(())
+}
+
+// A common kit layout, selected with `--track-names default`.
+const DEFAULT_TRACK_NAMES: &[&str] = &["Kick", "Snare", "Closed Hat", "Open Hat"];
+
+// Display names for the drum tracks; tracks without one read "Track N".
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TrackNames(Vec<String>);
+
+impl TrackNames {
+    // `default` for the built-in table, or comma-separated names in track order (empty
+    // skips a track). Names past the session's last track are never shown.
+    fn parse(spec: &str) -> Result<Self, String> {
+        if spec == "default" {
+            return Ok(TrackNames(DEFAULT_TRACK_NAMES.iter().map(|n| n.to_string()).collect()));
+        }
+        Ok(TrackNames(spec.split(',').map(|n| n.trim().to_string()).collect()))
+    }
+
+    fn get(&self, track: usize) -> Option<&str> {
+        self.0.get(track).map(String::as_str).filter(|n| !n.is_empty())
+    }
+
+    fn name(&self, track: usize) -> String {
+        self.get(track).map_or_else(|| format!("Track {}", track), str::to_string)
+    }
+
+    // Short grid label: the track's name, or "T<n>" without one.
+    fn label(&self, track: usize) -> String {
+        self.get(track).map_or_else(|| format!("T{}", track), str::to_string)
+    }
+
+    // "label:" for each of `tracks` rows, padded to one width so grid rows line up.
+    fn row_labels(&self, tracks: usize) -> Vec<String> {
+        let labels: Vec<String> = (0..tracks).map(|t| format!("{}:", self.label(t))).collect();
+        let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        labels.into_iter().map(|l| format!("{:<w$}", l, w = width)).collect()
+    }
+}
+
+// Sample names per drum track, indexed by step choice, read from a TOML file like
+//   tracks = [["Kick 1", "Kick 2", "Kick 3"], ["Snare 1", "Snare 2"]]
+// Tracks or choices without a name show the raw number.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+struct ChoiceNames {
+    tracks: Vec<Vec<String>>,
+}
+
+impl ChoiceNames {
+    fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tracks.iter().all(Vec::is_empty)
+    }
+
+    fn get(&self, track: usize, choice: u8) -> Option<&str> {
+        if choice == CHOICE_NONE {
+            return None;
+        }
+        self.tracks.get(track)?.get(choice as usize).map(String::as_str).filter(|n| !n.is_empty())
+    }
+
+    // How every report shows an explicit choice: `c2`, or `c2(Kick 3)` when named.
+    fn label(&self, track: usize, choice: u8) -> String {
+        match self.get(track, choice) {
+            Some(name) => format!("c{}({})", choice, name),
+            None => format!("c{}", choice),
+        }
+    }
+}
+
+// Lossless JSON of a parsed session: every field, with all eight bytes of every step.
+// With `stats`, a top-level "stats" array adds DrumData::pattern_stats. Each drum track
+// also gets its "index" and resolved "name", and steps with a named sample choice a
+// "choice_name".
+fn session_json(session: &Session, stats: bool, names: &TrackNames, choices: &ChoiceNames) -> io::Result<String> {
+    let mut value = serde_json::to_value(session).map_err(invalid_data)?;
+    if let Some(tracks) = value["drums"]["tracks"].as_array_mut() {
+        for (t, track) in tracks.iter_mut().enumerate() {
+            track["index"] = t.into();
+            track["name"] = names.name(t).into();
+        }
+    }
+    for (t, p, s, st) in session.drums.steps() {
+        if let Some(name) = choices.get(t, st.sample_choice()) {
+            value["drums"]["tracks"][t]["patterns"][p]["steps"][s]["choice_name"] = name.into();
+        }
+    }
+    if stats {
+        value["stats"] = serde_json::to_value(session.drums.pattern_stats()).map_err(invalid_data)?;
+    }
+    serde_json::to_string_pretty(&value).map_err(invalid_data)
+}
+
+fn render_pattern_stats(drums: &DrumData, names: &TrackNames, choices: &ChoiceNames) -> String {
+    let mut out = String::new();
+    for (t, track) in drums.pattern_stats().iter().enumerate() {
+        out.push_str(&format!("{}\n{:>4} {:>6} {:>8} {:>8}  probability 0..7\n", names.name(t), "pat", "onsets", "density", "mean vel"));
+        for (p, st) in track.iter().enumerate() {
+            let probs: Vec<String> = st.probability.iter().map(|n| format!("{:>2}", n)).collect();
+            out.push_str(&format!("{:>4} {:>6} {:>7.1}% {:>8.1}  {}\n", format!("P{:02}", p), st.onsets, st.density, st.mean_velocity, probs.join(" ")));
+        }
+        out.push_str(&format!("choices: {}\n", render_choice_usage(&drums.tracks[t].choice_histogram(), t, choices)));
+    }
+    out
+}
+
+// One track's choice histogram, e.g. "c0 x12, c2 x5, default x40; most c0, least c2;
+// unused c1(Kick 2)". Most/least only rank explicit choices; ties go to the lower
+// number. Unused lists the configured names no hit plays.
+fn render_choice_usage(hist: &[(u8, usize)], track: usize, choices: &ChoiceNames) -> String {
+    if hist.is_empty() {
+        return "no hits".to_string();
+    }
+    let label = |c: u8| choices.label(track, c);
+    let counts: Vec<String> = hist.iter()
+        .map(|&(c, n)| if c == CHOICE_NONE { format!("default x{}", n) } else { format!("{} x{}", label(c), n) })
+        .collect();
+    let mut out = counts.join(", ");
+    let explicit: Vec<(u8, usize)> = hist.iter().copied().filter(|&(c, _)| c != CHOICE_NONE).collect();
+    // max_by_key keeps the last of equals, so rank on (count, Reverse(choice))
+    let most = explicit.iter().max_by_key(|&&(c, n)| (n, std::cmp::Reverse(c)));
+    let least = explicit.iter().min_by_key(|&&(_, n)| n);
+    if let (Some(&(most, _)), Some(&(least, _))) = (most, least) {
+        out.push_str(&format!("; most {}, least {}", label(most), label(least)));
+    }
+    let configured = choices.tracks.get(track).map_or(0, Vec::len).min(CHOICE_NONE as usize);
+    let unused: Vec<String> = (0..configured as u8)
+        .filter(|&c| choices.get(track, c).is_some() && !hist.iter().any(|&(h, _)| h == c))
+        .map(label)
+        .collect();
+    if !unused.is_empty() {
+        out.push_str(&format!("; unused {}", unused.join(", ")));
+    }
+    out
+}
+
+// One row per scene; entries the parser warned about are marked with `!`.
+fn render_scenes(scenes: &Scenes, nonempty_only: bool) -> String {
+    let mut out = format!("{:<4}", "");
+    for e in 0..8 { out.push_str(&format!("{:>6}", format!("e{}", e))); }
+    out.push('\n');
+    for (i, scene) in scenes.scenes.iter().enumerate() {
+        if scene.is_empty() && nonempty_only { continue; }
+        out.push_str(&format!("{:<4}", format!("S{:02}", i)));
+        if scene.is_empty() {
+            out.push_str("  (empty)\n");
+            continue;
+        }
+        for e in &scene.entries {
+            let mark = if e.is_valid() { "" } else { "!" };
+            out.push_str(&format!("{:>6}", format!("{}..{}{}", e.start, e.end, mark)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn render_play_order(scene_chain: &SceneChain, pattern_chains: &PatternChains) -> String {
+    let join = |order: &[u8]| if order.is_empty() {
+        "(nothing: end < start)".to_string()
+    } else {
+        order.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(" → ")
+    };
+    let mut out = format!("Scenes: {}\n", join(&scene_chain.play_order()));
+    for (i, order) in pattern_chains.play_order().iter().enumerate() {
+        out.push_str(&format!("Slot {}: {}\n", i, join(order)));
+    }
+    out
+}
+
+const SWING_STEP_WIDTH: usize = 8;
+
+// One beat of 16ths, straight and swung: swing N% puts every second 16th N% of the
+// way through its pair (50 is straight). Values outside 20..80 are drawn clamped.
+fn render_swing(timing: &Timing) -> String {
+    let pair = 2 * SWING_STEP_WIDTH;
+    let swing = timing.swing.clamp(20, 80) as usize;
+    let delayed = (pair * swing + 50) / 100;
+    let line = |second: usize| {
+        let mut cells = vec!['.'; 2 * pair];
+        for start in [0, pair] {
+            cells[start] = 'x';
+            cells[start + second] = 'x';
+        }
+        cells.into_iter().collect::<String>()
+    };
+    let label = if timing.swing == swing as u8 { format!("swing {}", swing) } else { format!("swing {} ({} clamped)", swing, timing.swing) };
+    let w = label.len().max(10);
+    format!("{:>w$} |{}|\n{:>w$} |{}|\n", "straight", line(SWING_STEP_WIDTH), label, line(delayed))
+}
+
+// Read a session file together with the offset profile it should be parsed with.
+// Also returns a label saying which layout was picked and why, for the dump header.
+fn load_session_file(path: &str, offsets: Option<&str>, force_version: Option<&str>) -> io::Result<(Vec<u8>, OffsetProfile, String)> {
+    let data = read_file(path)?;
+    check_magic(&data)?;
+    let (profile, version) = select_profile(&data, offsets, force_version)?;
+    validate_header(&data, &profile)?;
+    Ok((data, profile, version))
+}
+
+// An explicit --offsets profile wins, then --force-version, then the detected version.
+// When detection finds no match or several, warn and use the v1 defaults.
+fn select_profile(data: &[u8], offsets: Option<&str>, force_version: Option<&str>) -> io::Result<(OffsetProfile, String)> {
+    if let Some(spec) = offsets {
+        return Ok((OffsetProfile::load(spec)?, format!("{} (--offsets)", spec)));
+    }
+    if let Some(name) = force_version {
+        let profile = OffsetProfile::builtin(name).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("Unknown format version {} (known: {})", name, BUILTIN_PROFILES.join(", "))))?;
+        return Ok((profile, format!("{} (forced)", name)));
+    }
+    match detect_versions(data).as_slice() {
+        [name] => Ok((OffsetProfile::builtin(name).unwrap_or_default(), format!("{} (detected)", name))),
+        candidates => {
+            let why = if candidates.is_empty() { "no known layout matches".to_string() } else { format!("matches {}", candidates.join(", ")) };
+            warn!("Could not detect the format version ({}); using v1", why);
+            Ok((OffsetProfile::default(), "v1 (default)".to_string()))
+        }
+    }
+}
+
+fn coverage_known(data: &[u8], offsets: &Offsets, layout: &Layout, fx_offsets: &FxOffsets) -> usize {
+    compute_known_bytes(data, offsets, layout, fx_offsets)
+        + 3  // timing bytes: tempo, swing, swing_sync_rate
+        + 8  // timing dwords: spare1, spare2
+        + (16 * 8 * 4)  // scenes table bytes
+        + 4              // scene chain: start,end,pad u16
+        + (8 * 4)        // pattern chains: 8 entries x 4 bytes
+}
+
+fn coverage_line(data: &[u8], offsets: &Offsets, layout: &Layout, fx_offsets: &FxOffsets) -> String {
+    let known = coverage_known(data, offsets, layout, fx_offsets);
+    let total = data.len();
+    let planes: Vec<&str> = validated_planes(data, offsets, layout).iter().map(|&(plane, _)| PLANE_NAMES[plane]).collect();
+
+    format!(
+        "Known bytes: {} / {} ({:.2}%) | fields: steps[{}], fx[delay,reverb], timing[tempo,swing,swing_sync_rate,spare1,spare2], scale[root,type], scenes+chains",
+        known,
+        total,
+        (known as f64) * 100.0 / (total.max(1) as f64),
+        planes.join(",")
+    )
+}
+
+// Which bytes some parser reads: everything a traced session parse touches, plus the magic.
+fn claimed_bytes(data: &[u8], profile: &OffsetProfile) -> io::Result<Vec<bool>> {
+    let reader = ByteReader::with_trace(data);
+    Session::parse(&reader, profile)?;
+    let mut claimed = vec![false; data.len()];
+    claimed[..MAGIC.len().min(data.len())].fill(true);
+    for e in reader.take_trace() {
+        claimed[e.offset..e.offset + e.len].fill(true);
+    }
+    Ok(claimed)
+}
+
+// Coalesce the claimed map into (known, start..end) runs in file order.
+fn coverage_runs(claimed: &[bool]) -> Vec<(bool, std::ops::Range<usize>)> {
+    let mut runs: Vec<(bool, std::ops::Range<usize>)> = Vec::new();
+    for (i, &known) in claimed.iter().enumerate() {
+        match runs.last_mut() {
+            Some((k, range)) if *k == known => range.end = i + 1,
+            _ => runs.push((known, i..i + 1)),
+        }
+    }
+    runs
+}
+
+fn render_coverage_map(runs: &[(bool, std::ops::Range<usize>)]) -> String {
+    let mut out = String::new();
+    for (known, range) in runs {
+        out.push_str(&format!("{:#08x}..{:#08x} {:<7} {:>7} bytes\n",
+            range.start, range.end, if *known { "known" } else { "unknown" }, range.len()));
+    }
+    out
+}
+
+// One-line summary of a session for `batch`, parsed the same way as single-file mode.
+fn batch_summary(path: &str) -> io::Result<(String, Vec<ValidationWarning>)> {
+    let (data, profile, _) = load_session_file(path, None, None)?;
+    let (session, warnings) = Session::from_bytes_checked(&data, &profile)?;
+    let used = session.drums.tracks.iter().flat_map(|t| t.patterns.iter()).filter(|p| !p.is_empty()).count();
+    let coverage = coverage_known(&data, &profile.drums, &profile.layout, &profile.fx) as f64 * 100.0 / data.len() as f64;
+    let line = format!("tempo {:>3} | scale {} {} | coverage {:.2}% | {:>2}/{} patterns | {} warnings",
+        session.timing.tempo, session.scale.root_name(), session.scale.scale_type_name(), coverage,
+        used, profile.layout.tracks * profile.layout.patterns, warnings.len());
+    Ok((line, warnings))
+}
+
+// How often each byte value occurs in one plane, rests included.
+fn plane_histogram(drums: &DrumData, plane: usize) -> [usize; 256] {
+    let mut counts = [0usize; 256];
+    for (_, _, _, st) in drums.steps() {
+        counts[st.planes()[plane] as usize] += 1;
+    }
+    counts
+}
+
+const HISTOGRAM_BAR: usize = 40;
+
+// Seen values in ascending order as `value: count` with a bar scaled to the largest count.
+fn render_histogram(counts: &[usize; 256]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mut out = String::new();
+    for (value, &n) in counts.iter().enumerate().filter(|(_, &n)| n > 0) {
+        let bar = (n * HISTOGRAM_BAR).div_ceil(max);
+        out.push_str(&format!("{:>3}: {:>5} {}\n", value, n, "#".repeat(bar)));
+    }
+    out
+}
+
+// Velocity from which a hit counts as accented (`X`) in a rhythm query.
+const ACCENT_VELOCITY: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RhythmStep {
+    Rest,
+    Hit,
+    Accent,
+}
+
+fn parse_rhythm(query: &str) -> io::Result<Vec<RhythmStep>> {
+    let steps = query.chars().map(|c| match c {
+        '.' => Ok(RhythmStep::Rest),
+        'x' => Ok(RhythmStep::Hit),
+        'X' => Ok(RhythmStep::Accent),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Rhythm may only contain x, X and ., found {:?}", c))),
+    }).collect::<io::Result<Vec<_>>>()?;
+    if steps.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Rhythm needs at least one step"));
+    }
+    Ok(steps)
+}
+
+// Compare the query against the first query.len() steps. `x` matches any hit; `X`
+// needs ACCENT_VELOCITY unless velocity is ignored.
+fn rhythm_matches(query: &[RhythmStep], steps: &[Step], ignore_velocity: bool) -> bool {
+    query.iter().zip(steps).all(|(q, st)| match q {
+        RhythmStep::Rest => st.velocity == 0,
+        RhythmStep::Hit => st.velocity > 0,
+        RhythmStep::Accent if ignore_velocity => st.velocity > 0,
+        RhythmStep::Accent => st.velocity >= ACCENT_VELOCITY,
+    })
+}
+
+fn find_rhythm(drums: &DrumData, query: &[RhythmStep], ignore_velocity: bool) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    for (t, track) in drums.tracks.iter().enumerate() {
+        for (p, pattern) in track.patterns.iter().enumerate() {
+            if rhythm_matches(query, &pattern.steps, ignore_velocity) { found.push((t, p)); }
+        }
+    }
+    found
+}
+
+// (track, pattern)
+type Slot = (usize, usize);
+
+// Every pair of non-empty patterns scoring at least `threshold`, most similar first.
+fn similar_patterns(drums: &DrumData, threshold: f32) -> Vec<(Slot, Slot, f32)> {
+    let slots: Vec<(usize, usize, &Pattern)> = drums.tracks.iter().enumerate()
+        .flat_map(|(t, track)| track.patterns.iter().enumerate().map(move |(p, patt)| (t, p, patt)))
+        .filter(|(_, _, patt)| !patt.is_empty())
+        .collect();
+    let mut out = Vec::new();
+    for (i, &(ta, pa, a)) in slots.iter().enumerate() {
+        for &(tb, pb, b) in &slots[i + 1..] {
+            let score = a.similarity(b);
+            if score >= threshold { out.push(((ta, pa), (tb, pb), score)); }
+        }
+    }
+    out.sort_by(|x, y| y.2.total_cmp(&x.2));
+    out
+}
+
+// One bar per line: the left grid padded to a common width, a gutter of `*` where any
+// step of the bar differs in velocity (`|` otherwise), then the right grid.
+fn render_side_by_side(a: &[Step], b: &[Step]) -> String {
+    let (left, right) = (render_ascii(a, true), render_ascii(b, true));
+    let width = left.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    let bars = a.len().max(b.len()).div_ceil(8);
+    let (mut ll, mut rl) = (left.lines(), right.lines());
+    let mut out = String::new();
+    for bar in 0..bars {
+        let differs = (bar * 8..(bar + 1) * 8)
+            .any(|s| a.get(s).map_or(0, |st| st.velocity) != b.get(s).map_or(0, |st| st.velocity));
+        let gutter = if differs { '*' } else { '|' };
+        out.push_str(&format!("{:<w$} {} {}\n", ll.next().unwrap_or(""), gutter, rl.next().unwrap_or(""), w = width));
+    }
+    out
+}
+
+// Every slot that holds notes in either session, both grids side by side. Slots that
+// only exist on one side (different layouts) are compared against an empty pattern.
+fn render_compare(a: &DrumData, b: &DrumData) -> String {
+    let empty = Pattern::default();
+    let tracks = a.tracks.len().max(b.tracks.len());
+    let mut out = String::new();
+    for t in 0..tracks {
+        let (ta, tb) = (a.tracks.get(t), b.tracks.get(t));
+        let patterns = ta.map_or(0, |x| x.patterns.len()).max(tb.map_or(0, |x| x.patterns.len()));
+        for p in 0..patterns {
+            let pa = ta.and_then(|x| x.patterns.get(p)).unwrap_or(&empty);
+            let pb = tb.and_then(|x| x.patterns.get(p)).unwrap_or(&empty);
+            if pa.is_empty() && pb.is_empty() {
+                continue;
+            }
+            let differ = pa.steps.iter().zip(&pb.steps).filter(|(x, y)| x.velocity != y.velocity).count()
+                + pa.steps.len().abs_diff(pb.steps.len());
+            let note = if differ == 0 { "identical".to_string() } else { format!("{} step(s) differ", differ) };
+            out.push_str(&format!("=== T{} P{:02} ({}) ===\n", t, p, note));
+            out.push_str(&render_side_by_side(&pa.steps, &pb.steps));
+        }
+    }
+    if out.is_empty() {
+        out.push_str("Both sessions have no notes\n");
+    }
+    out
+}
+
+// A query longer than the file's patterns is an error rather than a match on a prefix.
+fn find_in_file(path: &str, profile: &ProfileArgs, query: &[RhythmStep], ignore_velocity: bool) -> io::Result<Vec<Slot>> {
+    let drums = profile.drums(path)?;
+    let steps = drums.layout().steps;
+    if query.len() > steps {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Rhythm has {} steps but patterns here have {}", query.len(), steps)));
+    }
+    Ok(find_rhythm(&drums, query, ignore_velocity))
+}
+
+// Like batch, a file that cannot be read or parsed is reported and skipped rather than
+// ending the search.
+fn find(args: &FindArgs) -> io::Result<()> {
+    let query = parse_rhythm(&args.rhythm)?;
+    let (mut matches, mut skipped) = (0, 0);
+    for path in &args.files {
+        match find_in_file(path, &args.profile, &query, args.ignore_velocity) {
+            Ok(found) => {
+                for (t, p) in found {
+                    matches += 1;
+                    println!("{} T{} P{:02}", path, t, p);
+                }
+            }
+            Err(e) => {
+                skipped += 1;
+                warn!("{}: {}; skipped", path, e);
+            }
+        }
+    }
+    if skipped > 0 {
+        println!("{} matches ({} files skipped)", matches, skipped);
+    } else {
+        println!("{} matches", matches);
+    }
+    Ok(())
+}
+
+fn batch(args: &BatchArgs) -> io::Result<()> {
+    let dir = std::path::Path::new(&args.dir);
+    let (mut ok, mut failed, mut warned) = (0, 0, 0);
+    let names = ncs_file_names(dir)?;
+    for name in &names {
+        match batch_summary(&dir.join(name).to_string_lossy()) {
+            Ok((line, warnings)) => {
+                ok += 1;
+                if !warnings.is_empty() { warned += 1; }
+                println!("{:<24} {}", name, line);
+                for w in &warnings { println!("{:<24}   [warn] {}", "", w); }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{:<24} error: {}", name, e);
+            }
+        }
+    }
+    println!("{} files: {} parsed ({} with warnings), {} errors", names.len(), ok, warned, failed);
+    Ok(())
+}
+
+// `header` is false for the `render` subcommand, which prints only the grids.
+fn dump(args: &DumpArgs, header: bool) -> io::Result<()> {
+    let track_names = args.track_names.clone().unwrap_or_default();
+    let choice_names = args.choice_names.clone().unwrap_or_default();
+    let (data, profile, version) = load_session_file(args.file.as_deref().unwrap_or_default(), args.profile.offsets.as_deref(), args.profile.force_version.as_deref())?;
+    if args.json {
+        let (session, warnings) = Session::from_bytes_checked(&data, &profile)?;
+        report_warnings(&warnings, args.strict)?;
+        println!("{}", session_json(&session, args.stats, &track_names, &choice_names)?);
+        return Ok(());
+    }
+    if args.csv || args.csv_out.is_some() {
+        let reader = ByteReader::new(&data);
+        let drums = DrumData::parse_with(&reader, &profile.drums, &profile.layout)?;
+        report_warnings(&reader.take_warnings(), args.strict)?;
+        let csv = steps_csv(&drums, &choice_names);
+        match &args.csv_out {
+            Some(path) => std::fs::write(path, csv)?,
+            None => print!("{}", csv),
+        }
+        return Ok(());
+    }
+
+    let offsets = profile.drums.clone();
+
+    let fx_offsets = profile.fx.clone();
+    let timing_offsets = profile.timing;
+    let scale_offsets = profile.scale;
+
+    if args.hit_glyph_set.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--hit-glyph-set needs at least one glyph"));
+    }
+    let style = RenderStyle {
+        rest: args.rest_glyph.clone(),
+        hits: args.hit_glyph_set.chars().collect(),
+        bar_sep: args.bar_sep.clone(),
+        prob_digit: args.prob_format.style(args.legacy_prob_digit),
+        color: args.color.enabled(std::env::var("NO_COLOR").ok().as_deref(), io::stdout().is_terminal()),
+        scale: args.velocity_levels.clone().unwrap_or_default(),
+        ruler: args.ruler,
+    };
+    style.scale.check(style.hits.len())?;
+
+    // -v lists the scalar fields read, which needs the trace too
+    let verbose = log::log_enabled!(log::Level::Info);
+    let reader = if args.trace || verbose { ByteReader::with_trace(&data) } else { ByteReader::new(&data) };
+
+    check_header_length(&reader)?;
+    let timing = Timing::parse_with(&reader, &timing_offsets, args.spare_semantics)?;
+    let scale = ScaleSettings::parse(&reader, &scale_offsets)?;
+
+    let fx = Fx::parse(&reader, &fx_offsets)?;
+
+    if args.strict_planes { offsets.check_plane_layout(&profile.layout)?; }
+    let drums = DrumData::parse_with(&reader, &offsets, &profile.layout)?;
+
+    let name = SessionName::parse(&reader, &profile.name)?;
+    let globals = Globals::parse(&reader, &profile.globals)?;
+    // Scenes & chains
+    let scenes = Scenes::parse(&reader, &profile.scenes)?;
+    let scene_chain = SceneChain::parse(&reader, &profile.chains)?;
+    let pattern_chains = PatternChains::parse(&reader, &profile.chains)?;
+    report_warnings(&reader.take_warnings(), args.strict)?;
+    let trace = reader.take_trace();
+    if verbose {
+        // Per-step reads are what the grid shows; list the one-off fields
+        for e in trace.iter().filter(|e| !e.field.contains('[')) { info!("{} = {}", e.field, e.value); }
+    }
+    if args.trace {
+        for line in format_trace(&trace).lines() { debug!(target: logging::TRACE_TARGET, "{}", line); }
+    }
+    if header {
+        // Simple coverage metric
+        println!("{}", coverage_line(&data, &offsets, &profile.layout, &fx_offsets));
+
+        // ASCII/debug header
+        println!("Session: {}", name.name);
+        println!("Format: {}", version);
+        println!("Timing: tempo={} swing={} swing_sync_rate={} spare1={} spare2={}", timing.tempo, timing.swing, timing.swing_sync_rate, timing.spare1, timing.spare2);
+        print!("{}", render_swing(&timing));
+        if let Some(version) = timing.format_version(args.spare_semantics) {
+            println!("Format version (timing spare1): {}", version);
+        }
+        println!("Scale: root={} ({}) type={} ({})", scale.root_name(), scale.root, scale.scale_type_name(), scale.scale_type);
+        println!("FX: delay_preset={} reverb_preset={}", fx.delay_preset, fx.reverb_preset);
+        println!("Master volume: {} (provisional offset)", globals.master_volume);
+        println!("Scenes: 16x8 parsed | SceneChain: {}..{} | PatternChains: 8 entries",
+                 scene_chain.start_scene, scene_chain.end_scene);
+    }
+
+    if args.stats {
+        print!("\nPattern stats:\n{}", render_pattern_stats(&drums, &track_names, &choice_names));
+    }
+
+    if args.scenes {
+        print!("\nScenes (start..end per entry):\n{}", render_scenes(&scenes, args.nonempty_scenes));
+    }
+
+    if args.play_order {
+        print!("\nPlay order:\n{}", render_play_order(&scene_chain, &pattern_chains));
+    }
+
+    if args.coverage_map {
+        print!("\nCoverage map:\n{}", render_coverage_map(&coverage_runs(&claimed_bytes(&data, &profile)?)));
+    }
+
+    if let Some(path) = &args.svg {
+        let layout = drums.layout();
+        if args.svg_pattern >= layout.patterns || args.svg_track.is_some_and(|t| t >= layout.tracks) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "SVG track must be 0..{} and pattern 0..{}", layout.tracks.saturating_sub(1), layout.patterns.saturating_sub(1))));
+        }
+        let tracks: Vec<usize> = match args.svg_track { Some(t) => vec![t], None => (0..layout.tracks).collect() };
+        std::fs::write(path, render_svg(&drums, &tracks, args.svg_pattern, &track_names))?;
+    }
+
+    if let Some(path) = &args.html {
+        let (session, _) = Session::from_bytes_checked(&data, &profile)?;
+        std::fs::write(path, render_html(&session, &track_names))?;
+    }
+
+    if args.analyze {
+        print!("\nPattern usage:\n{}", render_pattern_use(&classify_patterns(&drums), &track_names));
+    }
+
+    if args.heatmap {
+        print!("\nPattern density (tracks x patterns):\n{}", render_density_heatmap(&drums.pattern_stats(), &style, &track_names));
+    }
+
+    if args.planes_heatmap {
+        let patterns = drums.layout().patterns;
+        print!("\nStep heatmap (patterns hitting each step, 0..{}):\n{}", patterns, render_step_heatmap(&step_heatmap(&drums), patterns, &track_names));
+    }
+
+    // Drums (ASCII)
+    for (t, track) in drums.tracks.iter().enumerate() {
+        let label = match track_names.get(t) { Some(name) => format!("{}: {}", t, name), None => t.to_string() };
+        println!("\n=== DRUM TRACK {} [dly {} rev {}] ===", label, track.delay_send, track.reverb_send);
+        for (p, patt) in track.patterns.iter().enumerate() {
+            let mut ascii = if args.rle { render_rle(&patt.steps) } else { render_ascii_styled(&patt.steps, true, &style) };
+            if args.fold_bars { ascii = fold_bars(&ascii); }
+            let label = format!("P{:02}: ", p);
+            let mut lines = ascii.lines();
+            if let Some(first) = lines.next() {
+                println!("{}{}", label, first);
+                let pad = " ".repeat(label.len());
+                for line in lines { println!("{}{}", pad, line); }
+            } else {
+                println!("{}", label);
+            }
+            if args.show_locks {
+                println!("{:>12}: {}", "locks", render_locks(&patt.steps, t, &choice_names));
+            }
+            if args.show_params {
+                for (plane, _) in PARAM_PLANES {
+                    println!("{:>12}: {}", PLANE_NAMES[plane], render_param_row(&patt.steps, plane));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn load_drums(path: &str) -> DrumData {
+        let data = read_file(path).expect("failed to read test ncs file");
+        let offsets = Offsets {
+            velocity: 0x0CD74,
+            probability: 0x0CD94,
+            choice: 0x0CDB4,
+            mask: 0x0CDD4,
+            pitch: 0x0CDF4,
+            decay: 0x0CE14,
+            distortion: 0x0CE34,
+            eq: 0x0CE54,
+            track_stride: 0x3540,
+            pattern_stride: 0x06A8,
+            sends: SendOffsets::default(),
+        };
+        DrumData::from_bytes(&data, &offsets).expect("parse drums")
+    }
+
+    #[test]
     fn deep_track2_pattern02_structure() {
         let drums = load_drums("../test_data/Deep.ncs");
         let steps = &drums.tracks[2].patterns[2].steps;
-        // First bar (0..7): █ ▁ . ▁ █ ▁ . ▁
-        assert!(steps[0].velocity >= 96, "step0 expected strong hit");
-        assert!(steps[1].velocity > 0 && steps[1].velocity <= 31, "step1 expected weak hit");
-        assert_eq!(steps[2].velocity, 0, "step2 expected rest");
-        assert!(steps[3].velocity > 0 && steps[3].velocity <= 31, "step3 expected weak hit");
-        assert!(steps[4].velocity >= 96, "step4 expected strong hit");
-        assert!(steps[5].velocity > 0 && steps[5].velocity <= 31, "step5 expected weak hit");
-        assert_eq!(steps[6].velocity, 0, "step6 expected rest");
-        assert!(steps[7].velocity > 0 && steps[7].velocity <= 31, "step7 expected weak hit");
-        // Probability commonly 7 for these datasets
-        for &i in &[0,1,3,4,5,7] { assert_eq!(steps[i].probability, 7, "prob mismatch at step {}", i); }
+        // First bar (0..7): █ ▁ . ▁ █ ▁ . ▁
+        assert!(steps[0].velocity >= 96, "step0 expected strong hit");
+        assert!(steps[1].velocity > 0 && steps[1].velocity <= 31, "step1 expected weak hit");
+        assert_eq!(steps[2].velocity, 0, "step2 expected rest");
+        assert!(steps[3].velocity > 0 && steps[3].velocity <= 31, "step3 expected weak hit");
+        assert!(steps[4].velocity >= 96, "step4 expected strong hit");
+        assert!(steps[5].velocity > 0 && steps[5].velocity <= 31, "step5 expected weak hit");
+        assert_eq!(steps[6].velocity, 0, "step6 expected rest");
+        assert!(steps[7].velocity > 0 && steps[7].velocity <= 31, "step7 expected weak hit");
+        // Probability commonly 7 for these datasets
+        for &i in &[0,1,3,4,5,7] { assert_eq!(steps[i].probability, 7, "prob mismatch at step {}", i); }
+    }
+
+    #[test]
+    fn funk_track2_pattern02_structure() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let steps = &drums.tracks[2].patterns[2].steps;
+        // First bar (0..7): █ ▁ ▁ ▁ █ ▁ ▁ ▁
+        assert!(steps[0].velocity >= 96, "step0 expected strong hit");
+        for &i in &[1usize,2,3,5,6,7] {
+            assert!(steps[i].velocity > 0 && steps[i].velocity <= 31, "step{} expected weak hit", i);
+        }
+        assert!(steps[4].velocity >= 96, "step4 expected strong hit");
+        // Bars 3 and 4 (16..31) were rests in Funk P02
+        for (i, st) in steps.iter().enumerate().skip(16) { assert_eq!(st.velocity, 0, "expected rest at step {}", i); }
+    }
+
+    #[test]
+    fn fold_bars_collapses_four_identical_bars() {
+        let bar = [100u8, 0, 0, 0, 30, 0, 0, 0];
+        let steps: Vec<Step> = (0..STEPS).map(|i| Step {
+            velocity: bar[i % 8], probability: 7, choice: 0, mask: 0, pitch: 0, decay: 0, distortion: 0, eq: 0,
+        }).collect();
+        let folded = fold_bars(&render_ascii(&steps, true));
+        assert_eq!(folded, "█7 . . . ▁7 . . . x4");
+    }
+
+    #[test]
+    fn sonify_emits_one_decay_cc_per_active_step() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        let smf = sonify_planes(&drums, None, 2, &TrackNames::default()).expect("sonify");
+        assert_eq!(midi::read_smf(&smf).expect("read back").ticks_per_quarter, MIDI_TPQ);
+        let lanes = sonify_lanes(&drums, None, 2, &TrackNames::default()).expect("sonify");
+        let controls: Vec<(u32, &[u8])> = lanes.iter().flatten()
+            .filter(|(_, ev)| ev[0] & 0xF0 == 0xB0)
+            .map(|(tick, ev)| (*tick, &ev[1..]))
+            .collect();
+        let active: usize = drums.tracks.iter()
+            .map(|t| t.patterns[2].steps.iter().filter(|st| st.velocity > 0).count())
+            .sum();
+        assert!(active > 0);
+        assert_eq!(controls.iter().filter(|(_, cc)| cc[0] == 15).count(), active);
+        assert!(controls.iter().all(|(tick, cc)| cc[1] <= 127 && tick % TICKS_PER_STEP == 0));
+    }
+
+    #[test]
+    fn track_names_reach_stats_json_and_midi() {
+        assert_eq!(TrackNames::default().name(2), "Track 2");
+        let kit = TrackNames::parse("default").unwrap();
+        assert_eq!(kit.name(0), "Kick");
+        let custom = TrackNames::parse("BD, ,Hat").unwrap();
+        assert_eq!((custom.name(0), custom.name(1), custom.name(2), custom.name(3)),
+                   ("BD".to_string(), "Track 1".to_string(), "Hat".to_string(), "Track 3".to_string()));
+        // Layouts with more tracks than the Circuit Tracks take a name for each
+        assert_eq!(TrackNames::parse("a,b,c,d,e").unwrap().name(4), "e");
+
+        let data = read_file("../test_data/Deep.ncs").unwrap();
+        let session = Session::from_bytes(&data).unwrap();
+        assert!(render_pattern_stats(&session.drums, &kit, &ChoiceNames::default()).starts_with("Kick\n"));
+        assert!(render_pattern_stats(&session.drums, &TrackNames::default(), &ChoiceNames::default()).starts_with("Track 0\n"));
+        let json: serde_json::Value = serde_json::from_str(&session_json(&session, false, &custom, &ChoiceNames::default()).unwrap()).unwrap();
+        assert_eq!((json["drums"]["tracks"][2]["index"].as_u64(), json["drums"]["tracks"][2]["name"].as_str()), (Some(2), Some("Hat")));
+
+        let smf = sonify_planes(&session.drums, Some(0), 2, &kit).unwrap();
+        assert!(smf.windows(6).any(|w| w == [0xFF, 0x03, 4, b'K', b'i', b'c']));
+        assert!(Cli::try_parse_from(["ncs-tui", "a.ncs", "--track-names", "a,b,c,d,e"]).is_ok());
+    }
+
+    #[test]
+    fn html_report_has_one_table_per_nonempty_pattern() {
+        let session = Session::from_bytes(&read_file("../test_data/Funk.ncs").unwrap()).unwrap();
+        let html = render_html(&session, &TrackNames::parse("default").unwrap());
+        let nonempty = session.drums.tracks.iter().flat_map(|t| t.patterns.iter()).filter(|p| !p.is_empty()).count();
+        assert!(nonempty > 0);
+        assert_eq!(html.matches("<table class=\"pattern\">").count(), nonempty);
+        assert_eq!(html.matches("<table").count(), html.matches("</table>").count());
+        assert_eq!(html.matches("<tr>").count(), html.matches("</tr>").count());
+        assert!(html.starts_with("<!DOCTYPE html>") && html.ends_with("</html>\n"));
+        assert!(!html.contains("http"), "no external assets");
+        assert!(html.contains("<h3>Kick P00</h3>") && html.contains("<pre>"));
+        assert_eq!(html_escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+
+    #[test]
+    fn rhythm_query_matches_onset_placement() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let steps = &drums.tracks[2].patterns[2].steps;
+        let query: String = steps[..8].iter().map(|st| match st.velocity { 0 => '.', v if v >= ACCENT_VELOCITY => 'X', _ => 'x' }).collect();
+        let exact = parse_rhythm(&query).unwrap();
+        assert!(find_rhythm(&drums, &exact, false).contains(&(2, 2)));
+
+        // `x` matches any hit; an accent on a quiet hit only matches by placement
+        assert!(rhythm_matches(&parse_rhythm(&query.replace('X', "x")).unwrap(), steps, false));
+        let quiet = [Step { velocity: 20, ..Step::rest() }, Step::rest()];
+        assert!(!rhythm_matches(&parse_rhythm("X.").unwrap(), &quiet, false));
+        assert!(rhythm_matches(&parse_rhythm("X.").unwrap(), &quiet, true));
+        let all_rests = parse_rhythm(&".".repeat(STEPS)).unwrap();
+        assert_eq!(find_rhythm(&drums, &all_rests, true).len(),
+                   drums.tracks.iter().flat_map(|t| t.patterns.iter()).filter(|p| p.is_empty()).count());
+
+        assert!(parse_rhythm("x.o.").is_err());
+        assert!(parse_rhythm("").is_err());
+        let too_long = parse_rhythm(&"x".repeat(STEPS + 1)).unwrap();
+        let err = find_in_file("../test_data/Funk.ncs", &ProfileArgs::default(), &too_long, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(Cli::try_parse_from(["ncs-tui", "find", "--rhythm", "x..."]).is_err());
+    }
+
+    #[test]
+    fn render_style_overrides_glyphs() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let steps = &drums.tracks[2].patterns[2].steps;
+        let style = RenderStyle { rest: "-".into(), ..RenderStyle::default() };
+        let out = render_ascii_styled(steps, true, &style);
+        assert!(out.contains('-') && !out.contains('.'));
+        // Defaults keep today's output byte-identical
+        assert_eq!(render_ascii_styled(steps, true, &RenderStyle::default()), render_ascii(steps, true));
+        let one_line = render_ascii_styled(steps, false, &RenderStyle { bar_sep: Some("|".into()), ..RenderStyle::default() });
+        assert_eq!(one_line.lines().count(), 1);
+        assert_eq!(one_line.matches('|').count(), STEPS / 8 - 1);
+    }
+
+    #[test]
+    fn import_midi_quantizes_beats_onto_grid() {
+        // Format 0, 96 ticks per quarter: notes on beats 1 and 2 of a one-bar clip
+        let mut smf = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60".to_vec();
+        let events: &[u8] = &[
+            0x00, 0x99, 60, 100, 0x30, 0x89, 60, 0,
+            0x30, 0x99, 60, 90, 0x30, 0x89, 60, 0,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(events.len() as u32).to_be_bytes());
+        smf.extend_from_slice(events);
+        let clip = midi::read_smf(&smf).expect("parse smf");
+
+        let base = read_file("../test_data/Deep.ncs").expect("read base");
+        let offsets = Offsets::default();
+        let out = import_midi(&base, &offsets, &Layout::default(), &clip, Some(1), 3, &[60, 62, 64, 65]).expect("import");
+        let drums = DrumData::from_bytes(&out, &offsets).expect("parse drums");
+        let steps = &drums.tracks[1].patterns[3].steps;
+        let active: Vec<usize> = (0..STEPS).filter(|&i| steps[i].velocity > 0).collect();
+        assert_eq!(active, vec![0, 8]);
+        assert_eq!(steps[0].planes(), Step::hit(100).planes());
+        assert_eq!(steps[8].planes(), Step::hit(90).planes());
+        // Cleared steps are full rests, not just zeroed velocities
+        assert!(steps.iter().enumerate().filter(|(i, _)| !active.contains(i)).all(|(_, st)| st.planes() == Step::rest().planes()));
+    }
+
+    #[test]
+    fn midi_delta_times_overflowing_the_tick_count_are_rejected() {
+        let mut events = Vec::new();
+        for _ in 0..17 {
+            events.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0x7F, 0xFF, 0x01, 0x00]);
+        }
+        let mut smf = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk".to_vec();
+        smf.extend_from_slice(&(events.len() as u32).to_be_bytes());
+        smf.extend_from_slice(&events);
+        let err = midi::read_smf(&smf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn plane_stats_csv_has_row_per_plane() {
+        let csv = render_plane_stats(&plane_stats(&load_drums("../test_data/Deep.ncs")), ReportFormat::Csv);
+        let mut lines = csv.lines();
+        let header: Vec<&str> = lines.next().expect("header").split(',').collect();
+        assert!(header.contains(&"rest_contamination"));
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), 8);
+        for (row, name) in rows.iter().zip(PLANE_NAMES) {
+            assert!(row.starts_with(&format!("{},", name)));
+            assert_eq!(row.split(',').count(), header.len());
+        }
+    }
+
+    #[test]
+    fn rename_round_trip_nul_pads_field() {
+        let off = NameOffsets::default();
+        let mut data = read_file("../test_data/Deep.ncs").unwrap();
+        assert_eq!(SessionName::parse(&ByteReader::new(&data), &off).unwrap().name, "Deep");
+        rename_session(&mut data, "TEST", &NameOffsets::default()).expect("rename");
+        assert_eq!(SessionName::parse(&ByteReader::new(&data), &off).unwrap().name, "TEST");
+        assert_eq!(&data[off.base..off.base + 4], b"TEST");
+        assert!(data[off.base + 4..off.base + off.len].iter().all(|&b| b == 0x00));
+        // Writing back the same name keeps the stock space padding
+        let mut stock = read_file("../test_data/Deep.ncs").unwrap();
+        rename_session(&mut stock, "Deep", &NameOffsets::default()).expect("rename");
+        assert_eq!(stock, read_file("../test_data/Deep.ncs").unwrap());
+        let huge = NameOffsets { base: usize::MAX, len: 2 };
+        assert!(SessionName::new("TEST", &off).unwrap().to_bytes(&huge, &mut data).is_err());
+
+        assert!(rename_session(&mut data, &"x".repeat(off.len + 1), &NameOffsets::default()).is_err());
+        assert!(rename_session(&mut data, "bad\u{7}name", &NameOffsets::default()).is_err());
+    }
+
+    #[test]
+    fn preview_length_matches_pattern_duration() {
+        let session = Session::from_bytes(&read_file("../test_data/Funk.ncs").unwrap()).unwrap();
+        let samples = preview_samples(&session, Some(2), 2).expect("render");
+        // 32 sixteenth notes at the session tempo
+        let seconds = STEPS as f64 * 60.0 / session.timing.tempo as f64 / 4.0;
+        assert_eq!(samples.len(), (seconds * preview::SAMPLE_RATE as f64).round() as usize);
+        assert!(samples.iter().any(|&s| s != 0));
+
+        let path = std::env::temp_dir().join(format!("ncs-preview-{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 1, sample_rate: preview::SAMPLE_RATE, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut wav = hound::WavWriter::create(&path, spec).unwrap();
+        for &s in &samples { wav.write_sample(s).unwrap(); }
+        wav.finalize().unwrap();
+        let len = hound::WavReader::open(&path).unwrap().len() as usize;
+        std::fs::remove_file(&path).ok();
+        assert_eq!(len, samples.len());
+    }
+
+    #[test]
+    fn scale_velocity_halves_hits_without_dropping_them() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        let mut scaled = drums.clone();
+        for patt in scaled.tracks[2].patterns.iter_mut() { patt.scale_velocity(0.5); }
+        for p in 0..PATTERNS {
+            for s in 0..STEPS {
+                let (before, after) = (drums.tracks[2].patterns[p].steps[s].velocity, scaled.tracks[2].patterns[p].steps[s].velocity);
+                if before == 0 {
+                    assert_eq!(after, 0);
+                } else {
+                    assert!(after > 0, "hit at P{} S{} became a rest", p, s);
+                    assert!((after as i32 - (before as i32 + 1) / 2).abs() <= 1, "{} -> {}", before, after);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn plane_list_reports_offsets_and_status() {
+        let out = render_plane_list(&Offsets::default());
+        let line = |name: &str| out.lines().find(|l| l.starts_with(&format!("{} ", name))).unwrap_or_else(|| panic!("no {} line", name)).to_string();
+        assert!(line("velocity").ends_with("validated") && !line("velocity").contains("unverified"));
+        let pitch = line("pitch");
+        assert!(pitch.contains("0xCDF4"), "{}", pitch);
+        assert!(pitch.ends_with("unverified"));
+        assert_eq!(out.lines().count(), 1 + PLANE_NAMES.len());
+    }
+
+    #[test]
+    fn step_plane_by_name() {
+        let mut st = Step { velocity: 100, probability: 7, choice: 1, mask: 2, pitch: 3, decay: 4, distortion: 5, eq: 6 };
+        assert_eq!(st.plane("eq"), Some(6));
+        assert_eq!(st.plane("bogus"), None);
+        for (i, name) in PLANE_NAMES.iter().enumerate() {
+            assert_eq!(st.plane(name), Some(st.planes()[i]));
+        }
+        assert!(st.set_plane("decay", 40));
+        assert_eq!(st.decay, 40);
+        assert!(!st.set_plane("bogus", 1));
+    }
+
+    #[test]
+    fn diff_heatmap_marks_only_edited_cell() {
+        let a = load_drums("../test_data/Deep.ncs");
+        let mut b = a.clone();
+        for st in b.tracks[2].patterns[2].steps.iter_mut().take(5) { st.velocity = st.velocity.wrapping_add(1); }
+        let heat = diff_heatmap(&a, &b);
+        for (plane, tracks) in heat.iter().enumerate() {
+            for (t, patterns) in tracks.iter().enumerate() {
+                for (p, &changed) in patterns.iter().enumerate() {
+                    let expected = if plane == 0 && t == 2 && p == 2 { 5 } else { 0 };
+                    assert_eq!(changed, expected, "plane {} T{} P{}", plane, t, p);
+                }
+            }
+        }
+        let rendered = render_diff_heatmap(&heat, STEPS, &TrackNames::default());
+        let t2 = rendered.lines().find(|l| l.starts_with("T2:")).unwrap();
+        assert_eq!(t2.split_whitespace().filter(|c| *c != ".").count(), 2, "{}", t2);
+        let named = render_diff_heatmap(&heat, STEPS, &TrackNames::parse("default").unwrap());
+        let hat = named.lines().find(|l| l.starts_with("Closed Hat:")).unwrap();
+        assert_eq!(hat.split_whitespace().filter(|c| *c != ".").count(), 3, "{}", hat);
+        assert!(named.lines().nth(1).unwrap().starts_with(&format!("{} P0", " ".repeat("Closed Hat:".len()))));
+    }
+
+    #[test]
+    fn diff_dir_pairs_files_by_name() {
+        let root = std::env::temp_dir().join(format!("ncs-diff-dir-{}", std::process::id()));
+        let (a, b) = (root.join("a"), root.join("b"));
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let deep = read_file("../test_data/Deep.ncs").unwrap();
+        let mut edited = deep.clone();
+        edited[Offsets::default().velocity] = 1;
+        std::fs::write(a.join("set.ncs"), &deep).unwrap();
+        std::fs::write(b.join("set.ncs"), &edited).unwrap();
+        std::fs::write(a.join("old.ncs"), &deep).unwrap();
+
+        let d = diff_dirs(&a, &b, &ProfileArgs::default()).expect("diff dirs");
+        std::fs::remove_dir_all(&root).ok();
+        assert_eq!(d.changed, vec![("set.ncs".to_string(), 1)]);
+        assert_eq!(d.only_a, vec!["old.ncs".to_string()]);
+        assert!(d.only_b.is_empty() && d.unchanged.is_empty() && d.errors.is_empty());
+    }
+
+    #[test]
+    fn velocity_matrix_export_covers_every_step() {
+        let csv = plane_matrix_csv(&load_drums("../test_data/Deep.ncs"), 0, false);
+        let rows: Vec<Vec<u32>> = csv.lines().skip(1)
+            .map(|l| l.split(',').map(|v| v.parse().expect("numeric cell")).collect())
+            .collect();
+        assert_eq!(rows.len(), TRACKS * PATTERNS);
+        let values: usize = rows.iter().map(|r| r.len() - 2).sum();
+        assert_eq!(values, 4 * 8 * 32);
+        let row = rows.iter().find(|r| r[0] == 2 && r[1] == 2).expect("row for track 2 pattern 2");
+        assert!(row[2] >= 96);
+    }
+
+    #[test]
+    fn compact_chains_keeps_each_entry_on_its_track() {
+        let e = |start, end| PatternChainEntry { start, end, pad: 0 };
+        let chains = PatternChains { entries: [e(0, 1), e(0xFF, 0xFF), e(2, 3), e(5, 4), e(0xFF, 0xFF), e(6, 7), e(0xFF, 0xFF), e(3, 9)] };
+        let compact = chains.normalized();
+        let ranges: Vec<(u8, u8)> = compact.entries.iter().map(|e| (e.start, e.end)).collect();
+        assert_eq!(ranges, vec![(0, 1), (0, 0), (2, 3), (4, 5), (0, 0), (6, 7), (0, 0), (0, 0)]);
+        // Playable entries stay at their track's index
+        for (i, (before, after)) in chains.entries.iter().zip(&compact.entries).enumerate() {
+            if before.start < 8 && before.end < 8 {
+                assert_eq!((after.start, after.end), (before.start.min(before.end), before.start.max(before.end)), "slot {}", i);
+            }
+        }
+
+        let off = ChainOffsets::default();
+        let mut buf = vec![0u8; 0x2E4];
+        compact.to_bytes(&off, &mut buf).expect("write chains");
+        let back = PatternChains::from_bytes(&buf, &off).expect("read chains");
+        assert!(back.entries.iter().all(|e| e.start <= e.end && e.end < 8));
+    }
+
+    #[test]
+    fn compact_chains_closes_gaps_in_each_track() {
+        let e = |start, end| PatternChainEntry { start, end, pad: 0 };
+        // Track 1 and 4 are gaps that play nothing, track 3 is reversed
+        let chains = PatternChains { entries: [e(0, 3), e(0xFF, 0xFF), e(2, 2), e(7, 1), e(5, 4), e(0, 7), e(1, 2), e(0, 0)] };
+        assert!(chains.play_order()[3].is_empty());
+        for (i, order) in chains.normalized().play_order().iter().enumerate() {
+            assert!(!order.is_empty(), "track {} plays nothing", i);
+            assert!(order.windows(2).all(|w| w[1] == w[0] + 1), "track {} not contiguous: {:?}", i, order);
+            assert!(order.iter().all(|&p| p < 8), "track {} references {:?}", i, order);
+        }
+        assert_eq!(chains.normalized().play_order()[3], (1..=7).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn plane_filter_drops_constant_planes() {
+        let stats = filter_plane_stats(plane_stats(&load_drums("../test_data/Deep.ncs")), 2, 0.0);
+        let names: Vec<&str> = stats.iter().map(|st| st.name).collect();
+        assert!(!names.contains(&"probability"), "constant probability plane should be filtered: {:?}", names);
+        assert!(names.contains(&"velocity"));
+        let all = filter_plane_stats(plane_stats(&load_drums("../test_data/Deep.ncs")), 0, 0.0);
+        assert_eq!(all.len(), 8);
+    }
+
+    #[test]
+    fn get_returns_none_out_of_range() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        assert!(drums.get(9, 9, 99).is_none());
+        assert!(drums.get(0, 0, STEPS).is_none());
+        let st = drums.get(2, 2, 0).expect("in range");
+        assert!(st.velocity >= 96);
+        assert_eq!(st.probability, 7);
+    }
+
+    #[test]
+    #[allow(clippy::needless_range_loop)] // corr[a][b] against corr[b][a] reads clearer indexed
+    fn plane_correlation_is_symmetric_with_unit_diagonal() {
+        let corr = plane_correlation(&load_drums("../test_data/Deep.ncs"));
+        assert!((corr[0][0] - 1.0).abs() < 1e-9, "velocity should self-correlate to 1.0");
+        for a in 0..8 {
+            for b in 0..8 {
+                assert_eq!(corr[a][b].to_bits(), corr[b][a].to_bits(), "corr[{}][{}] not symmetric", a, b);
+            }
+        }
+        // Probability is all 7s on active steps: no defined correlation
+        assert!(corr[1][0].is_nan() && corr[1][1].is_nan());
+    }
+
+    #[test]
+    fn density_heatmap_shades_each_pattern_slot() {
+        let mut drums = load_drums("../test_data/Funk.ncs");
+        drums.tracks[0].patterns[1] = Pattern::rests(STEPS);
+        for st in drums.tracks[0].patterns[2].steps.iter_mut() { st.velocity = 100; }
+        let rendered = render_density_heatmap(&drums.pattern_stats(), &RenderStyle::default(), &TrackNames::default());
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 1 + TRACKS);
+        assert_eq!(lines[0], "    P0 P1 P2 P3 P4 P5 P6 P7");
+        let cells: Vec<&str> = lines[1].split_whitespace().skip(1).collect();
+        assert_eq!(cells.len(), PATTERNS);
+        assert_eq!((cells[1], cells[2]), (".", "█"));
+        let mut sparse = Pattern::rests(STEPS);
+        sparse.steps[0].velocity = 127;
+        drums.tracks[3].patterns[0] = sparse;
+        let rendered = render_density_heatmap(&drums.pattern_stats(), &RenderStyle::default(), &TrackNames::default());
+        assert!(rendered.lines().nth(4).unwrap().starts_with("T3:  ▁"), "{}", rendered);
+        let named = render_density_heatmap(&drums.pattern_stats(), &RenderStyle::default(), &TrackNames::parse("Kick,,Hat").unwrap());
+        let lines: Vec<&str> = named.lines().collect();
+        assert_eq!(lines[0], "      P0 P1 P2 P3 P4 P5 P6 P7");
+        assert!(lines[1].starts_with("Kick:  ") && lines[2].starts_with("T1:    ") && lines[4].starts_with("T3:    ▁"), "{}", named);
+        assert!(Cli::try_parse_from(["ncs", "dump", "s.ncs", "--heatmap"]).is_ok());
+    }
+
+    #[test]
+    fn funk_heatmap_peaks_on_downbeat() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let heat = step_heatmap(&drums);
+        let row = &heat[2];
+        let max = *row.iter().max().unwrap();
+        assert!(max > 0);
+        assert_eq!(row[0], max, "step 0 should be the most-hit step on track 2");
+        let rendered = render_heatmap_row(row, PATTERNS);
+        assert_eq!(rendered.chars().count(), STEPS + 3);
+        assert_ne!(rendered.chars().next(), Some('.'));
+
+        let plain = render_step_heatmap(&heat, PATTERNS, &TrackNames::default());
+        assert!(plain.lines().nth(2).unwrap().starts_with(&format!("T2: {}", rendered)), "{}", plain);
+        let named = render_step_heatmap(&heat, PATTERNS, &TrackNames::parse("Kick,,Hat").unwrap());
+        let lines: Vec<&str> = named.lines().collect();
+        assert_eq!(lines[1], format!("T1:   {}", render_heatmap_row(&heat[1], PATTERNS)));
+        assert_eq!(lines[2], format!("Hat:  {}", rendered));
+    }
+
+    #[test]
+    fn track_sends_are_read_per_track() {
+        let offsets = Offsets::default();
+        let mut data = vec![0u8; 0x26D10];
+        for t in 0..TRACKS {
+            data[offsets.sends.delay_send + t * offsets.sends.track_stride] = 10 * (t as u8 + 1);
+            data[offsets.sends.reverb_send + t * offsets.sends.track_stride] = 5 * (t as u8 + 1);
+        }
+        let drums = DrumData::from_bytes(&data, &offsets).expect("parse drums");
+        for t in 0..TRACKS {
+            assert_eq!(drums.tracks[t].delay_send, 10 * (t as u8 + 1));
+            assert_eq!(drums.tracks[t].reverb_send, 5 * (t as u8 + 1));
+        }
+        let mut out = vec![0u8; data.len()];
+        drums.to_bytes(&offsets, &mut out).expect("write drums");
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn trace_records_tempo_read() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let reader = ByteReader::with_trace(&data);
+        let timing = Timing::parse(&reader, &TimingOffsets::default()).expect("timing");
+        let trace = reader.take_trace();
+        let tempo = trace.iter().find(|e| e.offset == 0x34).expect("read at 0x34");
+        assert_eq!(tempo.field, "tempo");
+        assert_eq!(tempo.value, timing.tempo as u32);
+        // Untraced readers record nothing
+        assert!(ByteReader::new(&data).take_trace().is_empty());
+    }
+
+    #[test]
+    fn trace_labels_step_reads_with_their_coordinates() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let off = Offsets::default();
+        let reader = ByteReader::with_trace(&data);
+        DrumData::parse(&reader, &off).expect("drums");
+        let trace = reader.take_trace();
+        let at = off.velocity + 2 * off.track_stride + 2 * off.pattern_stride;
+        let entry = trace.iter().find(|e| e.offset == at).expect("velocity read");
+        assert_eq!(entry.field, "velocity[t2,p2,s0]");
+        assert_eq!(format_trace(std::slice::from_ref(entry)), format!("velocity[t2,p2,s0] @ 0x{:05X} = 0x{:02X}\n", at, data[at]));
+        assert!(trace.iter().any(|e| e.field == "reverb_send[t3]"));
+
+        assert!(ByteReader::new(&data).u8_at(data.len(), "eq", &[('t', 1)]).unwrap_err().to_string().contains("eq[t1]"));
+    }
+
+    #[test]
+    fn spare1_decodes_as_version_without_warning() {
+        let off = TimingOffsets::default();
+        let mut data = vec![0u8; 0x40];
+        data[off.tempo] = 120;
+        data[off.swing] = 50;
+        data[off.spare1..off.spare1 + 4].copy_from_slice(&3u32.to_le_bytes());
+        let timing = Timing::parse_with(&ByteReader::new(&data), &off, SpareSemantics::Version).expect("timing");
+        assert_eq!(timing.format_version(SpareSemantics::Version), Some(3));
+        assert!(timing.spare_warnings(&off, SpareSemantics::Version).is_empty());
+        // The default semantics still flag it
+        assert_eq!(timing.format_version(SpareSemantics::Zero), None);
+        assert_eq!(timing.spare_warnings(&off, SpareSemantics::Zero).len(), 1);
+    }
+
+    #[test]
+    fn groove_round_trip_deep_onto_funk() {
+        let off = TimingOffsets::default();
+        let deep = read_file("../test_data/Deep.ncs").expect("read deep");
+        let mut funk = read_file("../test_data/Funk.ncs").expect("read funk");
+        let original = funk.clone();
+
+        let deep_timing = Timing::from_bytes(&deep, &off).expect("deep timing");
+        let text = toml::to_string(&Groove::from_timing(&deep_timing)).expect("serialize groove");
+        let groove: Groove = toml::from_str(&text).expect("parse groove");
+        groove.apply(&mut funk, &off).expect("apply groove");
+
+        let funk_timing = Timing::from_bytes(&funk, &off).expect("funk timing");
+        assert_eq!(funk_timing.tempo, deep_timing.tempo);
+        assert_eq!(funk_timing.swing, deep_timing.swing);
+        assert_eq!(funk_timing.swing_sync_rate, deep_timing.swing_sync_rate);
+        // Only the timing bytes may change
+        for (i, (a, b)) in original.iter().zip(&funk).enumerate() {
+            if a != b { assert!((off.tempo..=off.swing_sync_rate).contains(&i), "unexpected change at {:#x}", i); }
+        }
+
+        // A hand-edited template out of range is refused without touching the session
+        let before = funk.clone();
+        for bad in ["tempo = 250\nswing = 50\nswing_sync_rate = 0\n", "tempo = 120\nswing = 90\nswing_sync_rate = 0\n", "tempo = 120\nswing = 50\nswing_sync_rate = 9\n"] {
+            let groove: Groove = toml::from_str(bad).expect("parse groove");
+            let err = groove.apply(&mut funk, &off).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", bad);
+        }
+        assert_eq!(funk, before);
+    }
+
+    #[test]
+    fn journal_undo_restores_tempo() {
+        let off = TimingOffsets::default();
+        let deep = read_file("../test_data/Deep.ncs").expect("read deep");
+        let dir = std::env::temp_dir().join(format!("ncs_journal_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (out, log) = (dir.join("edited.ncs"), dir.join("edits.log"));
+        let _ = std::fs::remove_file(&log);
+
+        let mut edited = deep.clone();
+        Groove { tempo: 120, swing: 50, swing_sync_rate: 0 }.apply(&mut edited, &off).expect("apply groove");
+        write_edit(out.to_str().unwrap(), &deep, &edited, log.to_str(), "apply-groove").expect("journaled write");
+        assert_eq!(Timing::from_bytes(&read_file(out.to_str().unwrap()).unwrap(), &off).unwrap().tempo, 120);
+
+        let mut data = read_file(out.to_str().unwrap()).unwrap();
+        let mut records = journal::read(&log).expect("read journal");
+        let record = undo_last(&mut data, &mut records).expect("undo");
+        assert_eq!(record.command, "apply-groove");
+        assert!(records.is_empty());
+        assert_eq!(Timing::from_bytes(&data, &off).unwrap().tempo, Timing::from_bytes(&deep, &off).unwrap().tempo);
+        assert_eq!(data, deep);
+        // A record no longer matches once its edit is undone
+        assert!(record.revert(&mut data).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn master_volume_set_and_validated() {
+        let off = GlobalsOffsets::default();
+        let mut data = vec![0u8; off.master_volume + 1];
+        data[off.master_volume] = 0x40;
+        assert_eq!(Globals::from_bytes(&data, &off).unwrap().master_volume, 64);
+        set_master_volume(&mut data, 100, &GlobalsOffsets::default()).expect("in range");
+        assert_eq!(data[off.master_volume], 100);
+        set_master_volume(&mut data, MAX_MASTER_VOLUME, &GlobalsOffsets::default()).expect("max is accepted");
+        assert_eq!(data[off.master_volume], MAX_MASTER_VOLUME);
+        // Out of range is rejected and leaves the byte alone
+        assert!(set_master_volume(&mut data, MAX_MASTER_VOLUME + 1, &GlobalsOffsets::default()).is_err());
+        assert_eq!(data[off.master_volume], MAX_MASTER_VOLUME);
+        // Truncated buffers fail instead of panicking
+        assert!(Globals::from_bytes(&data[..off.master_volume], &off).is_err());
+    }
+
+    #[test]
+    fn binarized_velocity_matrix_is_a_trigger_grid() {
+        let csv = plane_matrix_csv(&load_drums("../test_data/Funk.ncs"), 0, true);
+        let row: Vec<u8> = csv.lines().find(|l| l.starts_with("2,2,")).expect("row for track 2 pattern 2")
+            .split(',').skip(2).map(|v| v.parse().expect("numeric cell")).collect();
+        assert_eq!(row.len(), STEPS);
+        assert!(row.iter().all(|&v| v <= 1));
+        assert_eq!((row[0], row[4]), (1, 1));
+        assert!(row[16..].iter().all(|&v| v == 0), "bars 3-4 should be rests");
+    }
+
+    #[test]
+    fn mirrored_tracks_reported_only_for_duplicates() {
+        let mut drums = load_drums("../test_data/Funk.ncs");
+        assert!(mirrored_tracks(&drums, 1.0).iter().all(|&(a, b, _)| (a, b) != (0, 3)));
+        drums.tracks[3].patterns = drums.tracks[0].patterns.clone();
+        let mirrors = mirrored_tracks(&drums, 1.0);
+        assert!(mirrors.contains(&(0, 3, 1.0)), "{:?}", mirrors);
+        // One differing step breaks an exact mirror but passes a looser threshold
+        drums.tracks[3].patterns[0].steps[1].velocity ^= 0x01;
+        assert!(!mirrored_tracks(&drums, 1.0).iter().any(|&(a, b, _)| (a, b) == (0, 3)));
+        assert!(mirrored_tracks(&drums, 0.99).iter().any(|&(a, b, _)| (a, b) == (0, 3)));
+    }
+
+    #[test]
+    fn plane_order_detected_for_deep() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let guesses = detect_plane_order(&data, &Offsets::default(), &Layout::default(), DEFAULT_TOLERANCE).expect("detect");
+        let role = |slot: usize| guesses.iter().find(|g| g.slot == slot).unwrap().role;
+        // Probability is 7 on every step, so it shows up as the constant probability-like slot
+        assert_eq!(guesses[1].slot, 1);
+        assert_eq!(role(1), PlaneRole::ProbabilityLike);
+        // Choice and mask follow velocity step for step
+        assert_eq!(role(2), PlaneRole::Step);
+        assert_eq!(role(3), PlaneRole::Step);
+        assert!(guesses.iter().filter(|g| g.role == PlaneRole::Step).all(|g| g.agreement >= 0.95));
+        let order: Vec<usize> = guesses.iter().map(|g| g.slot).collect();
+        assert_eq!(&order[..4], &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn plane_order_tolerance_decides_marginal_step_plane() {
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let off = Offsets::default();
+        // Set the choice slot on 2% of the rests so it follows velocity only approximately
+        let choice = off.velocity + 2 * STEPS;
+        let mut rests = Vec::new();
+        for t in 0..TRACKS {
+            for p in 0..PATTERNS {
+                let base = t * off.track_stride + p * off.pattern_stride;
+                rests.extend((0..STEPS).filter(|&s| data[off.velocity + base + s] == 0).map(|s| choice + base + s));
+            }
+        }
+        let total = (TRACKS * PATTERNS * STEPS) as f64;
+        for &i in rests.iter().take((total * 0.02) as usize) { data[i] ^= 0x01; }
+        let role = |tolerance: f64| detect_plane_order(&data, &off, &Layout::default(), tolerance).unwrap().into_iter().find(|g| g.slot == 2).unwrap().role;
+        assert_eq!(role(DEFAULT_TOLERANCE), PlaneRole::Step);
+        assert_ne!(role(0.01), PlaneRole::Step);
+        assert!(parse_tolerance("1.5").is_err());
+    }
+
+    #[test]
+    fn validate_fix_repairs_tempo_and_keeps_backup() {
+        let off = TimingOffsets::default();
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        data[off.tempo] = 250;
+        let dir = std::env::temp_dir().join(format!("ncs_validate_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("broken.ncs");
+        std::fs::write(&path, &data).unwrap();
+
+        let fixes = validate_session(path.to_str().unwrap(), None, None, true).expect("validate --fix");
+        assert_eq!(fixes, vec![FieldDiff { field: "tempo".into(), before: "250".into(), after: "240".into() }]);
+        let repaired = std::fs::read(&path).unwrap();
+        assert_eq!(Timing::from_bytes(&repaired, &off).unwrap().tempo, 240);
+        assert_eq!(std::fs::read(dir.join("broken.ncs.bak")).unwrap(), data);
+        assert!(!dir.join("broken.ncs.tmp").exists());
+        // A repaired file validates clean
+        assert!(validate_session(path.to_str().unwrap(), None, None, false).unwrap().is_empty());
+
+        // Provisional fields are left alone even when out of range
+        data[off.tempo] = 120;
+        data[GlobalsOffsets::default().master_volume] = 0xF0;
+        std::fs::write(&path, &data).unwrap();
+        assert!(validate_session(path.to_str().unwrap(), None, None, true).unwrap().is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+
+        // A failed replace (here: no original to back up) cleans up its temp file
+        let missing = dir.join("missing.ncs");
+        assert!(replace_atomically(&missing, &data).is_err());
+        assert!(!dir.join("missing.ncs.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_fix_refuses_gzip_and_follows_the_profile() {
+        let dir = std::env::temp_dir().join(format!("ncs_validate_gz_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A .gz session can be validated but is never overwritten with raw bytes
+        let gz = dir.join("d.ncs.gz");
+        std::fs::copy("../test_data/Deep.ncs.gz", &gz).unwrap();
+        let original = std::fs::read(&gz).unwrap();
+        assert!(validate_session(gz.to_str().unwrap(), None, None, false).unwrap().is_empty());
+        let err = validate_session(gz.to_str().unwrap(), None, None, true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err);
+        assert_eq!(std::fs::read(&gz).unwrap(), original);
+        assert!(!dir.join("d.ncs.gz.bak").exists());
+
+        // Anything that is not a session is rejected before a byte is "repaired"
+        let junk = dir.join("junk.bin");
+        std::fs::write(&junk, vec![0xFFu8; 0x20000]).unwrap();
+        assert!(validate_session(junk.to_str().unwrap(), None, None, true).is_err());
+        assert_eq!(std::fs::read(&junk).unwrap(), vec![0xFFu8; 0x20000]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Rules sit at the profile's offsets, not the defaults
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let moved = OffsetProfile { timing: TimingOffsets { tempo: 0x1000, ..TimingOffsets::default() }, ..OffsetProfile::default() };
+        data[0x1000] = 250;
+        let fixes = repair_session(&mut data, &moved).expect("repair");
+        assert_eq!(fixes, vec![FieldDiff { field: "tempo".into(), before: "250".into(), after: "240".into() }]);
+        assert_eq!(data[0x1000], 240);
+    }
+
+    #[test]
+    fn micro_timing_window_prefers_centered_signed_plane() {
+        let off = Offsets::default();
+        let len = off.velocity + (TRACKS - 1) * off.track_stride + PATTERNS * off.pattern_stride;
+        let mut data = vec![0u8; len];
+        let (nudge, noise) = (off.velocity + 0x100, off.velocity + 0x120);
+        let mut rng = 0x2468_ACE1u32;
+        for t in 0..TRACKS {
+            for p in 0..PATTERNS {
+                let base = t * off.track_stride + p * off.pattern_stride;
+                for s in 0..STEPS {
+                    rng ^= rng << 13; rng ^= rng >> 17; rng ^= rng << 5;
+                    data[noise + base + s] = rng as u8;
+                    if s % 2 == 0 {
+                        data[off.velocity + base + s] = 96;
+                        // Alternating small pushes and pulls: -6, +6, -3, +3, ...
+                        let v: i8 = if s % 8 < 4 { 6 } else { 3 };
+                        data[nudge + base + s] = (if s % 4 == 0 { -v } else { v }) as u8;
+                    }
+                }
+            }
+        }
+        let scores = micro_timing_scores(&data, &off, &Layout::default(), off.velocity + STEPS, off.velocity + 0x200, 1.0).expect("scores");
+        assert_eq!(scores[0].0, nudge);
+        let noise_score = scores.iter().find(|(c, _)| *c == noise).unwrap().1;
+        assert!(scores[0].1 > 0.9 && noise_score < 0.5, "{} vs {}", scores[0].1, noise_score);
+        // The default tolerance drops the noise plane, which is non-zero on its rests
+        let strict = micro_timing_scores(&data, &off, &Layout::default(), off.velocity + STEPS, off.velocity + 0x200, DEFAULT_TOLERANCE).expect("scores");
+        assert_eq!(strict[0].0, nudge);
+        assert!(strict.iter().all(|(c, _)| *c != noise));
+    }
+
+    #[test]
+    fn protobuf_export_round_trips_funk() {
+        use prost::Message;
+        let session = Session::from_bytes(&read_file("../test_data/Funk.ncs").expect("read funk")).expect("parse funk");
+        let bytes = proto::encode(&session);
+        let decoded = proto::Session::decode(bytes.as_slice()).expect("decode protobuf");
+        assert_eq!(decoded.timing.as_ref().unwrap().tempo, 174);
+        assert_eq!(decoded.name, session.name.name);
+        assert_eq!(decoded.tracks.len(), TRACKS);
+        assert_eq!(decoded.tracks[2].patterns[2].steps[0].velocity, 96);
+        assert_eq!(decoded.tracks[2].patterns[2].steps[1].velocity, session.drums.tracks[2].patterns[2].steps[1].velocity as u32);
+    }
+
+    #[test]
+    fn entropy_ranks_velocity_above_constant_probability() {
+        let entropy = plane_entropy(&load_drums("../test_data/Deep.ncs"));
+        let bits = |name: &str| entropy.iter().find(|(n, _)| *n == name).unwrap().1;
+        assert!(bits("probability").abs() < 1e-9);
+        assert!(bits("velocity") > 1.0);
+        assert!(entropy.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+
+    #[test]
+    fn probability_digit_follows_documented_range() {
+        let max = PLANE_INFO[1].max as u8;
+        assert!(step_symbol_styled(96, max, &RenderStyle::default()).ends_with(char::from_digit(max as u32, 10).unwrap()));
+        // Out-of-range values are flagged instead of wrapping onto a legal digit
+        assert!(step_symbol_styled(96, max + 1, &RenderStyle::default()).ends_with('?'));
+        // A 0..15 range shows its maximum as a single unambiguous digit, not 15 % 10
+        let wide = RenderStyle { prob_digit: ProbDigit::Range(15), ..RenderStyle::default() };
+        assert!(step_symbol_styled(96, 15, &wide).ends_with('f'));
+        let legacy = RenderStyle { prob_digit: ProbDigit::Decimal, ..RenderStyle::default() };
+        assert!(step_symbol_styled(96, 15, &legacy).ends_with('5'));
+    }
+
+    #[test]
+    fn probability_percent_and_raw_keep_columns_aligned() {
+        let percent = RenderStyle { prob_digit: ProbFormat::Percent.style(false), ..RenderStyle::default() };
+        assert_eq!(step_symbol_styled(96, 7, &percent), "█100%");
+        assert_eq!(step_symbol_styled(96, 4, &percent), "█ 57%");
+        assert_eq!(step_symbol_styled(96, 0, &percent), "█  0%");
+        assert_eq!(step_symbol_styled(96, 9, &percent), "█   ?");
+        assert_eq!(step_symbol_styled(0, 7, &percent), ".    ");
+        let raw = RenderStyle { prob_digit: ProbFormat::Raw.style(false), ..RenderStyle::default() };
+        assert_eq!(step_symbol_styled(96, 12, &raw), "█ 12");
+
+        // Every bar of a mixed pattern renders to the same number of columns
+        let drums = load_drums("../test_data/Funk.ncs");
+        let grid = render_ascii_styled(&drums.tracks[2].patterns[2].steps, true, &percent);
+        let widths: Vec<usize> = grid.lines().map(|l| l.chars().count()).collect();
+        assert!(widths.windows(2).all(|w| w[0] == w[1]), "{:?}\n{}", widths, grid);
+        assert_eq!(ProbFormat::Digit.style(true), ProbDigit::Decimal);
+    }
+
+    #[test]
+    fn rle_lists_active_steps_with_velocities() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let steps = &drums.tracks[2].patterns[2].steps;
+        let rle = render_rle(steps);
+        let pairs: Vec<(usize, u8)> = rle.split(' ')
+            .map(|pair| { let (s, v) = pair.split_once(':').expect("step:velocity"); (s.parse().unwrap(), v.parse().unwrap()) })
+            .collect();
+        let expected: Vec<(usize, u8)> = steps.iter().enumerate().filter(|(_, st)| st.velocity > 0).map(|(i, st)| (i, st.velocity)).collect();
+        assert_eq!(pairs, expected);
+        assert_eq!(pairs[0], (0, 96));
+        assert!(pairs.iter().all(|&(s, _)| s < 16));
+    }
+
+    #[test]
+    fn profile_diff_reports_moved_field_with_delta() {
+        let v1 = OffsetProfile::builtin("v1").expect("built-in v1");
+        let mut moved = v1.clone();
+        moved.timing.swing += 0x10;
+        // Round-trip through TOML like a profile file on disk
+        let moved: OffsetProfile = toml::from_str(&toml::to_string(&moved).unwrap()).expect("parse profile");
+        let diffs = profile_diff(&v1, &moved).expect("diff");
+        assert_eq!(diffs, vec![("timing.swing".to_string(), 0x35, 0x45)]);
+        assert!(profile_diff(&v1, &v1).unwrap().is_empty());
+        // Partial profiles fall back to the built-in values
+        let partial: OffsetProfile = toml::from_str("[fx]\nreverb_preset = 0x26D20\n").unwrap();
+        assert_eq!(profile_diff(&v1, &partial).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn strict_planes_rejects_planes_too_close() {
+        assert!(Offsets::default().check_plane_layout(&Layout::default()).is_ok());
+        let base = 0x0CD74;
+        let tight = Offsets {
+            velocity: base, probability: base + 0x08, choice: base + 0x10, mask: base + 0x18,
+            pitch: base + 0x20, decay: base + 0x28, distortion: base + 0x30, eq: base + 0x38,
+            ..Offsets::default()
+        };
+        let err = tight.check_plane_layout(&Layout::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("velocity") && err.to_string().contains("probability"), "{}", err);
+        // Planes spread wider than a pattern are rejected too
+        let wide = Offsets { eq: base + 0x700, ..Offsets::default() };
+        assert!(wide.check_plane_layout(&Layout::default()).is_err());
+    }
+
+    #[test]
+    fn param_planes_render_and_validate() {
+        assert!(param_in_range(4, 63) && !param_in_range(4, 64));
+        assert!(param_in_range(5, 127) && !param_in_range(5, 128));
+        assert!(param_in_range(7, PARAM_UNSET));
+        let drums = load_drums("../test_data/Deep.ncs");
+        for st in drums.tracks.iter().flat_map(|t| t.patterns.iter()).flat_map(|p| p.steps.iter()) {
+            for (plane, _) in PARAM_PLANES { assert!(param_in_range(plane, st.planes()[plane])); }
+        }
+        // Rests still show their stored values
+        let mut steps: [Step; STEPS] = std::array::from_fn(|_| Step { velocity: 0, probability: 7, choice: 0, mask: 0, pitch: 0xFE, decay: 40, distortion: PARAM_UNSET, eq: 0 });
+        steps[8].pitch = 3;
+        let pitch = render_param_row(&steps, 4);
+        assert!(pitch.starts_with(" -2") && pitch.contains("|  +3"), "{}", pitch);
+        assert!(render_param_row(&steps, 5).starts_with(" 40"));
+        assert!(render_param_row(&steps, 6).starts_with(" --"));
+        // Known bytes now include the four parameter planes
+        let data = read_file("../test_data/Deep.ncs").unwrap();
+        assert_eq!(compute_known_bytes(&data, &Offsets::default(), &Layout::default(), &FxOffsets::default()), 8 * TRACKS * PATTERNS * STEPS + 2);
+    }
+
+    #[test]
+    fn coverage_only_counts_planes_that_validate() {
+        let off = Offsets::default();
+        let mut data = read_file("../test_data/Funk.ncs").unwrap();
+        assert_eq!(validated_planes(&data, &off, &Layout::default()).len(), 8);
+        // Only the profile's layout is counted
+        let two_tracks = Layout { tracks: 2, ..Layout::default() };
+        assert!(validated_planes(&data, &off, &two_tracks).iter().all(|&(_, c)| c == 2 * PATTERNS * STEPS));
+        let full = compute_known_bytes(&data, &off, &Layout::default(), &FxOffsets::default());
+        // One sample index past the kit and one unconfirmed mask bit drop both planes
+        data[off.choice + off.track_stride + 5] = DRUM_SAMPLES;
+        data[off.mask + 3 * off.pattern_stride] = 0b10;
+        let planes: Vec<usize> = validated_planes(&data, &off, &Layout::default()).iter().map(|&(p, _)| p).collect();
+        assert_eq!(planes, [0, 1, 4, 5, 6, 7]);
+        assert_eq!(compute_known_bytes(&data, &off, &Layout::default(), &FxOffsets::default()), full - 2 * TRACKS * PATTERNS * STEPS);
+        let line = coverage_line(&data, &off, &Layout::default(), &FxOffsets::default());
+        assert!(line.contains("steps[velocity,probability,pitch,decay,distortion,eq]"), "{}", line);
+        assert!(plane_value_valid(2, CHOICE_NONE) && plane_value_valid(2, 63) && !plane_value_valid(1, 8));
+    }
+
+    #[test]
+    fn truncated_drum_data_is_an_error_not_a_panic() {
+        let err = DrumData::from_bytes(&[0u8; 1024], &Offsets::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(err.to_string().contains("velocity plane") && err.to_string().contains("track 0 pattern 0 step 0"), "{}", err);
+        // Cut just inside the last eq row: the error names eq and the first missing step
+        let data = read_file("../test_data/Deep.ncs").unwrap();
+        let off = Offsets::default();
+        let end = off.eq + (TRACKS - 1) * off.track_stride + (PATTERNS - 1) * off.pattern_stride + 5;
+        let err = DrumData::from_bytes(&data[..end], &off).unwrap_err();
+        assert!(err.to_string().contains("eq plane at track 3 pattern 7 step 5"), "{}", err);
+    }
+
+    #[test]
+    fn session_json_includes_every_step_byte() {
+        let session = Session::from_bytes(&read_file("../test_data/Deep.ncs").expect("read deep")).expect("parse deep");
+        let json: serde_json::Value = serde_json::from_str(&session_json(&session, false, &TrackNames::default(), &ChoiceNames::default()).expect("serialize")).expect("valid JSON");
+        let step = &json["drums"]["tracks"][2]["patterns"][2]["steps"][0];
+        assert_eq!(step["velocity"], 96);
+        assert_eq!(step["probability"], 7);
+        assert_eq!(step.as_object().unwrap().len(), 9);
+        assert_eq!(step["pitch_semitones"], serde_json::json!(session.drums.tracks[2].patterns[2].steps[0].pitch_semitones()));
+        assert_eq!(json["timing"]["tempo"], 174);
+        assert_eq!(json["scenes"]["scenes"].as_array().unwrap().len(), 16);
+        assert!(json.get("stats").is_none());
+    }
+
+    #[test]
+    fn session_round_trips_byte_identical() {
+        for path in ["../test_data/Deep.ncs", "../test_data/Funk.ncs"] {
+            let data = read_file(path).expect("read session");
+            let session = Session::from_bytes(&data).expect("parse session");
+            let mut out = data.clone();
+            session.to_bytes(&mut out).expect("write session");
+            assert!(out == data, "{} did not round-trip", path);
+
+            // Scribbling over every parsed region first proves the writer restores it all
+            let mut scribbled = data.clone();
+            let mut blank = session.clone();
+            blank.drums = DrumData::default();
+            blank.scenes = Scenes::default();
+            blank.to_bytes(&mut scribbled).unwrap();
+            session.to_bytes(&mut scribbled).unwrap();
+            assert!(scribbled == data, "{} did not restore after overwrite", path);
+
+            // Editing one velocity changes exactly one byte
+            let mut edited = session.clone();
+            edited.drums.tracks[1].patterns[3].steps[5].velocity = 77;
+            let mut buf = data.clone();
+            edited.to_bytes(&mut buf).unwrap();
+            assert_eq!(buf.iter().zip(&data).filter(|(a, b)| a != b).count(), 1);
+        }
+    }
+
+    #[test]
+    fn offsets_config_loads_and_is_bounds_checked() {
+        let dir = std::env::temp_dir().join(format!("ncs_offsets_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+
+        // A partial TOML profile overrides one field and keeps the rest
+        let toml_path = dir.join("moved.toml");
+        std::fs::write(&toml_path, "[timing]\ntempo = 0x35\n").unwrap();
+        let profile = OffsetProfile::load(toml_path.to_str().unwrap()).expect("load toml");
+        assert_eq!(profile.timing.tempo, 0x35);
+        assert_eq!(profile.drums, Offsets::default());
+        let session = Session::from_bytes_with(&data, &profile).expect("parse with profile");
+        assert_eq!(session.timing.tempo, 54); // the swing byte, read as tempo
+
+        // JSON works too, and an offset past EOF is reported by field name
+        let json_path = dir.join("far.json");
+        std::fs::write(&json_path, r#"{"fx": {"reverb_preset": 200000}}"#).unwrap();
+        let far = OffsetProfile::load(json_path.to_str().unwrap()).expect("load json");
+        let err = Session::from_bytes_with(&data, &far).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("fx.reverb_preset"), "{}", err);
+        assert!(OffsetProfile::default().check_bounds(data.len()).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commands_follow_the_profile_layout() {
+        let dir = std::env::temp_dir().join(format!("ncs_layout_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let profile_path = dir.join("small.json");
+        std::fs::write(&profile_path, r#"{"layout": {"tracks": 2, "steps": 16}}"#).unwrap();
+        let (prof, out) = (profile_path.to_str().unwrap(), dir.join("out.ncs"));
+        let small = ProfileArgs { offsets: Some(prof.to_string()), force_version: None };
+        let edit = |track: &str| run(Cli::try_parse_from(["ncs", "edit", "../test_data/Funk.ncs", "-o", out.to_str().unwrap(),
+            "--offsets", prof, "--track", track, "--pattern", "1", "--reverse"]).expect("parse args"));
+
+        assert_eq!(small.drums("../test_data/Funk.ncs").expect("parse").layout(), Layout { tracks: 2, patterns: PATTERNS, steps: 16 });
+
+        // Reversing a pattern only moves the 16 steps the layout covers
+        edit("1").expect("edit");
+        let (data, edited) = (read_file("../test_data/Funk.ncs").unwrap(), std::fs::read(&out).unwrap());
+        let off = Offsets::default();
+        let row = off.velocity + off.track_stride + off.pattern_stride;
+        let reversed: Vec<u8> = data[row..row + 16].iter().rev().copied().collect();
+        assert_ne!(reversed, data[row..row + 16]);
+        assert_eq!(edited[row..row + 16], reversed[..]);
+        assert_eq!(edited[row + 16..row + STEPS], data[row + 16..row + STEPS]);
+        assert_eq!(edit("2").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // A rhythm longer than the layout's patterns is refused rather than matched on a prefix
+        assert!(find_in_file("../test_data/Funk.ncs", &ProfileArgs::default(), &parse_rhythm(&"x".repeat(17)).unwrap(), false).is_ok());
+        assert!(find_in_file("../test_data/Funk.ncs", &small, &parse_rhythm(&"x".repeat(17)).unwrap(), false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cli_subcommands_parse_and_missing_files_fail_cleanly() {
+        let cli = Cli::try_parse_from(["ncs-tui", "coverage", "a.ncs"]).expect("coverage");
+        assert!(matches!(cli.command, Some(Command::Coverage(ref a)) if a.file == "a.ncs"));
+        let cli = Cli::try_parse_from(["ncs-tui", "render", "a.ncs", "--fold-bars"]).expect("render");
+        assert!(matches!(cli.command, Some(Command::Render(ref a)) if a.fold_bars));
+        // A bare path still means `dump`
+        let cli = Cli::try_parse_from(["ncs-tui", "a.ncs"]).expect("bare path");
+        assert!(cli.command.is_none());
+        assert_eq!(cli.dump.file.as_deref(), Some("a.ncs"));
+        let err = Cli::try_parse_from(["ncs-tui", "--version"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+
+        let err = read_file("../test_data/missing.ncs").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with("Cannot open ../test_data/missing.ncs"), "{}", err);
+    }
+
+    #[test]
+    fn scale_names_fall_back_to_raw_value() {
+        let scale = ScaleSettings { root: 1, scale_type: 15 };
+        assert_eq!(scale.root_name(), "C#");
+        assert_eq!(scale.scale_type_name(), "Chromatic");
+        let bad = ScaleSettings { root: 13, scale_type: 16 };
+        assert_eq!(bad.root_name(), "?(13)");
+        assert_eq!(bad.scale_type_name(), "?(16)");
+    }
+
+    #[test]
+    fn pattern_use_flags_empty_and_exact_duplicates() {
+        let mut drums = load_drums("../test_data/Funk.ncs");
+        drums.tracks[2].patterns[5] = Pattern::default();
+        drums.tracks[2].patterns[6] = drums.tracks[2].patterns[2].clone();
+        drums.tracks[2].patterns[7] = drums.tracks[2].patterns[2].clone();
+        // A parameter lock on an otherwise identical pattern is not a duplicate
+        drums.tracks[2].patterns[7].steps[0].pitch ^= 1;
+        let uses = classify_patterns(&drums);
+        assert_eq!(uses[2][5], PatternUse::Empty);
+        assert!(matches!(uses[2][6], PatternUse::DuplicateOf(q) if q <= 2));
+        assert_eq!(uses[2][7], PatternUse::Used);
+        let text = render_pattern_use(&uses, &TrackNames::default());
+        assert!(text.contains("P05 empty"), "{}", text);
+        assert!(text.starts_with("Track 0: "), "{}", text);
+        let named = render_pattern_use(&uses, &TrackNames::parse("Kick,Snare,Hat").unwrap());
+        assert!(named.contains("\nHat: ") && named.contains("\nTrack 3: "), "{}", named);
+    }
+
+    #[test]
+    fn pattern_stats_ignore_rests_in_mean_velocity() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let st = drums.tracks[2].patterns[2].stats();
+        let hits: Vec<u8> = drums.tracks[2].patterns[2].steps.iter().map(|s| s.velocity).filter(|&v| v > 0).collect();
+        assert_eq!(st.onsets, hits.len());
+        assert!(st.onsets <= 16, "bars 3-4 are rests");
+        assert_eq!(st.density, st.onsets as f64 * 100.0 / 32.0);
+        let mean = hits.iter().map(|&v| v as f64).sum::<f64>() / hits.len() as f64;
+        assert!((st.mean_velocity - mean).abs() < 1e-9);
+        assert_eq!(st.probability.iter().sum::<usize>(), st.onsets);
+        assert_eq!(Pattern::default().stats().mean_velocity, 0.0);
+
+        let session = Session::from_bytes(&read_file("../test_data/Funk.ncs").unwrap()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&session_json(&session, true, &TrackNames::default(), &ChoiceNames::default()).unwrap()).unwrap();
+        assert_eq!(json["stats"][2][2]["onsets"], st.onsets);
+    }
+
+    #[test]
+    fn diff_reports_fields_steps_and_length() {
+        let data = read_file("../test_data/Funk.ncs").expect("read funk");
+        assert!(diff_files(&data, &data, &ProfileArgs::default()).expect("self diff").is_empty());
+
+        let mut edited = data.clone();
+        edited[Offsets::default().velocity + 2 * 0x3540 + 2 * 0x6A8] = 100;
+        edited[TimingOffsets::default().tempo] = 120;
+        edited.push(0);
+        let lines: Vec<String> = diff_files(&data, &edited, &ProfileArgs::default()).expect("diff").iter().map(|d| d.to_string()).collect();
+        assert_eq!(lines[0], format!("file length {}→{}", data.len(), data.len() + 1));
+        assert!(lines.iter().any(|l| l.starts_with("tempo ") && l.ends_with("→120")), "{:?}", lines);
+        assert!(lines.contains(&"Track 2 Pattern 2 Step 0: velocity 96→100".to_string()), "{:?}", lines);
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn steps_csv_has_one_row_per_step_with_raw_bytes() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let csv = steps_csv(&drums, &ChoiceNames::default());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "track,pattern,step,velocity,probability,choice,mask,pitch,decay,distortion,eq,pitch_semitones");
+        assert_eq!(lines.len(), 1 + TRACKS * PATTERNS * STEPS);
+        let st = &drums.tracks[2].patterns[2].steps[0];
+        let row = lines[1 + (2 * PATTERNS + 2) * STEPS];
+        let mut expected: Vec<String> = st.planes().iter().map(|v| v.to_string()).collect();
+        expected.push(st.pitch_semitones().to_string());
+        assert_eq!(row, format!("2,2,0,{}", expected.join(",")));
+        assert!(row.starts_with("2,2,0,96,"));
+    }
+
+    #[test]
+    fn color_is_opt_in_and_plain_output_unchanged() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        let steps = &drums.tracks[2].patterns[2].steps;
+        let plain = render_ascii_styled(steps, true, &RenderStyle::default());
+        assert_eq!(plain, render_ascii(steps, true));
+        assert!(!plain.contains('\x1b'));
+        let colored = render_ascii_styled(steps, true, &RenderStyle { color: true, ..RenderStyle::default() });
+        assert!(colored.starts_with("\x1b[38;5;"), "{:?}", colored);
+        // Stripping the escapes gives back the plain grid
+        let stripped: String = colored.split("\x1b[").map(|part| part.split_once('m').map_or(part, |(_, rest)| rest)).collect();
+        assert_eq!(stripped, plain);
+
+        assert!(ColorChoice::Auto.enabled(None, true));
+        assert!(!ColorChoice::Auto.enabled(Some("1"), true));
+        assert!(ColorChoice::Auto.enabled(Some(""), true));
+        assert!(!ColorChoice::Auto.enabled(None, false));
+        assert!(ColorChoice::Always.enabled(Some("1"), false));
+        assert!(!ColorChoice::Never.enabled(None, true));
     }
 
     #[test]
-    fn funk_track2_pattern02_structure() {
+    fn svg_has_one_rect_per_hit() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        let hits = |tracks: &[usize]| tracks.iter().map(|&t| drums.tracks[t].patterns[2].steps.iter().filter(|st| st.velocity > 0).count()).sum::<usize>();
+        let svg = render_svg(&drums, &[2], 2, &TrackNames::default());
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains(">T2</text>") && svg.contains(">Pattern 2</text>"));
+        assert_eq!(svg.matches("<rect").count(), hits(&[2]));
+        let all: Vec<usize> = (0..TRACKS).collect();
+        assert_eq!(render_svg(&drums, &all, 2, &TrackNames::default()).matches("<rect").count(), hits(&all));
+        // Named rows are escaped and the grid starts past the longest name
+        let named = render_svg(&drums, &all, 2, &TrackNames::parse("Kick,Snare,Closed Hat,Hat & Ride").unwrap());
+        assert!(named.contains(">Closed Hat</text>") && named.contains(">Hat &amp; Ride</text>"), "{}", named);
+        assert!(named.contains("<text x=\"88\" y=\"16\">Pattern 2</text>"), "{}", named);
+    }
+
+    #[test]
+    fn parse_warnings_are_collected_with_offsets() {
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let profile = OffsetProfile::default();
+        let (_, clean) = Session::from_bytes_checked(&data, &profile).expect("parse deep");
+        assert!(clean.is_empty(), "{:?}", clean);
+        assert!(reject_warnings(&clean).is_ok());
+
+        data[profile.timing.tempo] = 250;
+        data[profile.chains.scene_chain_base + 2] = 1;
+        let (session, warnings) = Session::from_bytes_checked(&data, &profile).expect("parse edited");
+        assert_eq!(session.timing.tempo, 250);
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert_eq!((warnings[0].category, warnings[0].offset), (WarningCategory::Range, profile.timing.tempo));
+        assert_eq!(warnings[0].to_string(), "Tempo out of range: 250 (expected 40..240)");
+        assert_eq!((warnings[1].category, warnings[1].offset), (WarningCategory::Padding, profile.chains.scene_chain_base + 2));
+        let err = reject_warnings(&warnings).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("2 validation warning(s)"), "{}", err);
+    }
+
+    #[test]
+    fn provisional_plane_ranges_warn_but_never_fail_strict() {
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let profile = OffsetProfile::default();
+        data[profile.drums.decay] = 200;
+        let (_, warnings) = Session::from_bytes_checked(&data, &profile).expect("parse edited");
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert_eq!((warnings[0].category, warnings[0].offset), (WarningCategory::Provisional, profile.drums.decay));
+        assert!(reject_warnings(&warnings).is_ok());
+        assert!(report_warnings(&warnings, true).is_ok());
+        // Confirmed ranges still fail
+        data[profile.timing.tempo] = 250;
+        let (_, warnings) = Session::from_bytes_checked(&data, &profile).expect("parse edited");
+        assert!(warnings.iter().any(|w| w.category == WarningCategory::Range), "{:?}", warnings);
+        assert!(reject_warnings(&warnings).is_err());
+    }
+
+    #[test]
+    fn strict_warnings_get_their_own_exit_code() {
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        data[TimingOffsets::default().tempo] = 250;
+        let (_, warnings) = Session::from_bytes_checked(&data, &OffsetProfile::default()).expect("parse");
+        let err = report_warnings(&warnings, true).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_STRICT);
+        assert!(report_warnings(&warnings, false).is_ok());
+        assert!(report_warnings(&[], true).is_ok());
+        // Malformed files and I/O problems keep the generic code
+        assert_eq!(exit_code(&Session::from_bytes(&data[..100]).unwrap_err().into()), 1);
+        assert_eq!(exit_code(&read_file("../test_data/missing.ncs").unwrap_err()), 1);
+    }
+
+    #[test]
+    fn parse_failures_carry_structured_errors() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        match Session::from_bytes(&data[..0x100]) {
+            Err(NcsError::Truncated { needed, got, .. }) => assert!(got == 0x100 && needed > got, "{} {}", needed, got),
+            other => panic!("expected Truncated, got {:?}", other.map(|_| ())),
+        }
+        let r = ByteReader::new(&data[..10]);
+        match r.u32_le(8, "probe") {
+            Err(NcsError::Truncated { field, needed, got }) => assert_eq!((field.as_str(), needed, got), ("probe", 12, 10)),
+            other => panic!("expected Truncated, got {:?}", other),
+        }
+        let mut foreign = data.clone();
+        foreign[..4].copy_from_slice(b"RIFF");
+        assert!(matches!(Session::from_bytes(&foreign), Err(NcsError::BadMagic { ref found }) if found == "RIFF"));
+
+        // main prints a hint per variant, and plain I/O errors unchanged
+        let err: io::Error = Session::from_bytes(&foreign).unwrap_err().into();
+        assert!(error_message(&err).ends_with("(is this a .ncs file saved by Components?)"), "{}", error_message(&err));
+        let err = read_file("../test_data/missing.ncs").unwrap_err();
+        assert_eq!(error_message(&err), err.to_string());
+    }
+
+    #[test]
+    fn coverage_map_coalesces_claimed_bytes() {
+        assert_eq!(coverage_runs(&[true, true, false, true]), vec![(true, 0..2), (false, 2..3), (true, 3..4)]);
+        assert!(coverage_runs(&[]).is_empty());
+
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let runs = coverage_runs(&claimed_bytes(&data, &OffsetProfile::default()).expect("claim"));
+        // Runs alternate, tile the whole file, and start with the magic + length header
+        assert!(runs.windows(2).all(|w| w[0].0 != w[1].0 && w[0].1.end == w[1].1.start));
+        assert_eq!((runs[0].1.start, runs.last().unwrap().1.end), (0, data.len()));
+        assert!(runs[0].0 && runs[0].1.end >= 8);
+        let off = Offsets::default();
+        assert!(runs.iter().any(|(k, r)| *k && r.contains(&off.velocity)));
+        assert!(render_coverage_map(&runs[..1]).starts_with("0x000000..0x000008 known"));
+    }
+
+    #[test]
+    fn format_version_is_detected_forced_or_defaulted() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        assert_eq!(detect_versions(&data), vec!["v1"]);
+        assert_eq!(select_profile(&data, None, None).expect("select").1, "v1 (detected)");
+        assert_eq!(select_profile(&data, None, Some("v1")).expect("select").1, "v1 (forced)");
+
+        // An insane tempo or an unexpected length matches nothing, so the defaults are used
+        let mut odd = data.clone();
+        odd[TimingOffsets::default().tempo] = 0;
+        assert!(detect_versions(&odd).is_empty());
+        assert!(detect_versions(&data[..data.len() - 1]).is_empty());
+        let (profile, label) = select_profile(&odd, None, None).expect("select");
+        assert_eq!((profile, label.as_str()), (OffsetProfile::default(), "v1 (default)"));
+
+        assert!(Cli::try_parse_from(["ncs-tui", "a.ncs", "--force-version", "v9"]).is_err());
+        assert!(Cli::try_parse_from(["ncs-tui", "a.ncs", "--force-version", "v1", "--offsets", "v1"]).is_err());
+    }
+
+    #[test]
+    fn swing_timeline_delays_every_second_sixteenth() {
+        let mut timing = Timing::from_bytes(&read_file("../test_data/Deep.ncs").unwrap(), &TimingOffsets::default()).unwrap();
+        timing.swing = 50;
+        let out = render_swing(&timing);
+        let rows: Vec<&str> = out.lines().collect();
+        assert_eq!(rows[0].split('|').nth(1), rows[1].split('|').nth(1));
+        timing.swing = 75;
+        let swung = render_swing(&timing);
+        let hits: Vec<usize> = swung.lines().nth(1).unwrap().split('|').nth(1).unwrap().match_indices('x').map(|(i, _)| i).collect();
+        assert_eq!(hits, vec![0, 12, 16, 28]);
+        // Out-of-range values are drawn at the nearest legal swing and say so
+        timing.swing = 250;
+        let clamped = render_swing(&timing);
+        assert!(clamped.contains("swing 80 (250 clamped)"), "{}", clamped);
+        assert!(clamped.lines().all(|l| l.find('|') == clamped.lines().next().unwrap().find('|')));
+    }
+
+    #[test]
+    fn scene_table_shows_ranges_and_marks_bad_entries() {
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let off = ScenesOffsets::default();
+        data[off.base + off.scene_stride + 2 * off.entry_stride] = 5; // scene 1 entry 2: 5..0
+        let r = ByteReader::new(&data);
+        let scenes = Scenes::parse(&r, &off).expect("scenes");
+        assert!(r.take_warnings().iter().any(|w| w.category == WarningCategory::Order));
+
+        let out = render_scenes(&scenes, false);
+        assert_eq!(out.lines().count(), 17);
+        assert_eq!(out.lines().nth(1).unwrap().split_whitespace().collect::<Vec<_>>(),
+                   ["S00", "0..7", "0..7", "0..0", "0..0", "1..1", "2..2", "0..0", "2..2"]);
+        assert!(out.lines().nth(2).unwrap().contains("5..0!"));
+        assert!(out.contains("S15   (empty)"));
+
+        let nonempty = render_scenes(&scenes, true);
+        assert_eq!(nonempty.lines().count(), 4);
+        assert!(!nonempty.contains("(empty)"));
+        assert!(Cli::try_parse_from(["ncs-tui", "a.ncs", "--nonempty-scenes"]).is_err());
+    }
+
+    #[test]
+    fn chains_expand_into_play_order() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let off = ChainOffsets::default();
+        let chain = SceneChain::from_bytes(&data, &off).expect("scene chain");
+        assert_eq!(chain.play_order(), vec![0, 1, 2]);
+        let chains = PatternChains::from_bytes(&data, &off).expect("pattern chains");
+        assert_eq!(chains.play_order()[0], (0..8).collect::<Vec<u8>>());
+        assert_eq!(chains.play_order()[6], vec![2]);
+
+        // A reversed chain, already warned about by the parser, plays nothing
+        let reversed = SceneChain { start_scene: 5, end_scene: 2, pad: 0 };
+        assert!(reversed.play_order().is_empty());
+        let out = render_play_order(&reversed, &chains);
+        assert!(out.starts_with("Scenes: (nothing: end < start)\n"), "{}", out);
+        assert!(out.contains("Slot 5: 1\n") && out.contains("Slot 0: 0 → 1 → 2"), "{}", out);
+    }
+
+    #[test]
+    fn session_reads_from_a_stream_and_stops_at_its_length() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let mut stream = io::Cursor::new([data.clone(), b"trailing".to_vec()].concat());
+        let session = Session::from_reader(&mut stream, &OffsetProfile::default()).expect("from reader");
+        assert_eq!(session.timing.tempo, 174);
+        assert_eq!(stream.position() as usize, data.len());
+
+        // A stream that is not seekable works the same way
+        let mut chained = io::Read::chain(&data[..8], &data[8..]);
+        assert!(Session::from_reader(&mut chained, &OffsetProfile::default()).is_ok());
+
+        assert!(matches!(Session::from_reader(&mut &data[..6], &OffsetProfile::default()),
+            Err(NcsError::Truncated { needed: 8, got: 6, .. })));
+        assert!(matches!(Session::from_reader(&mut &data[..0x100], &OffsetProfile::default()), Err(NcsError::Truncated { .. })));
+        assert!(matches!(Session::from_reader(&mut &b"RIFF\0\0\0\0"[..], &OffsetProfile::default()), Err(NcsError::BadMagic { .. })));
+    }
+
+    #[test]
+    fn gzip_sessions_are_decompressed_on_read() {
+        let plain = read_file("../test_data/Deep.ncs").expect("read deep");
+        assert_eq!(read_file("../test_data/Deep.ncs.gz").expect("read gz"), plain);
+
+        // Fixed-Huffman and stored blocks, as written by gzip for tiny inputs
+        let fixed = [31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 11, 13, 118, 13, 82, 200, 72, 205, 201, 201, 71, 38, 1, 143, 146, 222, 1, 22, 0, 0, 0];
+        assert_eq!(gzip::decompress(&fixed).expect("fixed"), b"USER hello hello hello");
+        let stored = [31, 139, 8, 0, 0, 0, 0, 0, 4, 3, 1, 6, 0, 249, 255, 115, 116, 111, 114, 101, 100, 11, 249, 67, 86, 6, 0, 0, 0];
+        assert_eq!(gzip::decompress(&stored).expect("stored"), b"stored");
+
+        // Damage is reported as a gzip problem, not as a truncated session
+        let gz = std::fs::read("../test_data/Deep.ncs.gz").unwrap();
+        let cut = gunzip_if_compressed("cut.ncs.gz", gz[..gz.len() / 2].to_vec()).unwrap_err();
+        assert!(cut.to_string().starts_with("cut.ncs.gz is gzip-compressed but could not be decompressed"), "{}", cut);
+        let mut flipped = gz.clone();
+        let n = flipped.len();
+        flipped[n - 8] ^= 1;
+        assert_eq!(gzip::decompress(&flipped).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let bad_crc = gunzip_if_compressed("crc.ncs.gz", flipped).unwrap_err();
+        assert!(bad_crc.to_string().starts_with("crc.ncs.gz is gzip-compressed but could not be decompressed"), "{}", bad_crc);
+        assert!(gunzip_if_compressed("plain.gz", plain.clone()).is_err());
+        assert_eq!(gunzip_if_compressed("plain.ncs", plain.clone()).unwrap(), plain);
+    }
+
+    #[test]
+    fn integrity_reports_only_nonzero_reserved_fields() {
+        let mut data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let profile = OffsetProfile::default();
+        assert!(reserved_warnings(&data, &profile).expect("scan").is_empty());
+        assert_eq!(render_integrity(&[]), "All reserved fields are zero\n");
+
+        data[profile.timing.tempo] = 250;
+        data[profile.timing.spare2] = 9;
+        data[profile.scenes.base + profile.scenes.scene_stride + 2] = 1;
+        data[profile.chains.scene_chain_base + 3] = 1;
+        let found = reserved_warnings(&data, &profile).expect("scan");
+        let offsets: Vec<usize> = found.iter().map(|w| w.offset).collect();
+        assert_eq!(offsets, [profile.timing.spare2, profile.scenes.base + profile.scenes.scene_stride + 2, profile.chains.scene_chain_base + 2]);
+        let report = render_integrity(&found);
+        assert!(report.starts_with("3 reserved field(s) not zero:\n"), "{}", report);
+        assert!(report.contains(&format!("0x{:05X}  Session timing spare2 not set to zero: 9", profile.timing.spare2)), "{}", report);
+    }
+
+    #[test]
+    fn set_tempo_changes_only_the_timing_bytes() {
+        let data = read_file("../test_data/Funk.ncs").expect("read funk");
+        let out = std::env::temp_dir().join(format!("ncs_set_tempo_{}.ncs", std::process::id()));
+        let args = |tempo: &str, swing: Option<&str>| {
+            let mut argv = vec!["ncs", "set-tempo", "../test_data/Funk.ncs", "-o", out.to_str().unwrap(), "--tempo", tempo];
+            if let Some(sw) = swing { argv.extend(["--swing", sw]); }
+            match Cli::try_parse_from(argv).expect("parse args").command {
+                Some(Command::SetTempo(args)) => args,
+                other => panic!("unexpected command {:?}", other),
+            }
+        };
+        set_tempo(&args("97", Some("61"))).expect("set tempo");
+        let edited = std::fs::read(&out).expect("read output");
+        let off = TimingOffsets::default();
+        let changed: Vec<usize> = (0..data.len()).filter(|&i| data[i] != edited[i]).collect();
+        assert!(changed.iter().all(|&i| i == off.tempo || i == off.swing), "{:?}", changed);
+        let timing = Timing::from_bytes(&edited, &off).expect("reparse");
+        assert_eq!((timing.tempo, timing.swing), (97, 61));
+
+        set_tempo(&args("120", None)).expect("tempo only");
+        let swing_kept = Timing::from_bytes(&std::fs::read(&out).expect("read output"), &off).expect("reparse");
+        assert_eq!(swing_kept.swing, Timing::from_bytes(&data, &off).expect("parse").swing);
+
+        std::fs::remove_file(&out).expect("remove output");
+        let err = set_tempo(&args("241", None)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("tempo (40..240)"), "{}", err);
+        assert!(set_tempo(&args("120", Some("90"))).is_err());
+        assert!(!out.exists(), "rejected edits must not write a file");
+    }
+
+    #[test]
+    fn huge_strides_fail_cleanly_instead_of_overflowing() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let r = ByteReader::new(&data);
+        let is_overflow = |e: &NcsError| matches!(e, NcsError::OffsetOverflow { .. });
+
+        // Scene 1 already lands far past the end, before the stride could overflow
+        let scenes = ScenesOffsets { scene_stride: usize::MAX / 2, ..ScenesOffsets::default() };
+        assert!(matches!(Scenes::parse(&r, &scenes).unwrap_err(), NcsError::Truncated { .. }));
+        let err = Scenes::parse(&r, &ScenesOffsets { scene_stride: usize::MAX, ..ScenesOffsets::default() }).unwrap_err();
+        assert!(is_overflow(&err), "{}", err);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let chains = ChainOffsets { pattern_chain_stride: usize::MAX, ..ChainOffsets::default() };
+        assert!(is_overflow(&PatternChains::parse(&r, &chains).unwrap_err()));
+        let chains = ChainOffsets { scene_chain_base: usize::MAX, ..ChainOffsets::default() };
+        assert!(matches!(SceneChain::parse(&r, &chains).unwrap_err(), NcsError::Truncated { .. }));
+
+        let drums = Offsets { track_stride: usize::MAX, ..Offsets::default() };
+        let err = DrumData::parse(&r, &drums).unwrap_err();
+        assert!(is_overflow(&err), "{}", err);
+        assert!(err.to_string().starts_with("Offset of velocity plane overflows"), "{}", err);
+        let sends = Offsets { sends: SendOffsets { track_stride: usize::MAX, ..SendOffsets::default() }, ..Offsets::default() };
+        assert!(is_overflow(&DrumData::parse(&r, &sends).unwrap_err()));
+        let mut out = data.clone();
+        assert!(load_drums("../test_data/Deep.ncs").to_bytes(&drums, &mut out).is_err());
+
+        let profile = OffsetProfile { drums, ..OffsetProfile::default() };
+        assert!(profile.check_bounds(data.len()).is_err());
+        let err = Session::from_bytes_checked(&data, &profile).unwrap_err();
+        assert!(err.to_string().contains("drums.velocity reaches 0xFFFFFFFFFFFFFFFF"), "{}", err);
+        assert!(error_message(&NcsError::OffsetOverflow { field: "scenes".into(), base: 0x40 }.into()).contains("offset config"));
+    }
+
+    #[test]
+    fn tap_tempo_averages_intervals_and_stops_at_eof() {
+        use std::time::Duration;
+        let ms = |v: &[u64]| v.iter().map(|&m| Duration::from_millis(m)).collect::<Vec<_>>();
+        assert_eq!(tap::bpm(&ms(&[0, 500, 1000, 1500])), Some(120));
+        // Uneven taps average out: 3 intervals over 1.6 s
+        assert_eq!(tap::bpm(&ms(&[0, 450, 1100, 1600])), Some(113));
+        assert_eq!(tap::bpm(&ms(&[0, 100])), Some(240));
+        assert_eq!(tap::bpm(&ms(&[0, 5000])), Some(40));
+        assert_eq!(tap::bpm(&ms(&[300])), None);
+        assert_eq!(tap::bpm(&[]), None);
+
+        let mut clock = ms(&[0, 400, 800]).into_iter();
+        let mut progress = Vec::new();
+        let taps = tap::collect(io::Cursor::new("\n\n\n"), &mut progress, || clock.next().unwrap()).expect("collect");
+        assert_eq!(tap::bpm(&taps), Some(150));
+        assert_eq!(String::from_utf8(progress).unwrap(), "tap 1\ntap 2: 150 BPM\ntap 3: 150 BPM\n");
+        let taps = tap::collect(io::Cursor::new("\nq\n\n"), &mut Vec::new(), || Duration::ZERO).expect("collect");
+        assert_eq!(taps.len(), 1);
+    }
+
+    #[test]
+    fn header_rejects_foreign_and_truncated_files() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        assert!(validate_header(&data, &OffsetProfile::default()).is_ok());
+
+        let err = validate_header(&data[..0x100], &OffsetProfile::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(err.to_string().starts_with("File is too small: 256 bytes"), "{}", err);
+        assert!(Session::from_bytes(&data[..0x100]).is_err());
+
+        let mut foreign = data.clone();
+        foreign[..4].copy_from_slice(b"RIFF");
+        let err = validate_header(&foreign, &OffsetProfile::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("found \"RIFF\""), "{}", err);
+        assert!(validate_header(b"US", &OffsetProfile::default()).is_err());
+        // Extents come from the profile in use, not the built-in layout; a complete file
+        // it overruns blames the offset config
+        let mut reaching = OffsetProfile::default();
+        reaching.globals.master_volume = data.len();
+        let err = validate_header(&data, &reaching).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("globals.master_volume"), "{}", err);
+        assert_eq!(validate_header(&data[..0x100], &reaching).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // A wrong declared length parses, with a header warning
+        let mut longer = data.clone();
+        longer.push(0);
+        let (_, warnings) = Session::from_bytes_checked(&longer, &OffsetProfile::default()).expect("parse");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].category, warnings[0].offset), (WarningCategory::Header, HEADER_LENGTH_OFFSET));
+    }
+
+    #[test]
+    fn euclid_spreads_pulses_with_bjorklund() {
+        let hits = |p: &Pattern, n: usize| -> String { p.steps[..n].iter().map(|st| if st.velocity > 0 { 'x' } else { '.' }).collect() };
+        assert_eq!(hits(&Pattern::euclid(3, 8, 100).unwrap(), 8), "x..x..x.");
+        assert_eq!(hits(&Pattern::euclid(5, 8, 100).unwrap(), 8), "x.xx.xx.");
+        assert_eq!(hits(&Pattern::euclid(4, 16, 100).unwrap(), 16), "x...x...x...x...");
+        let p = Pattern::euclid(3, 8, 100).unwrap();
+        assert!(p.steps[8..].iter().all(|st| st.velocity == 0));
+        assert!(p.steps.iter().all(|st| st.probability == DEFAULT_PROBABILITY));
+        assert_eq!(p.steps[0].velocity, 100);
+        // Pulses clamp to steps; more than 32 steps is an error
+        assert_eq!(Pattern::euclid(12, 8, 90).unwrap().stats().onsets, 8);
+        assert_eq!(Pattern::euclid(0, 32, 90).unwrap().stats().onsets, 0);
+        assert_eq!(Pattern::euclid(4, 33, 90).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(Pattern::euclid(4, 33, 90), Err(NcsError::OutOfRange { value: 33, .. })));
+
+        // Generated steps are byte-identical to a stock rest and hit from Deep.ncs
+        let deep = load_drums("../test_data/Deep.ncs");
+        let stock = |hit: bool| deep.steps().map(|(_, _, _, st)| st)
+            .find(|st| (st.velocity > 0) == hit && st.choice == CHOICE_NONE && st.pitch == PITCH_CENTER && st.decay == PARAM_UNSET && st.distortion == PARAM_UNSET && st.eq == PARAM_UNSET)
+            .expect("stock step").planes();
+        let stock_hit = stock(true);
+        let p = Pattern::euclid(3, 8, stock_hit[0]).unwrap();
+        assert_eq!(p.steps[1].planes(), stock(false));
+        assert_eq!(p.steps[0].planes(), stock_hit);
+        assert_eq!(Step::hit(stock_hit[0]).planes(), stock_hit);
+    }
+
+    #[test]
+    fn pattern_transforms_keep_step_payloads() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let orig = drums.tracks[2].patterns[2].clone();
+        let same = |a: &Pattern, b: &Pattern| a.steps.iter().zip(&b.steps).all(|(x, y)| x.planes() == y.planes());
+
+        let mut p = orig.clone();
+        p.rotate(32);
+        assert!(same(&p, &orig));
+        p.rotate(3);
+        assert_eq!(p.steps[3].planes(), orig.steps[0].planes());
+        assert_eq!(p.steps[0].planes(), orig.steps[29].planes());
+        p.rotate(-3);
+        assert!(same(&p, &orig));
+
+        let mut p = orig.clone();
+        p.reverse();
+        assert_eq!(p.steps[31].planes(), orig.steps[0].planes());
+        p.reverse();
+        assert!(same(&p, &orig));
+
+        let mut p = orig.clone();
+        p.shift(2);
+        // Shifted-in steps are byte-identical to the file's most common rest
+        let mut rests: Vec<[u8; 8]> = drums.steps().filter(|(_, _, _, st)| st.velocity == 0).map(|(_, _, _, st)| st.planes()).collect();
+        rests.sort();
+        let stock = rests.chunk_by(|a, b| a == b).max_by_key(|run| run.len()).expect("rests")[0];
+        assert!(p.steps[..2].iter().all(|st| st.planes() == stock), "{:?} vs {:?}", p.steps[0].planes(), stock);
+        assert_eq!(p.steps[2].planes(), orig.steps[0].planes());
+        let mut p = orig.clone();
+        p.shift(-40);
+        assert!(p.is_empty());
+
+        let cli = Cli::try_parse_from(["ncs-tui", "edit", "a.ncs", "-o", "b.ncs", "--track", "1", "--pattern", "0", "--rotate", "-2"]).expect("edit");
+        assert!(matches!(cli.command, Some(Command::Edit(ref e)) if e.op.rotate == Some(-2)));
+        assert!(Cli::try_parse_from(["ncs-tui", "edit", "a.ncs", "-o", "b.ncs", "--track", "1", "--pattern", "0", "--reverse", "--shift", "1"]).is_err());
+    }
+
+    #[test]
+    fn clean_prob_snaps_stray_values_and_keeps_clean_files_identical() {
+        let offsets = Offsets::default();
+        let original = read_file("../test_data/Deep.ncs").expect("read deep");
+        let mut data = original.clone();
+        assert!(clean_probabilities(&mut data, &offsets, &Layout::default(), None, None).unwrap().is_empty());
+        assert_eq!(data, original);
+
+        // A stray value on a hit is snapped and reported; the same value on a rest is left alone
+        let drums = DrumData::from_bytes(&original, &offsets).unwrap();
+        let (t, p, s) = (0..TRACKS).flat_map(|t| (0..PATTERNS).flat_map(move |p| (0..STEPS).map(move |s| (t, p, s))))
+            .find(|&(t, p, s)| drums.tracks[t].patterns[p].steps[s].velocity > 0).unwrap();
+        let at = offsets.probability + t * offsets.track_stride + p * offsets.pattern_stride + s;
+        data[at] = 12;
+        let rest_at = offsets.probability + (0..STEPS).find(|&s| drums.tracks[0].patterns[0].steps[s].velocity == 0).unwrap();
+        data[rest_at] = 12;
+        let warnings = clean_probabilities(&mut data, &offsets, &Layout::default(), None, None).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].offset, at);
+        assert!(warnings[0].message.ends_with("probability 12 snapped to 7"), "{}", warnings[0].message);
+        assert_eq!((data[at], data[rest_at]), (7, 12));
+
+        assert!(Cli::try_parse_from(["ncs-tui", "edit", "a.ncs", "-o", "b.ncs", "--clean-prob"]).is_ok());
+        assert!(Cli::try_parse_from(["ncs-tui", "edit", "a.ncs", "-o", "b.ncs", "--reverse"]).is_err());
+    }
+
+    #[test]
+    fn histogram_counts_every_step_of_a_plane() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        let velocity = plane_histogram(&drums, 0);
+        assert_eq!(velocity.iter().sum::<usize>(), TRACKS * PATTERNS * STEPS);
+        let hits = drums.tracks.iter().flat_map(|t| t.patterns.iter()).flat_map(|p| p.steps.iter()).filter(|st| st.velocity > 0).count();
+        assert_eq!(velocity[0], TRACKS * PATTERNS * STEPS - hits);
+
+        let out = render_histogram(&velocity);
+        let values: Vec<usize> = out.lines().map(|l| l.split(':').next().unwrap().trim().parse().unwrap()).collect();
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+        // The most common value gets the full-width bar
+        assert!(out.lines().any(|l| l.ends_with(&"#".repeat(HISTOGRAM_BAR))));
+        assert!(out.starts_with(&format!("  0: {:>5} ", velocity[0])), "{}", out);
+        assert!(Cli::try_parse_from(["ncs-tui", "histogram", "a.ncs", "--plane", "wobble"]).is_err());
+    }
+
+    #[test]
+    fn steps_iterator_walks_every_step_in_order() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let steps = drums.steps();
+        assert_eq!(steps.len(), TRACKS * PATTERNS * STEPS);
+        let all: Vec<(usize, usize, usize, &Step)> = steps.collect();
+        assert_eq!(all.len(), 4 * 8 * 32);
+        for (i, &(t, p, s, st)) in all.iter().enumerate() {
+            assert_eq!((t, p, s), (i / (PATTERNS * STEPS), i / STEPS % PATTERNS, i % STEPS));
+            assert!(std::ptr::eq(st, &drums.tracks[t].patterns[p].steps[s]));
+        }
+        let mut tail = drums.steps().skip(TRACKS * PATTERNS * STEPS - 1);
+        assert_eq!(tail.next().map(|(t, p, s, _)| (t, p, s)), Some((TRACKS - 1, PATTERNS - 1, STEPS - 1)));
+        assert!(tail.next().is_none());
+    }
+
+    #[test]
+    fn layout_sets_track_pattern_and_step_counts() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        let profile = OffsetProfile { layout: Layout { tracks: 2, patterns: 4, steps: 16 }, ..OffsetProfile::default() };
+        let (session, _) = Session::from_bytes_checked(&data, &profile).expect("parse 2x4x16");
+        let drums = &session.drums;
+        assert_eq!(drums.layout(), profile.layout);
+        assert_eq!(drums.steps().len(), 2 * 4 * 16);
+        let full = load_drums("../test_data/Deep.ncs");
+        assert!(drums.steps().all(|(t, p, s, st)| st.planes() == full.tracks[t].patterns[p].steps[s].planes()));
+
+        // Writing back touches only the bytes inside the smaller layout
+        let mut edited = drums.clone();
+        edited.tracks[1].patterns[3].steps[15].velocity = 99;
+        let mut out = data.clone();
+        edited.to_bytes(&profile.drums, &mut out).expect("write");
+        let at = profile.drums.velocity + profile.drums.track_stride + 3 * profile.drums.pattern_stride + 15;
+        assert_eq!(out[at], 99);
+        assert_eq!(out.iter().zip(&data).filter(|(a, b)| a != b).count(), usize::from(data[at] != 99));
+        assert!(edited.copy_pattern(2, 0, 0, 0).unwrap_err().to_string().contains("tracks 0..1, patterns 0..3"));
+
+        let toml: OffsetProfile = toml::from_str("[layout]\nsteps = 16\n").expect("toml");
+        assert_eq!(toml.layout, Layout { tracks: TRACKS, patterns: PATTERNS, steps: 16 });
+        assert_eq!(Layout::default().total_steps(), 1024);
+    }
+
+    #[test]
+    fn similarity_weights_shared_onsets_by_velocity() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let patt = &drums.tracks[2].patterns[2];
+        assert_eq!(patt.similarity(patt), 1.0);
+        assert_eq!(Pattern::rests(STEPS).similarity(&Pattern::rests(STEPS)), 1.0);
+
+        let mut a = Pattern::rests(STEPS);
+        let mut b = a.clone();
+        for s in [0, 8, 16, 24] { a.steps[s].velocity = 127; }
+        for s in [0, 8, 16] { b.steps[s].velocity = 127; }
+        b.steps[4].velocity = 127;
+        // 3 shared onsets out of 5 steps with a hit
+        assert!((a.similarity(&b) - 0.6).abs() < 1e-6);
+        b.steps[0].velocity = 1;
+        let expect = (1.0 / 127.0 + 2.0) / 5.0;
+        assert!((a.similarity(&b) - expect).abs() < 1e-6);
+        assert_eq!(a.similarity(&b), b.similarity(&a));
+        assert_eq!(a.similarity(&Pattern::rests(STEPS)), 0.0);
+
+        let mut drums = drums.clone();
+        drums.tracks[0].patterns[5] = patt.clone();
+        let pairs = similar_patterns(&drums, 0.99);
+        assert!(pairs.iter().any(|&(x, y, score)| (x, y) == ((0, 5), (2, 2)) && score == 1.0), "{:?}", pairs);
+        assert!(similar_patterns(&drums, 0.0).windows(2).all(|w| w[0].2 >= w[1].2));
+    }
+
+    #[test]
+    fn fragment_round_trip_reproduces_the_original_bytes() {
+        let off = Offsets::default();
+        let data = read_file("../test_data/Funk.ncs").expect("read funk");
+        let frag = fragment::Fragment::extract(&data, &off, &Layout::default(), 2, 2).expect("extract");
+        let bytes = frag.to_bytes();
+        assert!(bytes.starts_with(&fragment::FRAGMENT_MAGIC));
+        let back = fragment::Fragment::from_bytes(&bytes).expect("reparse");
+        assert_eq!(back, frag);
+        assert_eq!(back.plane_offsets[0] as usize, off.velocity);
+
+        // Injecting into the slot it came from changes nothing
+        let mut same = data.clone();
+        back.inject(&mut same, &off, &Layout::default(), 2, 2).expect("inject");
+        assert_eq!(same, data);
+
+        // Into another slot: that slot now matches byte for byte, nothing else moved
+        let mut moved = data.clone();
+        back.inject(&mut moved, &off, &Layout::default(), 0, 7).expect("inject");
+        for base in off.planes() {
+            let at = |t: usize, p: usize| base + t * off.track_stride + p * off.pattern_stride;
+            assert_eq!(moved[at(0, 7)..at(0, 7) + STEPS], data[at(2, 2)..at(2, 2) + STEPS]);
+        }
+        let changed = moved.iter().zip(&data).filter(|(a, b)| a != b).count();
+        assert!(changed <= 8 * STEPS);
+        assert_eq!(fragment::Fragment::extract(&moved, &off, &Layout::default(), 0, 7).expect("extract").planes, frag.planes);
+
+        assert_eq!(fragment::Fragment::extract(&data, &off, &Layout::default(), 4, 0).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fragment::Fragment::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(fragment::Fragment::from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn compare_marks_only_bars_whose_velocities_differ() {
+        let a = load_drums("../test_data/Deep.ncs");
+        let same = render_compare(&a, &a);
+        assert!(!same.is_empty() && !same.contains(" * ") && !same.contains("differ"));
+
+        let mut b = a.clone();
+        let (t, p) = (0..TRACKS).flat_map(|t| (0..PATTERNS).map(move |p| (t, p)))
+            .find(|&(t, p)| !a.tracks[t].patterns[p].is_empty()).expect("a pattern with notes");
+        b.tracks[t].patterns[p].steps[9].velocity ^= 0x40;
+        let block = render_side_by_side(&a.tracks[t].patterns[p].steps, &b.tracks[t].patterns[p].steps);
+        let gutters: Vec<bool> = block.lines().map(|l| l.contains(" * ")).collect();
+        assert_eq!(gutters, [false, true, false, false]);
+        assert!(render_compare(&a, &b).contains(&format!("=== T{} P{:02} (1 step(s) differ) ===", t, p)));
+
+        let bars: Vec<usize> = block.lines().map(|l| l.chars().position(|c| c == '|' || c == '*').expect("gutter")).collect();
+        assert!(bars.windows(2).all(|w| w[0] == w[1]), "gutter column drifts: {:?}", bars);
+    }
+
+    #[test]
+    fn symbol_scale_defaults_to_the_even_split_and_takes_thresholds() {
+        let even = SymbolScale::default();
+        for v in 1..=127u8 {
+            assert_eq!(even.level(v, 4), (v as usize * 4) / 128);
+        }
+        let musical = SymbolScale::parse("musical").unwrap();
+        let levels: Vec<usize> = [1, 39, 40, 79, 80, 109, 110, 127].iter().map(|&v| musical.level(v, 4)).collect();
+        assert_eq!(levels, [0, 0, 1, 1, 2, 2, 3, 3]);
+        let style = RenderStyle { scale: musical, ..RenderStyle::default() };
+        // A ghost note at 35 drops a level under the musical scale
+        assert_eq!(step_symbol_styled(35, 7, &RenderStyle::default()), "▃7");
+        assert_eq!(step_symbol_styled(35, 7, &style), "▁7");
+
+        assert_eq!(SymbolScale::parse("20, 100").unwrap(), SymbolScale(vec![20, 100]));
+        assert!(SymbolScale::parse("70,30").is_err());
+        assert!(SymbolScale::parse("0,30").is_err());
+        assert!(SymbolScale::parse("loud").is_err());
+        assert!(SymbolScale(vec![20, 100]).check(4).is_err());
+        assert!(SymbolScale(vec![20, 100]).check(3).is_ok());
+    }
+
+    #[test]
+    fn partial_parse_keeps_the_tracks_before_the_cut() {
+        let data = read_file("../test_data/Deep.ncs").expect("fixture");
+        let full = load_drums("../test_data/Deep.ncs");
+        let off = Offsets::default();
+        let layout = Layout::default();
+
+        let planes = |d: &DrumData| d.steps().map(|(t, p, s, st)| (t, p, s, st.planes())).collect::<Vec<_>>();
+        let whole = DrumData::parse_partial(&ByteReader::new(&data), &off, &layout).expect("parse");
+        assert_eq!(planes(&whole), planes(&full));
+
+        // Cut inside track 2's eq rows: tracks 0 and 1 survive, sends fall back to 0
+        let cut = &data[..off.eq + 2 * off.track_stride + 5];
+        let r = ByteReader::new(cut);
+        assert!(DrumData::parse_with(&r, &off, &layout).is_err());
+        let partial = DrumData::parse_partial(&r, &off, &layout).expect("partial parse");
+        assert_eq!(partial.tracks.len(), 2);
+        assert_eq!(planes(&partial), planes(&full)[..planes(&partial).len()]);
+        assert_eq!(planes(&partial).len(), 2 * PATTERNS * STEPS);
+        assert!(partial.tracks.iter().all(|t| (t.delay_send, t.reverb_send) == (0, 0)));
+        assert!(r.take_warnings().iter().any(|w| w.message.contains("past the end")));
+        assert_eq!(salvage_summary(2, 4, cut.len()), format!("Parsed 2 of 4 tracks; file truncated at offset 0x{:X}", cut.len()));
+
+        assert!(DrumData::parse_partial(&ByteReader::new(&data[..off.velocity]), &off, &layout).expect("empty").tracks.is_empty());
+    }
+
+    // Seeded random buffers through every from_bytes: each must return Ok or an error,
+    // never panic. Half the cases are the real fixture with bytes scrambled and a random
+    // cut, so parsing gets past the magic and length checks into the plane loops.
+    // Session-shaped input three ways: the fixture with bytes overwritten, random bytes
+    // behind the magic, and plain random bytes; any of them possibly cut short.
+    fn session_bytes() -> impl Strategy<Value = Vec<u8>> {
+        let fixture = read_file("../test_data/Deep.ncs").expect("fixture");
+        let len = fixture.len();
+        let mutated = proptest::collection::vec((0..len, any::<u8>()), 0..4096).prop_map(move |edits| {
+            let mut d = fixture.clone();
+            for (i, v) in edits { d[i] = v; }
+            d
+        });
+        let behind_magic = proptest::collection::vec(any::<u8>(), 0..0x10000).prop_map(|mut d| {
+            if d.len() >= MAGIC.len() { d[..MAGIC.len()].copy_from_slice(MAGIC); }
+            d
+        });
+        let random = prop_oneof![proptest::collection::vec(any::<u8>(), 0..64), proptest::collection::vec(any::<u8>(), len..len + 64)];
+        (prop_oneof![mutated, behind_magic, random], any::<Option<proptest::sample::Index>>()).prop_map(|(mut d, cut)| {
+            if let Some(cut) = cut { d.truncate(cut.index(d.len() + 1)); }
+            d
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn random_buffers_never_panic_any_parser(data in session_bytes()) {
+            let clean = |e: NcsError| assert!(!e.to_string().is_empty());
+            let _ = Fx::from_bytes(&data, &FxOffsets::default()).map_err(clean);
+            let _ = Globals::from_bytes(&data, &GlobalsOffsets::default()).map_err(clean);
+            let _ = Timing::from_bytes(&data, &TimingOffsets::default()).map_err(clean);
+            let _ = ScaleSettings::from_bytes(&data, &ScaleOffsets::default()).map_err(clean);
+            let _ = Scenes::from_bytes(&data, &ScenesOffsets::default()).map_err(clean);
+            let _ = SceneChain::from_bytes(&data, &ChainOffsets::default()).map_err(clean);
+            let _ = PatternChains::from_bytes(&data, &ChainOffsets::default()).map_err(clean);
+            let _ = DrumData::from_bytes(&data, &Offsets::default()).map_err(clean);
+            let _ = DrumData::parse_partial(&ByteReader::new(&data), &Offsets::default(), &Layout::default()).map_err(clean);
+            let _ = Session::from_bytes(&data).map_err(clean);
+            let _ = Session::from_bytes_checked(&data, &OffsetProfile::default()).map_err(clean);
+            let _ = fragment::Fragment::from_bytes(&data);
+            let mut frag = data.clone();
+            if frag.len() >= 4 { frag[..4].copy_from_slice(&fragment::FRAGMENT_MAGIC); }
+            let _ = fragment::Fragment::from_bytes(&frag);
+        }
+    }
+
+    #[test]
+    fn verbosity_flags_override_rust_log() {
+        use log::{Level, Log, Metadata};
+        let logs = |verbose, quiet, trace, rust_log, target, level| {
+            logging::builder(verbose, quiet, trace, rust_log).build().enabled(&Metadata::builder().target(target).level(level).build())
+        };
+        assert!(logs(0, false, false, None, "ncs_tui", Level::Warn));
+        assert!(!logs(0, false, false, None, "ncs_tui", Level::Info));
+        assert!(logs(0, false, false, Some("debug"), "ncs_tui", Level::Debug));
+        // Per-module filters only open up the named module
+        assert!(logs(0, false, false, Some("ncs_tui=debug"), "ncs_tui", Level::Debug));
+        assert!(!logs(0, false, false, Some("ncs_tui=debug"), "other", Level::Info));
+        assert!(logs(1, false, false, Some("off"), "ncs_tui", Level::Info));
+        assert!(!logs(0, true, false, Some("ncs_tui=trace"), "ncs_tui", Level::Warn));
+        assert!(logs(0, true, false, None, "ncs_tui", Level::Error));
+        // --trace shows its own lines even under -q, and nothing else
+        assert!(logs(0, true, true, None, logging::TRACE_TARGET, Level::Debug));
+        assert!(!logs(0, true, true, None, "ncs_tui", Level::Debug));
+
+        let cli = Cli::try_parse_from(["ncs-tui", "integrity", "x.ncs", "-q"]).expect("quiet after a subcommand");
+        assert!(cli.quiet);
+        let cli = Cli::try_parse_from(["ncs-tui", "-vv", "x.ncs"]).expect("dump with -vv");
+        assert_eq!(cli.verbose, 2);
+        assert!(Cli::try_parse_from(["ncs-tui", "-v", "-q", "x.ncs"]).is_err());
+    }
+
+    #[test]
+    fn checksums_match_reference_vectors_and_stored_fields() {
+        assert_eq!(digest::hex(&digest::sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(digest::hex(&digest::sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // Two-block message: the padding spills into a second block
+        assert_eq!(digest::hex(&digest::sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(digest::hex(&file_digest(ChecksumAlgorithm::Crc32, b"123456789")), "cbf43926");
+
+        let mut data = read_file("../test_data/Deep.ncs").expect("fixture");
+        let region = ChecksumOffsets { algorithm: ChecksumAlgorithm::Crc32, stored: 0x3C, start: 0x40, end: data.len() };
+        let crc = digest::crc32(&data[0x40..]);
+        data[0x3C..0x40].copy_from_slice(&crc.to_le_bytes());
+        let (stored, computed) = stored_checksum(&data, &region).expect("in range");
+        assert_eq!(stored, computed);
+        data[0x1000] ^= 1;
+        let (stored, computed) = stored_checksum(&data, &region).expect("in range");
+        assert_ne!(stored, computed);
+        assert!(stored_checksum(&data, &ChecksumOffsets { end: data.len() + 1, ..region }).is_err());
+
+        let toml = "[checksum]\nalgorithm = \"sha256\"\nstored = 16\nstart = 64\nend = 128\n";
+        let profile: OffsetProfile = toml::from_str(toml).expect("profile");
+        assert_eq!(profile.checksum.map(|c| (c.algorithm, c.stored)), Some((ChecksumAlgorithm::Sha256, 16)));
+        assert!(OffsetProfile::default().checksum.is_none());
+    }
+
+    #[test]
+    fn choice_names_label_locks_json_and_csv() {
+        let names: ChoiceNames = toml::from_str("tracks = [[\"Kick 1\", \"Kick 2\", \"Kick 3\"], [], [\"\", \"Snare 2\"]]").expect("table");
+        assert_eq!(names.get(0, 2), Some("Kick 3"));
+        assert_eq!(names.get(0, 3), None);
+        assert_eq!(names.get(2, 0), None);
+        assert_eq!(names.get(0, CHOICE_NONE), None);
+        assert!(ChoiceNames::default().is_empty() && !names.is_empty());
+
+        let steps = [Step { velocity: 96, choice: 2, ..Step::rest() }, Step { velocity: 96, choice: 7, ..Step::rest() }];
+        assert_eq!(render_locks(&steps, 0, &names), "0:0b00000000[]c2(Kick 3) 1:0b00000000[]c7");
+        assert_eq!(render_locks(&steps, 0, &ChoiceNames::default()), "0:0b00000000[]c2 1:0b00000000[]c7");
+
+        let mut session = Session::from_bytes(&read_file("../test_data/Deep.ncs").expect("read deep")).expect("parse deep");
+        session.drums.tracks[0].patterns[1].steps[4].choice = 1;
+        let json: serde_json::Value = serde_json::from_str(&session_json(&session, false, &TrackNames::default(), &names).unwrap()).unwrap();
+        assert_eq!(json["drums"]["tracks"][0]["patterns"][1]["steps"][4]["choice_name"], "Kick 2");
+        assert_eq!(json["drums"]["tracks"][0]["patterns"][1]["steps"][4]["choice"], 1);
+
+        let csv = steps_csv(&session.drums, &names);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].starts_with("track,pattern,step,velocity,probability,choice,mask,") && lines[0].ends_with(",pitch_semitones,choice_name"));
+        let row = lines[1 + STEPS + 4];
+        assert!(row.starts_with("0,1,4,") && row.ends_with(",Kick 2"), "{}", row);
+
+        // Names with separators are quoted, so every row keeps the header's column count
+        let odd: ChoiceNames = toml::from_str("tracks = [[\"Kick, hard\", \"Say \\\"hi\\\"\"]]").expect("names");
+        session.drums.tracks[0].patterns[1].steps[5].choice = 0;
+        let csv = steps_csv(&session.drums, &odd);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[1 + STEPS + 4].ends_with(",\"Say \"\"hi\"\"\""), "{}", lines[1 + STEPS + 4]);
+        assert!(lines[1 + STEPS + 5].ends_with(",\"Kick, hard\""), "{}", lines[1 + STEPS + 5]);
+        assert_eq!(csv_field("Kick 2"), "Kick 2");
+    }
+
+    #[test]
+    fn choice_usage_ranks_choices_and_lists_unused_samples() {
+        let mut track = DrumTrack::default();
+        let hit = |choice| Step { velocity: 100, choice, ..Step::rest() };
+        let steps = &mut track.patterns[0].steps;
+        for (s, c) in [2, 0, 2, 0, 5, CHOICE_NONE, CHOICE_NONE, CHOICE_NONE].into_iter().enumerate() {
+            steps[s] = hit(c);
+        }
+        // A rest's choice byte is not a played sample
+        steps[9] = Step { velocity: 0, choice: 1, ..Step::rest() };
+        let hist = track.choice_histogram();
+        assert_eq!(hist, [(0, 2), (2, 2), (5, 1), (CHOICE_NONE, 3)]);
+
+        assert_eq!(render_choice_usage(&hist, 0, &ChoiceNames::default()),
+            "c0 x2, c2 x2, c5 x1, default x3; most c0, least c5");
+        let names: ChoiceNames = toml::from_str("tracks = [[\"Kick 1\", \"Kick 2\", \"Kick 3\", \"Kick 4\"]]").expect("table");
+        assert_eq!(render_choice_usage(&hist, 0, &names),
+            "c0(Kick 1) x2, c2(Kick 3) x2, c5 x1, default x3; most c0(Kick 1), least c5; unused c1(Kick 2), c3(Kick 4)");
+        assert_eq!(render_choice_usage(&[], 0, &names), "no hits");
+        assert_eq!(render_choice_usage(&[(CHOICE_NONE, 4)], 0, &ChoiceNames::default()), "default x4");
+
+        let drums = load_drums("../test_data/Deep.ncs");
+        let stats = render_pattern_stats(&drums, &TrackNames::default(), &ChoiceNames::default());
+        assert_eq!(stats.lines().filter(|l| l.starts_with("choices: ")).count(), TRACKS);
+    }
+
+    #[test]
+    fn ruler_lines_up_with_every_cell() {
+        let drums = load_drums("../test_data/Deep.ncs");
+        let steps = &drums.tracks[2].patterns[2].steps;
+        let ruled = RenderStyle { ruler: true, ..RenderStyle::default() };
+        // Off by default
+        assert_eq!(render_ascii_styled(steps, true, &RenderStyle::default()), render_ascii(steps, true));
+
+        let columns = |line: &str| line.char_indices().filter(|&(i, c)| c != ' ' && (i == 0 || line[..i].ends_with(' ')))
+            .map(|(i, _)| line[..i].chars().count()).collect::<Vec<usize>>();
+        for (style, show_prob) in [(ruled.clone(), true), (ruled.clone(), false), (RenderStyle { prob_digit: ProbDigit::Percent(7), ..ruled.clone() }, true)] {
+            let out = render_ascii_styled(steps, show_prob, &style);
+            let lines: Vec<&str> = out.lines().collect();
+            assert_eq!(lines.len(), 4 * 2 + 3, "{}", out);
+            assert!(lines[2].chars().all(|c| c == '-'));
+            assert_eq!(lines[0].split_whitespace().collect::<Vec<_>>(), ["1", "2", "3", "4", "5", "6", "7", "8"]);
+            let ruler = columns(lines[0]);
+            for bar in lines.chunks(3) {
+                // Every symbol starts in the column of its step number
+                assert!(columns(bar[1]).iter().all(|c| ruler.contains(c)), "{:?}\n{}", ruler, out);
+            }
+        }
+
+        let colored = render_ascii_styled(steps, true, &RenderStyle { color: true, ..ruled.clone() });
+        let plain = render_ascii_styled(steps, true, &ruled);
+        assert_eq!(colored.lines().map(visible_width).collect::<Vec<_>>(), plain.lines().map(visible_width).collect::<Vec<_>>());
+
+        let one_line = render_ascii_styled(steps, true, &RenderStyle { bar_sep: Some("|".into()), ..ruled });
+        let lines: Vec<&str> = one_line.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let seps = |l: &str| l.chars().enumerate().filter(|&(_, c)| c == '|').map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(seps(lines[0]), seps(lines[1]));
+        assert!(Cli::try_parse_from(["ncs-tui", "x.ncs", "--ruler", "--fold-bars"]).is_err());
+    }
+
+    #[test]
+    fn gzip_inflate_stops_past_the_output_cap() {
+        // Stored blocks of zeros adding up to more than MAX_OUTPUT; rejected before the trailer
+        let mut data = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        let blocks = gzip::MAX_OUTPUT / 0xFFFF + 2;
+        for i in 0..blocks {
+            data.push(u8::from(i + 1 == blocks));
+            data.extend_from_slice(&[0xff, 0xff, 0, 0]);
+            data.extend(std::iter::repeat_n(0, 0xFFFF));
+        }
+        let err = gzip::decompress(&data).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("inflates past"), "{}", err);
+    }
+
+    #[test]
+    fn copy_pattern_is_a_deep_byte_copy() {
+        let offsets = Offsets::default();
+        let data = read_file("../test_data/Funk.ncs").expect("read funk");
+        let mut drums = DrumData::from_bytes(&data, &offsets).expect("parse");
+        drums.copy_pattern(2, 2, 0, 7).expect("copy");
+        let mut out = data.clone();
+        drums.to_bytes(&offsets, &mut out).expect("write");
+        let back = DrumData::from_bytes(&out, &offsets).expect("reparse");
+        for plane in offsets.planes() {
+            let at = |t: usize, p: usize| plane + t * offsets.track_stride + p * offsets.pattern_stride;
+            assert_eq!(out[at(0, 7)..at(0, 7) + STEPS], data[at(2, 2)..at(2, 2) + STEPS]);
+        }
+        assert!(back.tracks[2].patterns[2].steps.iter().zip(&back.tracks[0].patterns[7].steps).all(|(a, b)| a.planes() == b.planes()));
+
+        let err = drums.copy_pattern(4, 0, 0, 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().starts_with("Source track 4"), "{}", err);
+        assert!(drums.copy_pattern(0, 0, 0, 8).unwrap_err().to_string().starts_with("Destination"));
+        assert_eq!(parse_slot("1:3"), Ok((1, 3)));
+        assert!(parse_slot("13").is_err());
+    }
+
+    #[test]
+    fn overlay_keeps_the_louder_hit_with_its_parameters() {
+        let mut a = Pattern::rests(STEPS);
+        let mut b = a.clone();
+        a.steps[0] = Step { velocity: 100, pitch: 1, decay: 2, ..Step::rest() };
+        b.steps[0] = Step { velocity: 60, pitch: 9, decay: 9, ..Step::rest() };
+        b.steps[1] = Step { velocity: 90, probability: 3, choice: 4, mask: 5, pitch: 6, decay: 7, distortion: 8, eq: 9 };
+        a.steps[2] = Step { velocity: 40, eq: 1, ..Step::rest() };
+        b.steps[2] = Step { velocity: 40, eq: 2, ..Step::rest() };
+        b.steps[3] = Step { pitch: 77, ..Step::rest() };
+        let m = a.overlay(&b);
+        assert_eq!(m.steps[0].planes(), a.steps[0].planes());
+        assert_eq!(m.steps[1].planes(), b.steps[1].planes());
+        assert_eq!(m.steps[2].eq, 1, "ties keep the first pattern");
+        assert_eq!(m.steps[3].planes(), a.steps[3].planes(), "rests are absent, not louder");
+        assert_eq!(b.overlay(&a).steps[0].planes(), a.steps[0].planes());
+
+        let mut drums = load_drums("../test_data/Funk.ncs");
+        let expect = drums.tracks[0].patterns[0].overlay(&drums.tracks[2].patterns[2]);
+        drums.merge_patterns((0, 0), (2, 2), (0, 0)).expect("merge");
+        assert!(drums.tracks[0].patterns[0].steps.iter().zip(&expect.steps).all(|(x, y)| x.planes() == y.planes()));
+        let err = drums.merge_patterns((0, 0), (0, 8), (1, 1)).unwrap_err();
+        assert!(err.to_string().starts_with("Second source track 0 pattern 8"), "{}", err);
+        assert!(Cli::try_parse_from(["ncs", "merge", "s.ncs", "-o", "o.ncs", "--a", "0:0", "--b", "1:0", "--to", "0:7"]).is_ok());
+        assert!(Cli::try_parse_from(["ncs", "merge", "s.ncs", "-o", "o.ncs", "--a", "0:0", "--to", "0:7"]).is_err());
+    }
+
+    #[test]
+    fn humanize_is_deterministic_and_keeps_rests() {
+        let data = read_file("../test_data/Funk.ncs").expect("read funk");
+        let offsets = Offsets::default();
+        let run = |seed: u64| {
+            let mut drums = DrumData::from_bytes(&data, &offsets).expect("parse");
+            drums.tracks[2].patterns[2].humanize(10, seed);
+            let mut out = data.clone();
+            drums.to_bytes(&offsets, &mut out).expect("write");
+            out
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+
+        let orig = load_drums("../test_data/Funk.ncs").tracks[2].patterns[2].clone();
+        let mut patt = orig.clone();
+        patt.humanize(10, 42);
+        for (a, b) in orig.steps.iter().zip(&patt.steps) {
+            if a.velocity == 0 {
+                assert_eq!(b.velocity, 0);
+            } else {
+                assert!((1..=127).contains(&b.velocity));
+                assert!((a.velocity as i32 - b.velocity as i32).abs() <= 10);
+            }
+            assert_eq!(a.pitch, b.pitch);
+        }
+        let mut same = orig.clone();
+        same.humanize(0, 7);
+        assert!(same.steps.iter().zip(&orig.steps).all(|(a, b)| a.planes() == b.planes()));
+        assert!(Cli::try_parse_from(["ncs", "edit", "s.ncs", "-o", "o.ncs", "--track", "0", "--pattern", "0", "--humanize", "8", "--seed", "3"]).is_ok());
+        assert!(Cli::try_parse_from(["ncs", "edit", "s.ncs", "-o", "o.ncs", "--track", "0", "--pattern", "0", "--reverse", "--seed", "3"]).is_err());
+    }
+
+    #[test]
+    fn probability_scaling_clamps_and_unit_factor_is_exact() {
+        let drums = load_drums("../test_data/Funk.ncs");
+        let orig = drums.tracks[2].patterns[2].clone();
+        let mut p = orig.clone();
+        p.steps[1].velocity = 200; // out of range, must survive a 1.0 scale untouched
+        let before = p.clone();
+        p.scale_velocity(1.0);
+        p.scale_probability(1.0);
+        assert!(p.steps.iter().zip(&before.steps).all(|(a, b)| a.planes() == b.planes()));
+
+        let mut p = orig.clone();
+        p.scale_probability(0.5);
+        for (a, b) in p.steps.iter().zip(&orig.steps) {
+            if b.velocity == 0 { assert_eq!(a.probability, b.probability); } else { assert_eq!(a.probability, (b.probability as f32 * 0.5).round() as u8); }
+        }
+        p.scale_probability(10.0);
+        assert!(p.steps.iter().filter(|st| st.velocity > 0).all(|st| st.probability <= 7));
+        assert!(p.steps.iter().zip(&orig.steps).all(|(a, b)| (a.velocity == 0) == (b.velocity == 0)));
+    }
+
+    #[test]
+    fn locks_decode_mask_bits_and_choice() {
+        let hit = Step { velocity: 96, mask: 0b0000_0101, choice: 57, ..Step::rest() };
+        assert_eq!(hit.locked_params(), vec!["note", "decay?"]);
+        assert_eq!(hit.sample_choice(), 57);
+        assert!(Step { choice: CHOICE_NONE, ..Step::rest() }.locked_params().is_empty());
+
         let drums = load_drums("../test_data/Funk.ncs");
         let steps = &drums.tracks[2].patterns[2].steps;
-        // First bar (0..7): █ ▁ ▁ ▁ █ ▁ ▁ ▁
-        assert!(steps[0].velocity >= 96, "step0 expected strong hit");
-        for &i in &[1usize,2,3,5,6,7] {
-            assert!(steps[i].velocity > 0 && steps[i].velocity <= 31, "step{} expected weak hit", i);
+        assert_eq!(steps[0].locked_params(), vec!["note"]);
+        let line = render_locks(steps, 0, &ChoiceNames::default());
+        assert!(line.starts_with("0:0b00000001[note]c"), "{}", line);
+        assert_eq!(line.split(' ').count(), steps.iter().filter(|st| st.mask != 0 || st.choice != CHOICE_NONE).count());
+        assert_eq!(render_locks(&vec![Step { choice: CHOICE_NONE, ..Step::rest() }; STEPS], 0, &ChoiceNames::default()), "-");
+    }
+
+    #[test]
+    fn pitch_decodes_to_signed_semitones() {
+        // Funk T2 P02 step 0 is a hit locked to pitch byte 15
+        let drums = load_drums("../test_data/Funk.ncs");
+        let st = &drums.tracks[2].patterns[2].steps[0];
+        assert_eq!(st.pitch, 15);
+        assert_eq!(st.pitch_semitones(), 15);
+        let at = |pitch| Step { pitch, ..Step::rest() }.pitch_semitones();
+        assert_eq!(at(PITCH_CENTER), 0);
+        assert_eq!(at(PARAM_UNSET), 0);
+        assert_eq!((at(3), at(0xFE)), (3, -2));
+        assert_eq!((at(63), at(0xC1)), (63, -63));
+        // The range check is symmetric around the centre
+        assert!(param_in_range(4, 63) && param_in_range(4, 0xC1));
+        assert!(!param_in_range(4, 64) && !param_in_range(4, 0xC0));
+        for b in (0..=u8::MAX).filter(|&b| param_in_range(4, b)) {
+            assert!((-63..=63).contains(&at(b)), "pitch byte {}", b);
         }
-        assert!(steps[4].velocity >= 96, "step4 expected strong hit");
-        // Bars 3 and 4 (16..31) were rests in Funk P02
-        for i in 16..32 { assert_eq!(steps[i].velocity, 0, "expected rest at step {}", i); }
+        let row = render_param_row(&[Step { pitch: 3, ..Step::rest() }, Step { pitch: 0xFE, ..Step::rest() }, Step { pitch: 0x80, ..Step::rest() }], 4);
+        assert_eq!(row.split_whitespace().collect::<Vec<_>>(), ["+3", "-2", "128"]);
+    }
+
+    #[test]
+    fn batch_summary_matches_single_file_parse() {
+        let (line, warnings) = batch_summary("../test_data/Deep.ncs").expect("summarize deep");
+        assert!(warnings.is_empty());
+        assert!(line.starts_with("tempo 174 | scale F Chromatic | coverage "), "{}", line);
+        assert!(line.ends_with("patterns | 0 warnings"), "{}", line);
+        let used = load_drums("../test_data/Deep.ncs").tracks.iter().flat_map(|t| t.patterns.iter()).filter(|p| !p.is_empty()).count();
+        assert!(line.contains(&format!("{:>2}/32 patterns", used)), "{}", line);
+        assert!(batch_summary("../test_data/missing.ncs").is_err());
     }
-}
 
+    #[test]
+    fn stdin_input_reads_everything_and_rejects_empty() {
+        let data = read_file("../test_data/Deep.ncs").expect("read deep");
+        assert_eq!(read_stdin(io::Cursor::new(data.clone())).expect("stdin"), data);
+        let err = read_stdin(io::empty()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "No session data on stdin");
+        let cli = Cli::try_parse_from(["ncs-tui", "dump", "-"]).expect("dash is a file argument");
+        assert!(matches!(cli.command, Some(Command::Dump(ref d)) if d.file.as_deref() == Some("-")));
+    }
+}
@@ -0,0 +1,145 @@
+// Minimal Standard MIDI File support: just enough to pull note-on events out
+// of a short drum clip. No external MIDI crate is needed for this.
+
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiNote {
+    pub tick: u32,
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct MidiClip {
+    pub ticks_per_quarter: u16,
+    pub notes: Vec<MidiNote>,
+}
+
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_vlq(data: &[u8], pos: &mut usize) -> io::Result<u32> {
+    let mut value: u32 = 0;
+    for _ in 0..4 {
+        let b = *data.get(*pos).ok_or_else(|| bad("MIDI variable-length value truncated"))?;
+        *pos += 1;
+        value = (value << 7) | (b & 0x7F) as u32;
+        if b & 0x80 == 0 { return Ok(value); }
+    }
+    Err(bad("MIDI variable-length value too long"))
+}
+
+fn read_chunk<'a>(data: &'a [u8], pos: &mut usize) -> io::Result<([u8; 4], &'a [u8])> {
+    if *pos + 8 > data.len() { return Err(bad("MIDI chunk header truncated")); }
+    let id = [data[*pos], data[*pos + 1], data[*pos + 2], data[*pos + 3]];
+    let len = u32::from_be_bytes([data[*pos + 4], data[*pos + 5], data[*pos + 6], data[*pos + 7]]) as usize;
+    *pos += 8;
+    if *pos + len > data.len() { return Err(bad("MIDI chunk body truncated")); }
+    let body = &data[*pos..*pos + len];
+    *pos += len;
+    Ok((id, body))
+}
+
+/// Parse a format 0/1 SMF and return all note-on events (velocity > 0),
+/// merged across tracks and sorted by absolute tick.
+pub fn read_smf(data: &[u8]) -> io::Result<MidiClip> {
+    let mut pos = 0;
+    let (id, header) = read_chunk(data, &mut pos)?;
+    if &id != b"MThd" || header.len() < 6 { return Err(bad("Not a Standard MIDI File (missing MThd)")); }
+    let ntrks = u16::from_be_bytes([header[2], header[3]]);
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(bad("SMPTE time division is not supported"));
+    }
+
+    let mut notes = Vec::new();
+    for _ in 0..ntrks {
+        let (id, track) = read_chunk(data, &mut pos)?;
+        if &id != b"MTrk" { continue; }
+        let mut p = 0;
+        let mut tick: u32 = 0;
+        let mut running: u8 = 0;
+        while p < track.len() {
+            tick = tick.checked_add(read_vlq(track, &mut p)?).ok_or_else(|| bad("MIDI track is too long (tick count overflows)"))?;
+            let mut status = *track.get(p).ok_or_else(|| bad("MIDI event truncated"))?;
+            if status & 0x80 != 0 { p += 1; } else { status = running; }
+            match status {
+                0xFF => {
+                    p += 1; // meta type
+                    let len = read_vlq(track, &mut p)? as usize;
+                    p += len;
+                }
+                0xF0 | 0xF7 => {
+                    let len = read_vlq(track, &mut p)? as usize;
+                    p += len;
+                }
+                0x80..=0xEF => {
+                    running = status;
+                    let nbytes = if matches!(status & 0xF0, 0xC0 | 0xD0) { 1 } else { 2 };
+                    if p + nbytes > track.len() { return Err(bad("MIDI event truncated")); }
+                    if status & 0xF0 == 0x90 && track[p + 1] > 0 {
+                        notes.push(MidiNote { tick, channel: status & 0x0F, note: track[p], velocity: track[p + 1] });
+                    }
+                    p += nbytes;
+                }
+                _ => return Err(bad("MIDI running status without a previous status byte")),
+            }
+        }
+    }
+    notes.sort_by_key(|n| n.tick);
+    Ok(MidiClip { ticks_per_quarter: division, notes })
+}
+
+fn write_vlq(out: &mut Vec<u8>, mut value: u32) {
+    let mut buf = [0u8; 4];
+    let mut n = 0;
+    loop {
+        buf[n] = (value & 0x7F) as u8;
+        n += 1;
+        value >>= 7;
+        if value == 0 { break; }
+    }
+    for i in (0..n).rev() {
+        out.push(if i > 0 { buf[i] | 0x80 } else { buf[i] });
+    }
+}
+
+/// Track name meta event (FF 03), to put at tick 0 of an MTrk.
+pub fn track_name(name: &str) -> Vec<u8> {
+    let mut out = vec![0xFF, 0x03];
+    write_vlq(&mut out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+    out
+}
+
+/// One MTrk's events as (absolute tick, raw event bytes).
+pub type TrackEvents = Vec<(u32, Vec<u8>)>;
+
+/// Write a format 1 SMF with one MTrk per entry of `tracks`. Each track's events
+/// are sorted and delta-encoded.
+pub fn write_smf(ticks_per_quarter: u16, tracks: &[TrackEvents]) -> Vec<u8> {
+    let mut out = b"MThd".to_vec();
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    out.extend_from_slice(&ticks_per_quarter.to_be_bytes());
+    for events in tracks {
+        let mut sorted: Vec<&(u32, Vec<u8>)> = events.iter().collect();
+        sorted.sort_by_key(|(tick, _)| *tick);
+        let mut body = Vec::new();
+        let mut last = 0;
+        for (tick, bytes) in sorted {
+            write_vlq(&mut body, tick - last);
+            body.extend_from_slice(bytes);
+            last = *tick;
+        }
+        body.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]); // end of track
+        out.extend_from_slice(b"MTrk");
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(&body);
+    }
+    out
+}
@@ -0,0 +1,69 @@
+// Crude drum synthesis for audible pattern previews. Each drum track gets a
+// fixed voice (sine thump, noise burst, ...) scaled by step velocity; this is a
+// sanity check of the groove, not an emulation of the device's samples.
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// Seconds per step: the sequencer runs 16th notes.
+pub fn step_seconds(tempo: u8) -> f64 {
+    60.0 / tempo.max(1) as f64 / 4.0
+}
+
+// Total samples for `steps` steps at `tempo`; swing moves hits but not the length.
+pub fn pattern_samples(steps: usize, tempo: u8) -> usize {
+    (steps as f64 * step_seconds(tempo) * SAMPLE_RATE as f64).round() as usize
+}
+
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        // xorshift32: deterministic so previews are reproducible
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+fn voice(track: usize, t: f32, noise: &mut Noise) -> f32 {
+    use std::f32::consts::TAU;
+    match track % 4 {
+        // kick: pitch-dropping sine
+        0 => (TAU * (50.0 + 90.0 * (-t * 30.0).exp()) * t).sin() * (-t * 12.0).exp(),
+        // snare: noise plus a short body tone
+        1 => (noise.next() * 0.7 + (TAU * 190.0 * t).sin() * 0.3) * (-t * 20.0).exp(),
+        // closed hat: short noise
+        2 => noise.next() * (-t * 60.0).exp() * 0.6,
+        // perc: high sine blip
+        _ => (TAU * 800.0 * t).sin() * (-t * 25.0).exp() * 0.6,
+    }
+}
+
+/// Render one pattern. `tracks` pairs a track index (selects the voice) with its
+/// per-step velocities. `swing` is the device value (50 = straight, 20..80) and
+/// delays every odd step by the swung fraction of a step.
+pub fn render(tracks: &[(usize, Vec<u8>)], tempo: u8, swing: u8) -> Vec<i16> {
+    let steps = tracks.iter().map(|(_, v)| v.len()).max().unwrap_or(0);
+    let len = pattern_samples(steps, tempo);
+    let step_len = step_seconds(tempo) * SAMPLE_RATE as f64;
+    let swing_shift = (swing.clamp(20, 80) as f64 - 50.0) / 50.0 * step_len;
+    let hit_len = (0.4 * SAMPLE_RATE as f64) as usize;
+    let mut mix = vec![0f32; len];
+    let mut noise = Noise(0x1234_5678);
+    for (track, velocities) in tracks {
+        for (s, &vel) in velocities.iter().enumerate() {
+            if vel == 0 { continue; }
+            let mut start = s as f64 * step_len;
+            if s % 2 == 1 { start += swing_shift; }
+            let start = start.round().max(0.0) as usize;
+            let amp = vel.min(127) as f32 / 127.0;
+            for i in 0..hit_len.min(len.saturating_sub(start)) {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                mix[start + i] += voice(*track, t, &mut noise) * amp;
+            }
+        }
+    }
+    let gain = 1.0 / tracks.len().max(1) as f32;
+    mix.iter().map(|&x| ((x * gain).clamp(-1.0, 1.0) * i16::MAX as f32) as i16).collect()
+}
@@ -0,0 +1,155 @@
+// Protobuf form of a parsed session for `export --format protobuf`. The messages
+// are derived by hand rather than generated, so no protoc is needed at build time;
+// proto/session.proto is the matching schema for other languages.
+
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Step {
+    #[prost(uint32, tag = "1")]
+    pub velocity: u32,
+    #[prost(uint32, tag = "2")]
+    pub probability: u32,
+    #[prost(uint32, tag = "3")]
+    pub choice: u32,
+    #[prost(uint32, tag = "4")]
+    pub mask: u32,
+    #[prost(uint32, tag = "5")]
+    pub pitch: u32,
+    #[prost(uint32, tag = "6")]
+    pub decay: u32,
+    #[prost(uint32, tag = "7")]
+    pub distortion: u32,
+    #[prost(uint32, tag = "8")]
+    pub eq: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Pattern {
+    #[prost(message, repeated, tag = "1")]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DrumTrack {
+    #[prost(message, repeated, tag = "1")]
+    pub patterns: Vec<Pattern>,
+    #[prost(uint32, tag = "2")]
+    pub delay_send: u32,
+    #[prost(uint32, tag = "3")]
+    pub reverb_send: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Timing {
+    #[prost(uint32, tag = "1")]
+    pub tempo: u32,
+    #[prost(uint32, tag = "2")]
+    pub swing: u32,
+    #[prost(uint32, tag = "3")]
+    pub swing_sync_rate: u32,
+    #[prost(uint32, tag = "4")]
+    pub spare1: u32,
+    #[prost(uint32, tag = "5")]
+    pub spare2: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Scale {
+    #[prost(uint32, tag = "1")]
+    pub root: u32,
+    #[prost(uint32, tag = "2")]
+    pub scale_type: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Fx {
+    #[prost(uint32, tag = "1")]
+    pub delay_preset: u32,
+    #[prost(uint32, tag = "2")]
+    pub reverb_preset: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ChainEntry {
+    #[prost(uint32, tag = "1")]
+    pub start: u32,
+    #[prost(uint32, tag = "2")]
+    pub end: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Scene {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<ChainEntry>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Session {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "2")]
+    pub timing: Option<Timing>,
+    #[prost(message, optional, tag = "3")]
+    pub scale: Option<Scale>,
+    #[prost(message, optional, tag = "4")]
+    pub fx: Option<Fx>,
+    #[prost(message, repeated, tag = "5")]
+    pub scenes: Vec<Scene>,
+    #[prost(message, optional, tag = "6")]
+    pub scene_chain: Option<ChainEntry>,
+    #[prost(message, repeated, tag = "7")]
+    pub pattern_chains: Vec<ChainEntry>,
+    #[prost(message, repeated, tag = "8")]
+    pub tracks: Vec<DrumTrack>,
+    #[prost(uint32, tag = "9")]
+    pub master_volume: u32,
+}
+
+fn entry(start: u8, end: u8) -> ChainEntry {
+    ChainEntry { start: start as u32, end: end as u32 }
+}
+
+impl From<&crate::Session> for Session {
+    fn from(s: &crate::Session) -> Self {
+        let step = |st: &crate::Step| Step {
+            velocity: st.velocity as u32,
+            probability: st.probability as u32,
+            choice: st.choice as u32,
+            mask: st.mask as u32,
+            pitch: st.pitch as u32,
+            decay: st.decay as u32,
+            distortion: st.distortion as u32,
+            eq: st.eq as u32,
+        };
+        Session {
+            name: s.name.name.clone(),
+            timing: Some(Timing {
+                tempo: s.timing.tempo as u32,
+                swing: s.timing.swing as u32,
+                swing_sync_rate: s.timing.swing_sync_rate as u32,
+                spare1: s.timing.spare1,
+                spare2: s.timing.spare2,
+            }),
+            scale: Some(Scale { root: s.scale.root as u32, scale_type: s.scale.scale_type as u32 }),
+            fx: Some(Fx { delay_preset: s.fx.delay_preset as u32, reverb_preset: s.fx.reverb_preset as u32 }),
+            scenes: s.scenes.scenes.iter()
+                .map(|sc| Scene { entries: sc.entries.iter().map(|e| entry(e.start, e.end)).collect() })
+                .collect(),
+            scene_chain: Some(entry(s.scene_chain.start_scene, s.scene_chain.end_scene)),
+            pattern_chains: s.pattern_chains.entries.iter().map(|e| entry(e.start, e.end)).collect(),
+            tracks: s.drums.tracks.iter().map(|t| DrumTrack {
+                patterns: t.patterns.iter()
+                    .map(|p| Pattern { steps: p.steps.iter().map(step).collect() })
+                    .collect(),
+                delay_send: t.delay_send as u32,
+                reverb_send: t.reverb_send as u32,
+            }).collect(),
+            master_volume: s.globals.master_volume as u32,
+        }
+    }
+}
+
+pub fn encode(session: &crate::Session) -> Vec<u8> {
+    Session::from(session).encode_to_vec()
+}
@@ -0,0 +1,48 @@
+// Tap tempo: press Enter on every beat of a reference track, end with EOF (Ctrl-D)
+// or `q`, and get the BPM to pass to `set-tempo`. Only the `tap` command uses this.
+
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+const MIN_BPM: f64 = 40.0;
+const MAX_BPM: f64 = 240.0;
+
+// Tempo from tap times (elapsed since any fixed start), averaging every interval.
+// None until there are two taps to measure between.
+pub fn bpm(taps: &[Duration]) -> Option<u8> {
+    let (first, last) = (taps.first()?, taps.last()?);
+    if taps.len() < 2 || last <= first {
+        return None;
+    }
+    let interval = (*last - *first).as_secs_f64() / (taps.len() - 1) as f64;
+    Some((60.0 / interval).round().clamp(MIN_BPM, MAX_BPM) as u8)
+}
+
+// Read one tap per line until EOF or a line starting with `q`, stamping each with `now`.
+// The running tempo goes to `progress` after every tap.
+pub fn collect<R: BufRead, W: Write>(input: R, progress: &mut W, mut now: impl FnMut() -> Duration) -> io::Result<Vec<Duration>> {
+    let mut taps = Vec::new();
+    for line in input.lines() {
+        if line?.trim_start().starts_with('q') {
+            break;
+        }
+        taps.push(now());
+        match bpm(&taps) {
+            Some(b) => writeln!(progress, "tap {}: {} BPM", taps.len(), b)?,
+            None => writeln!(progress, "tap {}", taps.len())?,
+        }
+    }
+    Ok(taps)
+}
+
+pub fn run() -> io::Result<()> {
+    let start = Instant::now();
+    let mut err = io::stderr();
+    writeln!(err, "Press Enter on each beat; Ctrl-D or q then Enter to finish.")?;
+    let taps = collect(io::stdin().lock(), &mut err, || start.elapsed())?;
+    match bpm(&taps) {
+        Some(b) => println!("{}", b),
+        None => writeln!(err, "Need at least two taps to measure a tempo")?,
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// Runs the built binary to check what reaches stdout and stderr.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Deep.ncs with track 0's delay send pushed past its 0..127 range.
+fn session_with_bad_send(tag: &str) -> PathBuf {
+    let manifest = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut data = fs::read(manifest.join("../test_data/Deep.ncs")).expect("read Deep.ncs");
+    data[0x26CFC] = 0xC8;
+    let path = std::env::temp_dir().join(format!("ncs-cli-{}-{}.ncs", tag, std::process::id()));
+    fs::write(&path, data).expect("write session");
+    path
+}
+
+fn run(args: &[&str], path: &PathBuf) -> Output {
+    let out = run_unchecked(args, path);
+    assert!(out.status.success(), "ncs-tui {:?} failed: {}", args, String::from_utf8_lossy(&out.stderr));
+    out
+}
+
+// Like run, for invocations that are meant to fail.
+fn run_unchecked(args: &[&str], path: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ncs-tui"))
+        .args(args)
+        .arg(path)
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("run ncs-tui")
+}
+
+#[test]
+fn quiet_silences_warnings_but_keeps_stdout() {
+    let path = session_with_bad_send("quiet");
+    let normal = run(&[], &path);
+    let quiet = run(&["--quiet"], &path);
+    let verbose = run(&["--verbose"], &path);
+    fs::remove_file(&path).ok();
+
+    let stderr = String::from_utf8_lossy(&normal.stderr);
+    assert!(stderr.contains("[warn] ") && stderr.contains("delay send out of range"), "{}", stderr);
+    assert!(quiet.stderr.is_empty(), "{}", String::from_utf8_lossy(&quiet.stderr));
+    assert_eq!(quiet.stdout, normal.stdout);
+
+    let stderr = String::from_utf8_lossy(&verbose.stderr);
+    assert!(stderr.contains("[warn] ") && stderr.contains("[info] tempo = "), "{}", stderr);
+    assert_eq!(verbose.stdout, normal.stdout);
+}
+
+#[test]
+fn checksum_hashes_gzip_backups_as_stored_unless_decompressed() {
+    let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_data");
+    let digest = |out: Output| String::from_utf8_lossy(&out.stdout).split_whitespace().nth(1).expect("digest").to_string();
+    let plain = digest(run(&["checksum"], &data.join("Deep.ncs")));
+    let raw = digest(run(&["checksum"], &data.join("Deep.ncs.gz")));
+    let decompressed = digest(run(&["checksum", "--decompressed"], &data.join("Deep.ncs.gz")));
+    assert_ne!(raw, plain);
+    assert_eq!(decompressed, plain);
+}
+
+#[test]
+fn find_skips_unreadable_files_and_keeps_searching() {
+    let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_data");
+    let missing = data.join("missing.ncs");
+    let out = Command::new(env!("CARGO_BIN_EXE_ncs-tui"))
+        .args(["find", "--rhythm", "x...x...x...x..."])
+        .arg(&missing)
+        .arg(data.join("Deep.ncs"))
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("run ncs-tui");
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("missing.ncs") && stderr.contains("skipped"), "{}", stderr);
+    assert!(stdout.trim_end().ends_with("matches (1 files skipped)"), "{}", stdout);
+}
+
+#[test]
+fn profile_offsets_at_usize_max_fail_cleanly() {
+    let deep = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_data/Deep.ncs");
+    let profile = std::env::temp_dir().join(format!("ncs-cli-max-{}.json", std::process::id()));
+    let max = usize::MAX;
+    let sections = [
+        format!(r#"{{"timing": {{"tempo": {max}}}}}"#),
+        format!(r#"{{"timing": {{"spare1": {max}}}}}"#),
+        format!(r#"{{"name": {{"base": {max}}}}}"#),
+        format!(r#"{{"scale": {{"root": {max}}}}}"#),
+        format!(r#"{{"fx": {{"delay_preset": {max}}}}}"#),
+        format!(r#"{{"globals": {{"master_volume": {max}}}}}"#),
+        format!(r#"{{"drums": {{"velocity": {max}, "track_stride": {max}}}}}"#),
+        format!(r#"{{"layout": {{"tracks": {max}}}, "drums": {{"track_stride": 0}}}}"#),
+    ];
+    for json in &sections {
+        fs::write(&profile, json).expect("write profile");
+        // salvage only reads the drum block, so it may still succeed; nothing may panic
+        for (command, fails) in [(&["dump"][..], true), (&["set-tempo", "--tempo", "120", "-o", "/dev/null"], true), (&["salvage"], false)] {
+            let mut args = command.to_vec();
+            args.extend(["--offsets", profile.to_str().unwrap()]);
+            let out = run_unchecked(&args, &deep);
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(!stderr.contains("panicked"), "{:?} with {}: {}", command, json, stderr);
+            let expected: &[i32] = if fails { &[1] } else { &[0, 1] };
+            assert!(out.status.code().is_some_and(|c| expected.contains(&c)), "{:?} with {}: {:?} {}", command, json, out.status, stderr);
+        }
+    }
+    fs::remove_file(&profile).ok();
+}
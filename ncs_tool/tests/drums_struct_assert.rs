@@ -1,6 +1,7 @@
 use std::fs;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct HitSpec {
     // Expect velocity constraints for a step
     min: u8,
@@ -64,6 +65,7 @@ fn deep_track2_p2_structure() {
 }
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn funk_track2_p2_structure() {
     let steps = load_funk_track2_p2();
     // First bar: strong, weak, weak, weak, strong, weak, weak, weak
@@ -75,3 +77,16 @@ fn funk_track2_p2_structure() {
     for i in 16..32 { assert_eq!(steps[i].0, 0, "Funk rest expected at step {}", i); }
 }
 
+#[test]
+fn library_parse_matches_raw_offsets() {
+    let data = load_bytes("../test_data/Deep.ncs");
+    let drums = ncs_tui::DrumData::from_bytes(&data, &ncs_tui::Offsets::default()).expect("parse drums");
+    for s in 0..32 {
+        let i = step_idx(2, 2, s);
+        let st = &drums.tracks[2].patterns[2].steps[s];
+        assert_eq!((st.velocity, st.probability, st.choice, st.mask), (data[VELOCITY_OFF + i], data[PROB_OFF + i], data[CHOICE_OFF + i], data[MASK_OFF + i]));
+        assert_eq!((st.pitch, st.decay, st.distortion, st.eq), (data[PITCH_OFF + i], data[DECAY_OFF + i], data[DIST_OFF + i], data[EQ_OFF + i]));
+    }
+    let timing = ncs_tui::Timing::from_bytes(&data, &ncs_tui::TimingOffsets::default()).expect("parse timing");
+    assert_eq!(timing.tempo, 174);
+}